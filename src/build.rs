@@ -0,0 +1,795 @@
+pub mod configure;
+pub mod context;
+pub mod copy;
+pub mod schedule;
+
+use crate::config::image::{self, typ};
+use crate::config::{scriptlet, secret};
+use crate::layer::{self, archive, compression, diff};
+use crate::oci::{config, manifest, media_type};
+use crate::registry::registry;
+use crate::result;
+use crate::rootfs;
+use crate::storage;
+use crate::storage::digest;
+use crate::storage::{cache, cache_mount, index, reference};
+use chrono::{TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path;
+use std::sync;
+
+#[derive(Debug)]
+struct CopyFromScratchError;
+
+impl fmt::Display for CopyFromScratchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot copy from scratch, which has no files")
+    }
+}
+
+impl error::Error for CopyFromScratchError {}
+
+#[derive(Debug)]
+struct MissingCopySourceError {
+    image: String,
+    source: String,
+}
+
+impl fmt::Display for MissingCopySourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} has no {}", self.image, self.source)
+    }
+}
+
+impl error::Error for MissingCopySourceError {}
+
+#[derive(Debug)]
+struct UndeclaredSecretError {
+    id: String,
+}
+
+impl fmt::Display for UndeclaredSecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "secret {} is not declared by the image or --secret",
+            self.id
+        )
+    }
+}
+
+impl error::Error for UndeclaredSecretError {}
+
+#[derive(Debug)]
+struct NonUnicodeSecretError {
+    id: String,
+}
+
+impl fmt::Display for NonUnicodeSecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "secret {} is not valid UTF-8, as needed for an environment variable",
+            self.id
+        )
+    }
+}
+
+impl error::Error for NonUnicodeSecretError {}
+
+/// Architecture of this host as named in image configurations.
+fn architecture() -> &'static str {
+    match env::consts::ARCH {
+        "x86_64" => "amd64",
+        "x86" => "386",
+        "aarch64" => "arm64",
+        "powerpc64" => "ppc64le",
+        architecture => architecture,
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// codec of images which do not configure their own
+    pub compression: compression::Compression,
+    /// clamps modification times and sets the creation time, for reproducible builds
+    pub source_date_epoch: Option<u64>,
+    /// builds every step even if a layer is cached for it
+    pub no_cache: bool,
+    /// tells which inputs of missed steps changed since they were last cached
+    pub explain_cache: bool,
+    /// imports cache entries from here before building
+    pub cache_from: Option<cache::remote::Location>,
+    /// exports the cache entries of the built images here after building
+    pub cache_to: Option<cache::remote::Location>,
+    /// images built at the same time, one per CPU if unset
+    pub jobs: Option<usize>,
+    /// keeps building images not based on a failed one
+    pub keep_going: bool,
+    /// patterns of the images to build along with their bases, every image if empty
+    pub targets: Vec<String>,
+    /// lists the images to build instead of building them
+    pub list_targets: bool,
+    /// secrets of `run` scriptlets, overriding those declared by images
+    pub secrets: BTreeMap<String, secret::Source>,
+}
+
+/// Precedes the scriptlet in `created_by` of the history, as `/bin/sh -c` does for Dockerfiles.
+const CREATED_BY_PREFIX: &str = "amethyst";
+
+/// `digest` of `Base` when building on no image.
+const SCRATCH: &str = "scratch";
+
+struct Base {
+    /// manifest digest, or `SCRATCH`
+    digest: String,
+    config: config::ImageConfig,
+    layers: Vec<manifest::Layer>,
+}
+
+/// Held while pulling, so that concurrent builds pull a shared base image once.
+static PULL_LOCK: sync::Mutex<()> = sync::Mutex::new(());
+
+/// Returns the manifest digest of `name:tag`, pulling it if it is not stored yet.
+fn pull(
+    label: &str,
+    name: &str,
+    tag: &str,
+    registry: &dyn registry::Registry,
+) -> result::Result<String> {
+    let _lock = PULL_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let index = index::Index::load(index::path())?;
+    let reference = format!("{}:{}", name, tag);
+    if let Ok(resolved) = reference::resolve(&index, &reference) {
+        return Ok(resolved.digest().to_string());
+    }
+    println!("[{}] pulling {}", label, reference);
+    registry.download_base_image(name, tag)
+}
+
+/// Returns the manifest digest of `image`, pulling it if needed, or `None` for scratch.
+fn image_digest(
+    label: &str,
+    image: &typ::ImageType,
+    registry: &dyn registry::Registry,
+) -> result::Result<Option<String>> {
+    match image {
+        typ::ImageType::Scratch => Ok(None),
+        typ::ImageType::BaseImage { name, tag } => pull(label, name, tag, registry).map(Some),
+        // built earlier in the same run, see `config::graph::order`
+        typ::ImageType::Local { name, tag } => {
+            let reference = match tag {
+                Some(tag) => format!("{}:{}", name, tag),
+                None => name.to_string(),
+            };
+            let index = index::Index::load(index::path())?;
+            Ok(Some(
+                reference::resolve(&index, &reference)?.digest().to_string(),
+            ))
+        }
+    }
+}
+
+fn base(
+    label: &str,
+    base_image: &typ::ImageType,
+    registry: &dyn registry::Registry,
+) -> result::Result<Base> {
+    let digest = match image_digest(label, base_image, registry)? {
+        Some(digest) => digest,
+        None => {
+            return Ok(Base {
+                digest: SCRATCH.to_string(),
+                config: config::ImageConfig {
+                    architecture: architecture().to_string(),
+                    os: env::consts::OS.to_string(),
+                    ..Default::default()
+                },
+                layers: vec![],
+            })
+        }
+    };
+    let manifest = storage::read_manifest(&digest)?;
+    let mut config: config::ImageConfig =
+        serde_json::from_slice(&fs::read(storage::blob_path(&manifest.config.digest))?)?;
+    if config.architecture.is_empty() {
+        config.architecture = architecture().to_string();
+    }
+    if config.os.is_empty() {
+        config.os = env::consts::OS.to_string();
+    }
+    Ok(Base {
+        digest,
+        config,
+        layers: manifest.layers,
+    })
+}
+
+/// Cache inputs of the files `add` copies from `sources`, with the mode they
+/// are given and their modification times if it keeps them.
+fn sources(
+    context: &context::Context,
+    sources: &scriptlet::Sources,
+    mode: Option<scriptlet::Mode>,
+    preserve_timestamps: bool,
+) -> result::Result<Vec<cache::Source>> {
+    let mut inputs = vec![];
+    for path in context.expand(sources)? {
+        for (index, path) in context.walk(&path)?.into_iter().enumerate() {
+            // symbolic links are only followed when matched, as `add` does
+            let metadata = if index == 0 {
+                fs::metadata(&path)?
+            } else {
+                fs::symlink_metadata(&path)?
+            };
+            let digest = if metadata.file_type().is_symlink() {
+                digest::sha256(fs::read_link(&path)?.as_os_str().as_bytes())
+            } else if metadata.is_file() {
+                digest::sha256_reader(&mut fs::File::open(&path)?)?
+            } else {
+                String::new()
+            };
+            // only the permissions kept in layers, so that the umask of the
+            // checkout does not matter once overridden
+            let file_type = metadata.mode() & libc::S_IFMT;
+            let mode = match mode {
+                Some(scriptlet::Mode(mode)) if !metadata.file_type().is_symlink() => {
+                    file_type | mode
+                }
+                _ => file_type | metadata.mode() & layer::unpack::PERMISSION_MASK,
+            };
+            inputs.push(cache::Source {
+                path: path.to_string_lossy().to_string(),
+                digest,
+                mode,
+                mtime: preserve_timestamps.then(|| metadata.mtime()),
+            });
+        }
+    }
+    Ok(inputs)
+}
+
+/// Parts of `config` affecting the commands of `run` scriptlets.
+fn run_config(config: &config::ImageConfig) -> config::ContainerConfig {
+    let config = config.config.clone().unwrap_or_default();
+    config::ContainerConfig {
+        user: config.user,
+        env: config.env,
+        working_dir: config.working_dir,
+        shell: config.shell,
+        ..Default::default()
+    }
+}
+
+/// Cache inputs of running `scriptlet` on the layers identified by `parent`
+/// of an image configured by `config`.
+fn inputs(
+    label: &str,
+    parent: &str,
+    config: &config::ImageConfig,
+    scriptlet: &scriptlet::Scriptlet,
+    archive: &archive::Options,
+    context: &context::Context,
+    registry: &dyn registry::Registry,
+) -> result::Result<cache::Inputs> {
+    let sources = match scriptlet {
+        scriptlet::Scriptlet::Add {
+            source,
+            mode,
+            preserve_timestamps,
+            ..
+        } => sources(context, source, *mode, *preserve_timestamps)?,
+        // the whole image stands for the copied path, as it is only read once built
+        scriptlet::Scriptlet::CopyFrom { from, .. } => vec![cache::Source {
+            path: from.to_string(),
+            digest: image_digest(label, from, registry)?.ok_or(CopyFromScratchError)?,
+            mode: 0,
+            mtime: None,
+        }],
+        // whatever the command reads is not tracked, only how it is run
+        scriptlet::Scriptlet::Run { .. } => vec![],
+        // applied to the configuration without a layer, see `configure::apply`
+        _ => vec![],
+    };
+    Ok(cache::Inputs {
+        parent: parent.to_string(),
+        scriptlet: scriptlet.clone(),
+        sources,
+        archive: archive.clone(),
+        config: match scriptlet {
+            scriptlet::Scriptlet::Run { .. } => Some(run_config(config)),
+            _ => None,
+        },
+    })
+}
+
+/// Chain ID of `diff_id` stacked on the layers identified by `parent`, as
+/// defined by the OCI image specification.
+fn chain_id(parent: &str, diff_id: &str) -> String {
+    if parent.is_empty() {
+        diff_id.to_string()
+    } else {
+        digest::sha256(format!("{} {}", parent, diff_id).as_bytes())
+    }
+}
+
+/// Prints how `inputs` differ from the last cached run of the same step.
+fn explain(label: &str, cache: &cache::Cache, step: usize, base: &str, inputs: &cache::Inputs) {
+    let changes = match cache.latest(label, step) {
+        Some(previous) => previous.explain(base, inputs),
+        None => vec![format!("step {} of {} was never cached", step + 1, label)],
+    };
+    for change in changes {
+        println!("[{}]   {}", label, change);
+    }
+}
+
+/// Copies what `sources` match, relative to the configuration directory, to
+/// `destination` in the root filesystem, except what `context` excludes, with
+/// `mode` and `owner` if given, and returns the owner of every path written.
+///
+/// As with Docker, the content of a directory is merged into `destination`,
+/// and files are copied into it if it is a directory, ends with `/` or if
+/// there are several sources.
+fn add(
+    rootfs: &rootfs::Rootfs,
+    context: &context::Context,
+    sources: &scriptlet::Sources,
+    destination: &str,
+    owner: Option<&str>,
+    mode: Option<scriptlet::Mode>,
+    preserve_timestamps: bool,
+) -> result::Result<Written> {
+    let paths = context.expand(sources)?;
+    let destination_path = rootfs.resolve(destination)?;
+    let into_directory = paths.len() > 1 || destination.ends_with('/') || destination_path.is_dir();
+    let excluded = |path: &path::Path| context.is_excluded(path);
+    // paths written with the source they were copied from
+    let mut written = vec![];
+    for path in paths {
+        if path.is_dir() {
+            if !destination_path.is_dir() {
+                fs::create_dir_all(&destination_path)?;
+                written.push((destination_path.clone(), path.clone()));
+            }
+            for entry in context.entries(&path)? {
+                let target = destination_path.join(entry.file_name().expect("entries have a name"));
+                for copied in copy::copy_except(&entry, &target, &excluded)? {
+                    let source = match copied.strip_prefix(&target)? {
+                        relative if relative.as_os_str().is_empty() => entry.clone(),
+                        relative => entry.join(relative),
+                    };
+                    written.push((copied, source));
+                }
+            }
+            continue;
+        }
+        let mut target = destination_path.clone();
+        if into_directory {
+            if let Some(file_name) = path.file_name() {
+                target = target.join(file_name);
+            }
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // rather than writing through a symbolic link which may lead outside
+        copy::remove_non_directory(&target)?;
+        fs::copy(&path, &target)?;
+        written.push((target, path));
+    }
+
+    if preserve_timestamps {
+        for (path, source) in &written {
+            // symbolic links are copied as they are except the matched ones
+            let metadata = if fs::symlink_metadata(path)?.file_type().is_symlink() {
+                fs::symlink_metadata(source)?
+            } else {
+                fs::metadata(source)?
+            };
+            let mtime = filetime::FileTime::from_last_modification_time(&metadata);
+            filetime::set_symlink_file_times(path, mtime, mtime)?;
+        }
+    }
+    let written = written
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect::<Vec<_>>();
+    attributes(rootfs, &written, owner, mode)
+}
+
+/// Owner of every path written, relative to the root filesystem.
+type Written = Vec<(path::PathBuf, (u32, u32))>;
+
+/// Copies `source` from the image `from` to `destination` in the root
+/// filesystem, with `mode` and `owner` if given, and returns the owner of every
+/// path written.
+///
+/// A directory is merged into `destination`, and a file copied into it if it
+/// is a directory.
+#[allow(clippy::too_many_arguments)]
+fn copy_from(
+    label: &str,
+    rootfs: &rootfs::Rootfs,
+    from: &typ::ImageType,
+    source: &str,
+    destination: &str,
+    owner: Option<&str>,
+    mode: Option<scriptlet::Mode>,
+    registry: &dyn registry::Registry,
+) -> result::Result<Written> {
+    let digest = image_digest(label, from, registry)?.ok_or(CopyFromScratchError)?;
+    let image = rootfs::Rootfs::new()?;
+    rootfs::materialize(image.path(), &storage::read_manifest(&digest)?.layers)?;
+    let source_path = image.resolve(source)?;
+    let metadata = fs::symlink_metadata(&source_path).map_err(|_| MissingCopySourceError {
+        image: from.to_string(),
+        source: source.to_string(),
+    })?;
+
+    let mut destination = rootfs.resolve(destination)?;
+    if !metadata.is_dir() && destination.is_dir() {
+        if let Some(file_name) = source_path.file_name() {
+            destination = destination.join(file_name);
+        }
+    }
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let written = copy::copy(&source_path, &destination)?;
+    attributes(rootfs, &written, owner, mode)
+}
+
+/// Sets `mode` on the `written` paths of the root filesystem except symbolic
+/// links, and returns their owner, root unless `owner` is given.
+fn attributes(
+    rootfs: &rootfs::Rootfs,
+    written: &[path::PathBuf],
+    owner: Option<&str>,
+    mode: Option<scriptlet::Mode>,
+) -> result::Result<Written> {
+    if let Some(scriptlet::Mode(mode)) = mode {
+        for path in written {
+            if !fs::symlink_metadata(path)?.file_type().is_symlink() {
+                fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+            }
+        }
+    }
+    let owner = match owner {
+        Some(owner) => rootfs::user::owner(rootfs.path(), owner)?,
+        None => (0, 0),
+    };
+    written
+        .iter()
+        .map(|path| Ok((path.strip_prefix(rootfs.path())?.to_path_buf(), owner)))
+        .collect()
+}
+
+/// Hostname of the commands of `run` scriptlets.
+const HOSTNAME: &str = "amethyst";
+
+/// Runs `command` in the root filesystem whose files have `owners`, as
+/// configured by `config`, with `mounts` of the `secrets` and `caches`
+/// available to it, logging its output, and returns the owners once it exited.
+#[allow(clippy::too_many_arguments)]
+fn run(
+    label: &str,
+    rootfs: &rootfs::Rootfs,
+    owners: &layer::Owners,
+    config: &config::ImageConfig,
+    command: &scriptlet::Command,
+    network: Option<scriptlet::Network>,
+    mounts: &[scriptlet::SecretMount],
+    secrets: &BTreeMap<String, secret::Source>,
+    caches: &[scriptlet::CacheMount],
+) -> result::Result<layer::Owners> {
+    let config = run_config(config);
+    let (uid, gid) = rootfs::user::owner(rootfs.path(), config.user.as_deref().unwrap_or("0"))?;
+    let mut env = config.env.clone().unwrap_or_default();
+    // outside the root filesystem and removed when dropped, after the command
+    let mut files = vec![];
+    let mut secret_files = vec![];
+    for mount in mounts {
+        let value = secrets
+            .get(&mount.id)
+            .ok_or_else(|| UndeclaredSecretError {
+                id: mount.id.clone(),
+            })?
+            .read(&mount.id)?;
+        if let Some(variable) = &mount.env {
+            let value = String::from_utf8(value.clone()).map_err(|_| NonUnicodeSecretError {
+                id: mount.id.clone(),
+            })?;
+            env.push(format!("{}={}", variable, value));
+        }
+        if let Some(target) = mount.file() {
+            fs::create_dir_all(storage::temporary_storage())?;
+            let mut file = tempfile::Builder::new()
+                .prefix("secret-")
+                .tempfile_in(storage::temporary_storage())?;
+            file.write_all(&value)?;
+            secret_files.push(rootfs::sandbox::Secret {
+                source: file.path().to_path_buf(),
+                target,
+            });
+            files.push(file);
+        }
+    }
+    // released once the command exited
+    let mut leases = vec![];
+    let mut cache_directories = vec![];
+    for cache in caches {
+        let lease = cache_mount::acquire(
+            &cache_mount::directory(),
+            cache.id(),
+            cache.sharing.unwrap_or(scriptlet::Sharing::Shared),
+        )?;
+        cache_directories.push(rootfs::sandbox::Cache {
+            source: fs::canonicalize(lease.path())?,
+            target: cache.target.clone(),
+            owners: lease.owners()?,
+        });
+        leases.push(lease);
+    }
+    let spec = rootfs::sandbox::Spec {
+        root: fs::canonicalize(rootfs.path())?,
+        arguments: configure::arguments(&config, command),
+        env,
+        working_dir: config.working_dir.unwrap_or_else(|| "/".to_string()),
+        uid,
+        gid,
+        network: network != Some(scriptlet::Network::None),
+        hostname: HOSTNAME.to_string(),
+        secrets: secret_files,
+        caches: cache_directories,
+        owners: owners.clone(),
+    };
+    let report = rootfs::sandbox::run(&spec, |line| println!("[{}] | {}", label, line))?;
+    for (lease, owners) in leases.iter().zip(&report.caches) {
+        lease.save_owners(owners)?;
+    }
+    Ok(report.owners)
+}
+
+/// Docker image manifest, or an OCI one if a layer has no Docker media type such as zstd ones.
+fn manifest(
+    config_digest: String,
+    config_size: usize,
+    mut layers: Vec<manifest::Layer>,
+) -> manifest::Manifest {
+    let (media_type, config_media_type) = if layers
+        .iter()
+        .all(|layer| media_type::is_docker(&layer.media_type))
+    {
+        (media_type::DOCKER_MANIFEST, media_type::DOCKER_CONFIG)
+    } else {
+        for layer in &mut layers {
+            layer.media_type = media_type::to_oci_layer(&layer.media_type).to_string();
+        }
+        (media_type::OCI_MANIFEST, media_type::OCI_CONFIG)
+    };
+    manifest::Manifest {
+        schema_version: 2,
+        media_type: media_type.to_string(),
+        config: manifest::Config {
+            media_type: config_media_type.to_string(),
+            size: config_size,
+            digest: config_digest,
+        },
+        layers,
+    }
+}
+
+/// Outcome of building an image.
+#[derive(Debug)]
+pub struct Built {
+    /// manifest digest
+    pub digest: String,
+    /// cache keys of every step changing the filesystem, whether the cache was hit or not
+    pub cache_keys: Vec<String>,
+}
+
+/// Builds `image` in the current directory and records it as `name:tag`.
+pub fn build(
+    image: &image::Image<scriptlet::Scriptlet>,
+    options: &Options,
+    registry: &dyn registry::Registry,
+) -> result::Result<Built> {
+    let label = format!("{}:{}", image.name, image.tag);
+    let mut base = base(&label, &image.base_image, registry)?;
+    let mut secrets = image.secrets.clone();
+    secrets.extend(options.secrets.clone());
+    let context = context::Context::load()?;
+    let archive_options = archive::Options {
+        compression: image.compression.unwrap_or(options.compression),
+        source_date_epoch: options.source_date_epoch,
+    };
+
+    let created = match options.source_date_epoch {
+        Some(source_date_epoch) => Utc.timestamp_opt(source_date_epoch as i64, 0).single(),
+        None => Some(Utc::now()),
+    };
+    let cache_path = cache::path();
+    let cache = cache::Cache::load(&cache_path)?;
+    let mut cache_keys = vec![];
+    let mut parent = base
+        .config
+        .rootfs
+        .diff_ids
+        .iter()
+        .fold(String::new(), |parent, diff_id| chain_id(&parent, diff_id));
+    // materialized on the first cache miss, then kept in sync until a hit
+    let mut workspace: Option<(rootfs::Rootfs, diff::Snapshot, layer::Owners)> = None;
+    for (step, scriptlet) in image.scripts.iter().enumerate() {
+        println!("[{}] {}", label, scriptlet);
+        if configure::apply(&mut base.config, scriptlet, &image.scripts[..step]) {
+            base.config.history.push(config::History {
+                created,
+                created_by: Some(format!("{} {}", CREATED_BY_PREFIX, scriptlet)),
+                empty_layer: true,
+                ..Default::default()
+            });
+            continue;
+        }
+        let inputs = inputs(
+            &label,
+            &parent,
+            &base.config,
+            scriptlet,
+            &archive_options,
+            &context,
+            registry,
+        )?;
+        let key = inputs.key()?;
+        cache_keys.push(key.clone());
+        let cached = if options.no_cache {
+            Err(cache::Miss::Disabled)
+        } else {
+            cache.lookup(&key).cloned()
+        };
+        let (layer, diff_id) = match cached {
+            Ok(entry) => {
+                println!("[{}] cache hit {}", label, entry.layer.digest);
+                workspace = None;
+                (entry.layer, entry.diff_id)
+            }
+            Err(miss) => {
+                println!("[{}] cache miss: {}", label, miss);
+                if options.explain_cache && miss == cache::Miss::NotCached {
+                    explain(&label, &cache, step, &base.digest, &inputs);
+                }
+                let (rootfs, snapshot, mut owners) = match workspace.take() {
+                    Some(workspace) => workspace,
+                    None => {
+                        let rootfs = rootfs::Rootfs::new()?;
+                        let owners = rootfs::materialize(rootfs.path(), &base.layers)?;
+                        let snapshot = diff::Snapshot::take(rootfs.path(), &owners)?;
+                        (rootfs, snapshot, owners)
+                    }
+                };
+                let written = match scriptlet {
+                    scriptlet::Scriptlet::Add {
+                        source,
+                        destination,
+                        owner,
+                        mode,
+                        preserve_timestamps,
+                    } => add(
+                        &rootfs,
+                        &context,
+                        source,
+                        destination,
+                        owner.as_deref(),
+                        *mode,
+                        *preserve_timestamps,
+                    )?,
+                    scriptlet::Scriptlet::CopyFrom {
+                        from,
+                        source,
+                        destination,
+                        owner,
+                        mode,
+                    } => copy_from(
+                        &label,
+                        &rootfs,
+                        from,
+                        source,
+                        destination,
+                        owner.as_deref(),
+                        *mode,
+                        registry,
+                    )?,
+                    scriptlet::Scriptlet::Run {
+                        command,
+                        network,
+                        secrets: mounts,
+                        caches,
+                    } => {
+                        owners = run(
+                            &label,
+                            &rootfs,
+                            &owners,
+                            &base.config,
+                            command,
+                            *network,
+                            mounts,
+                            &secrets,
+                            caches,
+                        )?;
+                        vec![]
+                    }
+                    _ => unreachable!("configuration scriptlets are applied without a layer"),
+                };
+                for (name, owner) in written {
+                    if owner == (0, 0) {
+                        owners.remove(&name);
+                    } else {
+                        owners.insert(name, owner);
+                    }
+                }
+                // gone along with a replaced directory
+                owners.retain(|name, _| fs::symlink_metadata(rootfs.path().join(name)).is_ok());
+                let upper = diff::Snapshot::take(rootfs.path(), &owners)?;
+                let changes = diff::Diff::between(&snapshot, &upper);
+                if changes.is_empty() {
+                    println!("[{}] no changes", label);
+                }
+                let stored = layer::store(&archive_options, |archive| {
+                    for (name, (uid, gid)) in &owners {
+                        archive.set_owner(name.clone(), *uid, *gid);
+                    }
+                    changes.write(rootfs.path(), archive)
+                })?;
+                workspace = Some((rootfs, upper, owners));
+                let layer = manifest::Layer {
+                    media_type: stored.media_type,
+                    size: stored.size as usize,
+                    digest: stored.digest,
+                };
+                cache::record(
+                    &cache_path,
+                    key,
+                    cache::CacheEntry {
+                        layer: layer.clone(),
+                        diff_id: stored.diff_id.clone(),
+                        created_at: Utc::now(),
+                        image: label.clone(),
+                        step,
+                        base: base.digest.clone(),
+                        inputs,
+                    },
+                )?;
+                (layer, stored.diff_id)
+            }
+        };
+        parent = chain_id(&parent, &diff_id);
+        base.config.rootfs.diff_ids.push(diff_id);
+        base.config.history.push(config::History {
+            created,
+            created_by: Some(format!("{} {}", CREATED_BY_PREFIX, scriptlet)),
+            ..Default::default()
+        });
+        base.layers.push(layer);
+    }
+
+    base.config.created = created;
+    let config = serde_json::to_vec(&base.config)?;
+    let manifest = manifest(storage::write_blob(&config)?, config.len(), base.layers);
+    let manifest_digest = storage::write_blob(&serde_json::to_vec(&manifest)?)?;
+
+    storage::tag_image(&image.name, &image.tag, &manifest_digest, true)?;
+    println!("[{}] built {}", label, manifest_digest);
+    Ok(Built {
+        digest: manifest_digest,
+        cache_keys,
+    })
+}
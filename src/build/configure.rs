@@ -0,0 +1,200 @@
+use crate::config::scriptlet::{Command, Scriptlet};
+use crate::oci::config;
+use std::collections::BTreeMap;
+use std::path;
+
+/// Shell running the string form of commands unless set by `Scriptlet::Shell`.
+pub const DEFAULT_SHELL: &[&str] = &["/bin/sh", "-c"];
+
+/// Arguments running `command` in containers configured by `config`.
+pub fn arguments(config: &config::ContainerConfig, command: &Command) -> Vec<String> {
+    match command {
+        Command::Exec(arguments) => arguments.clone(),
+        Command::Shell(command) => {
+            let mut arguments = match &config.shell {
+                Some(shell) => shell.clone(),
+                None => DEFAULT_SHELL.iter().map(ToString::to_string).collect(),
+            };
+            arguments.push(command.clone());
+            arguments
+        }
+    }
+}
+
+/// Sets `key` to `value` in `map`, created if needed.
+fn insert<V>(map: &mut Option<BTreeMap<String, V>>, key: String, value: V) {
+    map.get_or_insert_with(BTreeMap::new).insert(key, value);
+}
+
+/// Applies `scriptlet` to `image` if it only changes the configuration, and
+/// returns whether it did. `earlier` are the scriptlets before it in the same
+/// image.
+pub fn apply(
+    image: &mut config::ImageConfig,
+    scriptlet: &Scriptlet,
+    earlier: &[Scriptlet],
+) -> bool {
+    if scriptlet.changes_filesystem() {
+        return false;
+    }
+    let config = image.config.get_or_insert_with(Default::default);
+    match scriptlet {
+        Scriptlet::Env { variables } => {
+            let env = config.env.get_or_insert_with(Vec::new);
+            for (key, value) in variables {
+                let variable = format!("{}={}", key, value);
+                let prefix = format!("{}=", key);
+                match env
+                    .iter_mut()
+                    .find(|existing| existing.starts_with(&prefix))
+                {
+                    Some(existing) => *existing = variable,
+                    None => env.push(variable),
+                }
+            }
+        }
+        Scriptlet::Workdir { path } => {
+            let previous = config.working_dir.as_deref().unwrap_or("/");
+            config.working_dir = Some(
+                path::Path::new(previous)
+                    .join(path)
+                    .to_string_lossy()
+                    .to_string(),
+            );
+        }
+        Scriptlet::User { user } => config.user = Some(user.clone()),
+        Scriptlet::Entrypoint { command } => {
+            config.entrypoint = Some(arguments(config, command));
+            // as with Dockerfiles, the command of the base image no longer applies
+            if !earlier
+                .iter()
+                .any(|scriptlet| matches!(scriptlet, Scriptlet::Cmd { .. }))
+            {
+                config.cmd = None;
+            }
+        }
+        Scriptlet::Cmd { command } => config.cmd = Some(arguments(config, command)),
+        Scriptlet::Label { labels } => {
+            for (key, value) in labels {
+                insert(&mut config.labels, key.clone(), value.clone());
+            }
+        }
+        Scriptlet::Expose { ports } => {
+            for port in ports {
+                insert(
+                    &mut config.exposed_ports,
+                    port.to_string(),
+                    serde_json::json!({}),
+                );
+            }
+        }
+        Scriptlet::Volume { paths } => {
+            for path in paths {
+                insert(&mut config.volumes, path.clone(), serde_json::json!({}));
+            }
+        }
+        Scriptlet::StopSignal { signal } => config.stop_signal = Some(signal.clone()),
+        Scriptlet::Healthcheck {
+            command,
+            interval,
+            timeout,
+            start_period,
+            retries,
+        } => {
+            let test = match command {
+                None => vec!["NONE".to_string()],
+                Some(Command::Exec(arguments)) => {
+                    let mut test = vec!["CMD".to_string()];
+                    test.extend(arguments.iter().cloned());
+                    test
+                }
+                Some(Command::Shell(command)) => vec!["CMD-SHELL".to_string(), command.clone()],
+            };
+            config.healthcheck = Some(config::Healthcheck {
+                test: Some(test),
+                interval: interval.map(|duration| duration.0),
+                timeout: timeout.map(|duration| duration.0),
+                start_period: start_period.map(|duration| duration.0),
+                retries: *retries,
+                ..Default::default()
+            });
+        }
+        Scriptlet::Shell { command } => config.shell = Some(command.clone()),
+        Scriptlet::Add { .. } | Scriptlet::CopyFrom { .. } | Scriptlet::Run { .. } => {
+            unreachable!()
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::apply;
+    use crate::config::scriptlet::Scriptlet;
+    use crate::oci::config;
+
+    fn configure(base: config::ContainerConfig, scripts: &str) -> config::ContainerConfig {
+        let scripts = serde_yaml::from_str::<Vec<Scriptlet>>(scripts).unwrap();
+        let mut image = config::ImageConfig {
+            config: Some(base),
+            ..Default::default()
+        };
+        for (step, scriptlet) in scripts.iter().enumerate() {
+            assert!(apply(&mut image, scriptlet, &scripts[..step]));
+        }
+        image.config.unwrap()
+    }
+
+    #[test]
+    fn override_base_configuration() {
+        let base = config::ContainerConfig {
+            env: Some(vec!["PATH=/usr/bin".to_string(), "LANG=C".to_string()]),
+            working_dir: Some("/srv".to_string()),
+            cmd: Some(vec!["bash".to_string()]),
+            ..Default::default()
+        };
+
+        let config = configure(
+            base,
+            r#"
+            - { type: env, variables: { PATH: /opt/bin, HOME: /app } }
+            - { type: workdir, path: app }
+            - { type: shell, command: [/bin/bash, -c] }
+            - { type: entrypoint, command: exec app }
+            - { type: expose, ports: [8080] }
+            "#,
+        );
+
+        assert_eq!(
+            config.env.unwrap(),
+            vec!["PATH=/opt/bin", "LANG=C", "HOME=/app"]
+        );
+        assert_eq!(config.working_dir.unwrap(), "/srv/app");
+        assert_eq!(
+            config.entrypoint.unwrap(),
+            vec!["/bin/bash", "-c", "exec app"]
+        );
+        assert_eq!(config.cmd, None);
+        assert!(config.exposed_ports.unwrap().contains_key("8080/tcp"));
+    }
+
+    #[test]
+    fn keep_command_set_before_entrypoint() {
+        let config = configure(
+            Default::default(),
+            r#"
+            - { type: cmd, command: [--help] }
+            - { type: entrypoint, command: [app] }
+            - { type: healthcheck, command: curl -f localhost, interval: 30s }
+            "#,
+        );
+
+        assert_eq!(config.cmd.unwrap(), vec!["--help"]);
+        let healthcheck = config.healthcheck.unwrap();
+        assert_eq!(
+            healthcheck.test.unwrap(),
+            vec!["CMD-SHELL", "curl -f localhost"]
+        );
+        assert_eq!(healthcheck.interval, Some(30_000_000_000));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::config::ignore;
+use crate::config::scriptlet::Sources;
+use crate::glob;
+use crate::result;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path;
+
+#[derive(Debug)]
+struct NoSourceError {
+    pattern: String,
+}
+
+impl fmt::Display for NoSourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} matches no file which is not ignored", self.pattern)
+    }
+}
+
+impl error::Error for NoSourceError {}
+
+/// Files `add` scriptlets copy, the current directory being the configuration
+/// directory, except those excluded by its ignore file.
+pub struct Context {
+    ignore: ignore::Ignore,
+}
+
+impl Context {
+    pub fn load() -> result::Result<Self> {
+        Ok(Self {
+            ignore: ignore::Ignore::load(path::Path::new("."))?,
+        })
+    }
+
+    /// Whether the ignore file excludes `path`, which it never does outside
+    /// the configuration directory.
+    pub fn is_excluded(&self, path: &path::Path) -> bool {
+        path.is_relative() && !path.starts_with("..") && self.ignore.is_ignored(path, path.is_dir())
+    }
+
+    /// Paths matching `sources`, each of which must match one.
+    pub fn expand(&self, sources: &Sources) -> result::Result<Vec<path::PathBuf>> {
+        let mut paths = vec![];
+        for pattern in &sources.0 {
+            let matched = self.expand_pattern(pattern)?;
+            if matched.is_empty() {
+                return Err(Box::new(NoSourceError {
+                    pattern: pattern.clone(),
+                }));
+            }
+            paths.extend(matched);
+        }
+        Ok(paths)
+    }
+
+    fn expand_pattern(&self, pattern: &str) -> io::Result<Vec<path::PathBuf>> {
+        let mut paths = vec![path::PathBuf::new()];
+        for component in path::Path::new(pattern).components() {
+            let name = component.as_os_str().to_string_lossy();
+            if !glob::has_wildcards(&name) {
+                paths.iter_mut().for_each(|path| path.push(component));
+                continue;
+            }
+            let mut matched = vec![];
+            for path in paths {
+                let directory = if path.as_os_str().is_empty() {
+                    path::Path::new(".")
+                } else {
+                    &path
+                };
+                let entries = match fs::read_dir(directory) {
+                    Ok(entries) => entries,
+                    Err(_) => continue,
+                };
+                let mut names = entries
+                    .map(|entry| entry.map(|entry| entry.file_name()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                names.sort();
+                for entry in names {
+                    if glob::matches(&name, &entry.to_string_lossy()) {
+                        matched.push(path.join(entry));
+                    }
+                }
+            }
+            paths = matched;
+        }
+        Ok(paths
+            .into_iter()
+            .filter(|path| fs::symlink_metadata(path).is_ok() && !self.is_excluded(path))
+            .collect())
+    }
+
+    /// Entries of the directory `path` which are not excluded, sorted.
+    pub fn entries(&self, path: &path::Path) -> io::Result<Vec<path::PathBuf>> {
+        let mut names = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<Vec<_>, _>>()?;
+        names.sort();
+        Ok(names
+            .into_iter()
+            .map(|name| path.join(name))
+            .filter(|path| !self.is_excluded(path))
+            .collect())
+    }
+
+    /// `path` and everything in it which is not excluded, recursively.
+    pub fn walk(&self, path: &path::Path) -> io::Result<Vec<path::PathBuf>> {
+        let mut paths = vec![path.to_path_buf()];
+        if path.is_dir() {
+            for entry in self.entries(path)? {
+                if fs::symlink_metadata(&entry)?.is_dir() {
+                    paths.extend(self.walk(&entry)?);
+                } else {
+                    paths.push(entry);
+                }
+            }
+        }
+        Ok(paths)
+    }
+}
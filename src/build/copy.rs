@@ -0,0 +1,135 @@
+use crate::result;
+use std::fs;
+use std::io;
+use std::os::unix::fs::symlink;
+use std::path;
+
+/// Copies `source`, a file, a symbolic link or a directory with everything in
+/// it, to `destination` and returns the paths written, except directories
+/// which existed already. Other file types are skipped.
+pub fn copy(source: &path::Path, destination: &path::Path) -> result::Result<Vec<path::PathBuf>> {
+    copy_except(source, destination, &|_| false)
+}
+
+/// Copies as `copy` does, skipping the paths in `source` which are `excluded`.
+pub fn copy_except(
+    source: &path::Path,
+    destination: &path::Path,
+    excluded: &dyn Fn(&path::Path) -> bool,
+) -> result::Result<Vec<path::PathBuf>> {
+    let mut written = vec![];
+    copy_entry(source, destination, excluded, &mut written)?;
+    Ok(written)
+}
+
+/// Removes whatever is at `path` unless it is a directory.
+pub fn remove_non_directory(path: &path::Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.is_dir() => fs::remove_file(path),
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+fn copy_entry(
+    source: &path::Path,
+    destination: &path::Path,
+    excluded: &dyn Fn(&path::Path) -> bool,
+    written: &mut Vec<path::PathBuf>,
+) -> result::Result<()> {
+    let metadata = fs::symlink_metadata(source)?;
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        remove_non_directory(destination)?;
+        symlink(fs::read_link(source)?, destination)?;
+    } else if file_type.is_dir() {
+        remove_non_directory(destination)?;
+        let existed = destination.is_dir();
+        if !existed {
+            fs::create_dir(destination)?;
+            written.push(destination.to_path_buf());
+        }
+        let mut entries = fs::read_dir(source)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for name in entries {
+            let entry = source.join(&name);
+            if !excluded(&entry) {
+                copy_entry(&entry, &destination.join(&name), excluded, written)?;
+            }
+        }
+        // after the content, as the permissions may not allow writing into it
+        if !existed {
+            fs::set_permissions(destination, metadata.permissions())?;
+        }
+        return Ok(());
+    } else if file_type.is_file() {
+        remove_non_directory(destination)?;
+        fs::copy(source, destination)?;
+    } else {
+        return Ok(());
+    }
+    written.push(destination.to_path_buf());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::copy;
+    use std::fs;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    #[test]
+    fn copy_tree() {
+        let source = tempfile::tempdir().unwrap();
+        let destination = tempfile::tempdir().unwrap();
+        let tree = source.path().join("app");
+        fs::create_dir_all(tree.join("bin")).unwrap();
+        fs::write(tree.join("bin").join("app"), "app").unwrap();
+        fs::set_permissions(
+            tree.join("bin").join("app"),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        symlink("bin/app", tree.join("app")).unwrap();
+        fs::create_dir(destination.path().join("bin")).unwrap();
+
+        let written = copy(&tree, destination.path()).unwrap();
+
+        let destination = destination.path();
+        assert_eq!(
+            written,
+            vec![destination.join("app"), destination.join("bin").join("app")]
+        );
+        assert_eq!(
+            fs::read_link(destination.join("app")).unwrap(),
+            std::path::Path::new("bin/app")
+        );
+        let mode = fs::metadata(destination.join("bin").join("app"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+
+    #[test]
+    fn replace_file() {
+        let source = tempfile::tempdir().unwrap();
+        let destination = tempfile::tempdir().unwrap();
+        fs::write(source.path().join("file"), "new").unwrap();
+        symlink("/etc/passwd", destination.path().join("file")).unwrap();
+
+        copy(
+            &source.path().join("file"),
+            &destination.path().join("file"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(destination.path().join("file")).unwrap(),
+            "new"
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use crate::result;
+use std::any;
+use std::error;
+use std::fmt;
+use std::panic;
+use std::sync;
+use std::thread;
+
+#[derive(Debug)]
+struct PanicError {
+    message: String,
+}
+
+impl PanicError {
+    fn new(payload: Box<dyn any::Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "unknown cause".to_string(),
+            },
+        };
+        Self { message }
+    }
+}
+
+impl fmt::Display for PanicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "panicked: {}", self.message)
+    }
+}
+
+impl error::Error for PanicError {}
+
+/// What became of a scheduled job.
+#[derive(Debug)]
+pub enum Outcome<T> {
+    Done(T),
+    Failed(result::BoxedError),
+    /// not run since a job it depends on did not succeed, or another failed
+    /// without `keep_going`
+    Skipped,
+}
+
+enum State<T> {
+    Waiting,
+    Running,
+    Finished(Outcome<T>),
+}
+
+struct Scheduler<'a, T> {
+    dependencies: &'a [Vec<usize>],
+    states: Vec<State<T>>,
+    /// set once a job failed without `keep_going`
+    stopped: bool,
+}
+
+impl<'a, T> Scheduler<'a, T> {
+    /// Skips the waiting jobs which can no longer run.
+    fn skip(&mut self) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for index in 0..self.states.len() {
+                if !matches!(self.states[index], State::Waiting) {
+                    continue;
+                }
+                let unsatisfiable = self.dependencies[index].iter().any(|dependency| {
+                    matches!(
+                        self.states[*dependency],
+                        State::Finished(Outcome::Failed(_)) | State::Finished(Outcome::Skipped)
+                    )
+                });
+                if self.stopped || unsatisfiable {
+                    self.states[index] = State::Finished(Outcome::Skipped);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    /// First waiting job whose dependencies are done, marked as running.
+    fn next(&mut self) -> Option<usize> {
+        let index = (0..self.states.len()).find(|index| {
+            matches!(self.states[*index], State::Waiting)
+                && self.dependencies[*index].iter().all(|dependency| {
+                    matches!(self.states[*dependency], State::Finished(Outcome::Done(_)))
+                })
+        })?;
+        self.states[index] = State::Running;
+        Some(index)
+    }
+
+    fn waiting(&self) -> bool {
+        self.states
+            .iter()
+            .any(|state| matches!(state, State::Waiting))
+    }
+}
+
+/// Runs `job` for every index of `dependencies` on up to `jobs` threads, each
+/// after the jobs it depends on succeeded, lowest indices first.
+pub fn run<T, F>(
+    dependencies: &[Vec<usize>],
+    jobs: usize,
+    keep_going: bool,
+    job: F,
+) -> Vec<Outcome<T>>
+where
+    T: Send,
+    F: Fn(usize) -> result::Result<T> + Sync,
+{
+    let scheduler = sync::Mutex::new(Scheduler {
+        dependencies,
+        states: dependencies.iter().map(|_| State::Waiting).collect(),
+        stopped: false,
+    });
+    let finished = sync::Condvar::new();
+    let worker = || loop {
+        let mut guard = scheduler.lock().unwrap();
+        let index = loop {
+            guard.skip();
+            if let Some(index) = guard.next() {
+                break index;
+            }
+            if !guard.waiting() {
+                return;
+            }
+            guard = finished.wait(guard).unwrap();
+        };
+        drop(guard);
+        // a panic fails the job, else the jobs depending on it would wait forever
+        let outcome = match panic::catch_unwind(panic::AssertUnwindSafe(|| job(index))) {
+            Ok(Ok(value)) => Outcome::Done(value),
+            Ok(Err(error)) => Outcome::Failed(error),
+            Err(payload) => Outcome::Failed(Box::new(PanicError::new(payload))),
+        };
+        let mut guard = scheduler.lock().unwrap();
+        if matches!(outcome, Outcome::Failed(_)) && !keep_going {
+            guard.stopped = true;
+        }
+        guard.states[index] = State::Finished(outcome);
+        finished.notify_all();
+    };
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, dependencies.len().max(1)) {
+            scope.spawn(worker);
+        }
+    });
+    scheduler
+        .into_inner()
+        .unwrap()
+        .states
+        .into_iter()
+        .map(|state| match state {
+            State::Finished(outcome) => outcome,
+            // every job finishes or is skipped before the workers return
+            State::Waiting | State::Running => Outcome::Skipped,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run, Outcome};
+    use std::sync;
+
+    fn summary<T>(outcomes: &[Outcome<T>]) -> Vec<&'static str> {
+        outcomes
+            .iter()
+            .map(|outcome| match outcome {
+                Outcome::Done(_) => "done",
+                Outcome::Failed(_) => "failed",
+                Outcome::Skipped => "skipped",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn run_dependencies_first() {
+        let order = sync::Mutex::new(vec![]);
+        let outcomes = run(&[vec![2], vec![], vec![1], vec![0]], 4, false, |index| {
+            order.lock().unwrap().push(index);
+            Ok(index)
+        });
+
+        assert_eq!(summary(&outcomes), vec!["done"; 4]);
+        assert_eq!(order.into_inner().unwrap(), vec![1, 2, 0, 3]);
+    }
+
+    #[test]
+    fn run_independent_jobs_concurrently() {
+        let barrier = sync::Barrier::new(2);
+        let outcomes = run(&[vec![], vec![]], 2, false, |_| {
+            // deadlocks unless both jobs run at the same time
+            barrier.wait();
+            Ok(())
+        });
+
+        assert_eq!(summary(&outcomes), vec!["done", "done"]);
+    }
+
+    #[test]
+    fn stop_after_failure() {
+        let outcomes = run(&[vec![], vec![0], vec![]], 1, false, |index| {
+            if index == 0 {
+                Err("failure".into())
+            } else {
+                Ok(())
+            }
+        });
+
+        assert_eq!(summary(&outcomes), vec!["failed", "skipped", "skipped"]);
+    }
+
+    #[test]
+    fn fail_panicking_job() {
+        let outcomes = run(&[vec![], vec![0], vec![]], 2, true, |index| {
+            if index == 0 {
+                panic!("bug");
+            }
+            Ok(())
+        });
+
+        assert_eq!(summary(&outcomes), vec!["failed", "skipped", "done"]);
+        match &outcomes[0] {
+            Outcome::Failed(error) => assert_eq!(error.to_string(), "panicked: bug"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn keep_going_after_failure() {
+        let outcomes = run(&[vec![], vec![0], vec![], vec![1, 2]], 1, true, |index| {
+            if index == 0 {
+                Err("failure".into())
+            } else {
+                Ok(())
+            }
+        });
+
+        assert_eq!(
+            summary(&outcomes),
+            vec!["failed", "skipped", "done", "skipped"]
+        );
+    }
+}
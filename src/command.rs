@@ -1,3 +1,17 @@
 mod build;
+mod clear_cache_mounts;
+mod fsck;
+mod images;
+mod inspect;
+mod prune;
+mod rmi;
+mod rootfs;
 
 pub use build::build;
+pub use clear_cache_mounts::clear_cache_mounts;
+pub use fsck::fsck;
+pub use images::images;
+pub use inspect::inspect;
+pub use prune::prune;
+pub use rmi::rmi;
+pub use rootfs::rootfs;
@@ -1,10 +1,18 @@
+use crate::build;
+use crate::build::schedule;
 use crate::config;
+use crate::config::{graph, image, scriptlet};
+use crate::registry::{docker_hub, registry};
 use crate::result;
+use crate::storage;
+use crate::storage::cache::remote;
+use chrono::Utc;
 use std::convert;
 use std::env;
 use std::error;
 use std::fmt;
 use std::path;
+use std::thread;
 
 #[derive(Debug)]
 struct UnchangedWorkingDirectory {
@@ -24,7 +32,76 @@ impl fmt::Display for UnchangedWorkingDirectory {
 
 impl error::Error for UnchangedWorkingDirectory {}
 
-pub fn build<P>(config_directory: P) -> result::Result<()>
+const SOURCE_DATE_EPOCH_ENVIRONMENT_VARIABLE: &str = "SOURCE_DATE_EPOCH";
+
+#[derive(Debug)]
+struct InvalidSourceDateEpochError {
+    value: String,
+}
+
+impl fmt::Display for InvalidSourceDateEpochError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} must be a unix time, not {:?}",
+            SOURCE_DATE_EPOCH_ENVIRONMENT_VARIABLE, self.value
+        )
+    }
+}
+
+impl error::Error for InvalidSourceDateEpochError {}
+
+#[derive(Debug)]
+struct UnreproducibleImageError {
+    image: String,
+    first: String,
+    second: String,
+}
+
+impl fmt::Display for UnreproducibleImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is not reproducible: built {} and then {}",
+            self.image, self.first, self.second
+        )
+    }
+}
+
+impl error::Error for UnreproducibleImageError {}
+
+#[derive(Debug)]
+struct FailedImagesError {
+    images: Vec<String>,
+}
+
+impl fmt::Display for FailedImagesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot build {}", self.images.join(", "))
+    }
+}
+
+impl error::Error for FailedImagesError {}
+
+/// `SOURCE_DATE_EPOCH` if set, otherwise `source_date_epoch` of the configuration.
+fn source_date_epoch(configured: Option<u64>) -> result::Result<Option<u64>> {
+    match env::var(SOURCE_DATE_EPOCH_ENVIRONMENT_VARIABLE) {
+        Ok(value) => match value.trim().parse() {
+            Ok(source_date_epoch) => Ok(Some(source_date_epoch)),
+            Err(_) => Err(Box::new(InvalidSourceDateEpochError { value })),
+        },
+        Err(_) => Ok(configured),
+    }
+}
+
+/// Builds every configured image, images not based on each other concurrently,
+/// twice when `verify_reproducible` is set to check that a build without the
+/// cache results in the same manifest.
+pub fn build<P>(
+    config_directory: P,
+    mut options: build::Options,
+    verify_reproducible: bool,
+) -> result::Result<()>
 where
     P: convert::AsRef<path::Path>,
 {
@@ -35,10 +112,98 @@ where
         }));
     }
 
-    let config = config::build()?;
-    match serde_yaml::to_string(&config) {
-        Ok(config) => println!("{}", config),
-        Err(err) => eprintln!("error occured: {}", err),
+    let mut config = config::build()?;
+    config.images = graph::select(config.images, &options.targets)?;
+    if options.list_targets {
+        for image in &config.images {
+            println!("{}:{}", image.name, image.tag);
+        }
+        return Ok(());
+    }
+    storage::initialize()?;
+    let registry = docker_hub::DockerHub::new(None)?;
+    options.source_date_epoch = source_date_epoch(config.source_date_epoch)?;
+    if verify_reproducible && options.source_date_epoch.is_none() {
+        options.source_date_epoch = Some(Utc::now().timestamp() as u64);
+    }
+    if let Some(location) = &options.cache_from {
+        match remote::import(location) {
+            Ok(imported) if imported.skipped > 0 => println!(
+                "imported {} cache entries from {}, skipped {} not matching their step or layer",
+                imported.count, location, imported.skipped
+            ),
+            Ok(imported) => println!(
+                "imported {} cache entries from {}",
+                imported.count, location
+            ),
+            // a missing cache only makes the build slower, e.g. on the first CI run
+            Err(error) => eprintln!("cannot import cache from {}: {}", location, error),
+        }
+    }
+    let jobs = match options.jobs {
+        Some(jobs) => jobs,
+        None => thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+    };
+    let outcomes = schedule::run(
+        &graph::dependencies(&config.images),
+        jobs,
+        options.keep_going,
+        |index| {
+            let image = &config.images[index];
+            let built = build::build(image, &options, &registry)?;
+            if verify_reproducible {
+                verify(image, &options, &registry, &built.digest)?;
+            }
+            Ok(built.cache_keys)
+        },
+    );
+
+    let mut cache_keys = vec![];
+    let mut failed = vec![];
+    for (image, outcome) in config.images.iter().zip(outcomes) {
+        let label = format!("{}:{}", image.name, image.tag);
+        match outcome {
+            schedule::Outcome::Done(keys) => cache_keys.extend(keys),
+            schedule::Outcome::Failed(error) => {
+                eprintln!("[{}] failed: {}", label, error);
+                failed.push(label);
+            }
+            schedule::Outcome::Skipped => {
+                eprintln!("[{}] skipped", label);
+                failed.push(label);
+            }
+        }
+    }
+    if let Some(location) = &options.cache_to {
+        let count = remote::export(location, &cache_keys)?;
+        println!("exported {} cache entries to {}", count, location);
+    }
+    if !failed.is_empty() {
+        return Err(Box::new(FailedImagesError { images: failed }));
+    }
+    Ok(())
+}
+
+/// Builds `image` again without the cache and fails unless it results in `digest`.
+fn verify(
+    image: &image::Image<scriptlet::Scriptlet>,
+    options: &build::Options,
+    registry: &dyn registry::Registry,
+    digest: &str,
+) -> result::Result<()> {
+    let uncached = build::Options {
+        no_cache: true,
+        ..options.clone()
+    };
+    let second = build::build(image, &uncached, registry)?.digest;
+    let label = format!("{}:{}", image.name, image.tag);
+    if digest != second {
+        return Err(Box::new(UnreproducibleImageError {
+            image: label,
+            first: digest.to_string(),
+            second,
+        }));
     }
+    println!("[{}] reproducible {}", label, digest);
     Ok(())
 }
@@ -0,0 +1,18 @@
+use crate::result;
+use crate::storage;
+use crate::storage::cache_mount;
+
+pub fn clear_cache_mounts(ids: Vec<String>) -> result::Result<()> {
+    storage::initialize()?;
+    let directory = cache_mount::directory();
+    let ids = if ids.is_empty() {
+        cache_mount::list(&directory)?
+    } else {
+        ids
+    };
+    for id in ids {
+        cache_mount::remove(&directory, &id)?;
+        println!("removed {}", id);
+    }
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use crate::registry::docker_hub;
+use crate::result;
+use crate::storage;
+use crate::storage::fsck;
+use std::error;
+use std::fmt;
+
+#[derive(Debug)]
+struct IntegrityError {
+    problems: usize,
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "storage has {} unresolved problem(s)", self.problems)
+    }
+}
+
+impl error::Error for IntegrityError {}
+
+pub fn fsck(options: fsck::Options) -> result::Result<()> {
+    storage::initialize()?;
+    let registry = docker_hub::DockerHub::new(None)?;
+    let report = fsck::check(&options, &registry)?;
+    for problem in &report.problems {
+        println!("{}", problem);
+    }
+    for digest in &report.quarantined {
+        println!("quarantined {}", digest);
+    }
+    for digest in &report.repaired {
+        println!("repaired {}", digest);
+    }
+    for digest in &report.unrepairable {
+        println!("cannot repair {}: not pulled from a registry", digest);
+    }
+    let problems = report.unresolved().count();
+    println!(
+        "checked {} blob(s), {} unresolved problem(s)",
+        report.checked, problems
+    );
+    if problems > 0 {
+        return Err(Box::new(IntegrityError { problems }));
+    }
+    Ok(())
+}
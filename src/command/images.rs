@@ -0,0 +1,72 @@
+use crate::result;
+use crate::storage;
+use crate::storage::{images, index};
+use crate::unit;
+
+const SHORT_DIGEST_LENGTH: usize = 12;
+
+fn short_digest(digest: &str) -> &str {
+    match storage::digest::split(digest) {
+        Some((_, encoded)) if encoded.len() > SHORT_DIGEST_LENGTH => {
+            &encoded[..SHORT_DIGEST_LENGTH]
+        }
+        _ => digest,
+    }
+}
+
+pub fn images(no_trunc: bool) -> result::Result<()> {
+    storage::initialize()?;
+    let index = index::Index::load(index::path())?;
+    let images_path = images::path();
+    let mut database = images::ImageDatabase::load(&images_path)?;
+
+    let mut rows = vec![[
+        "REPOSITORY".to_string(),
+        "TAG".to_string(),
+        "DIGEST".to_string(),
+        "SIZE".to_string(),
+        "CREATED".to_string(),
+    ]];
+    for (repository, tag, entry) in index.entries() {
+        let (size, created) = match database.get_or_describe(&entry.digest) {
+            Ok(record) => (
+                unit::format_size(record.size as u64),
+                record
+                    .created
+                    .map(|created| created.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+            ),
+            Err(_) => ("-".to_string(), "-".to_string()),
+        };
+        let digest = if no_trunc {
+            entry.digest.as_str()
+        } else {
+            short_digest(&entry.digest)
+        };
+        rows.push([
+            repository.to_string(),
+            tag.to_string(),
+            digest.to_string(),
+            size,
+            created,
+        ]);
+    }
+    database.save(&images_path)?;
+
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.len());
+        }
+    }
+    for row in &rows {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(column, width)| format!("{:width$}", column, width = width))
+            .collect::<Vec<_>>()
+            .join("   ");
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use crate::result;
+use crate::storage;
+use crate::storage::{images, index, reference};
+use std::fs;
+
+pub fn inspect(references: Vec<String>) -> result::Result<()> {
+    storage::initialize()?;
+    let index = index::Index::load(index::path())?;
+    let mut database = images::ImageDatabase::load(images::path())?;
+
+    let mut inspected = vec![];
+    for reference in references {
+        let digest = reference::resolve(&index, &reference)?.digest().to_string();
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&fs::read(storage::blob_path(&digest))?)?;
+        let record = database.get_or_describe(&digest)?;
+        let config: serde_json::Value =
+            serde_json::from_slice(&fs::read(storage::blob_path(&record.config))?)?;
+        let tags = reference::tags_of(&index, &digest)
+            .into_iter()
+            .map(|(repository, tag)| format!("{}:{}", repository, tag))
+            .collect::<Vec<_>>();
+        inspected.push(serde_json::json!({
+            "digest": digest,
+            "tags": tags,
+            "size": record.size,
+            "manifest": manifest,
+            "config": config,
+        }));
+    }
+    println!("{}", serde_json::to_string_pretty(&inspected)?);
+    Ok(())
+}
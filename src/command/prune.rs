@@ -0,0 +1,32 @@
+use crate::result;
+use crate::storage;
+use crate::storage::gc;
+use crate::unit;
+
+pub fn prune(options: gc::Options) -> result::Result<()> {
+    storage::initialize()?;
+    let report = gc::collect(&options)?;
+    let (remove, evict, reclaim) = if options.dry_run {
+        ("would remove", "would evict", "would reclaim")
+    } else {
+        ("removed", "evicted", "reclaimed")
+    };
+    for image in &report.evicted {
+        println!("{} {}", evict, image);
+    }
+    for blob in &report.removed {
+        println!(
+            "{} {} ({})",
+            remove,
+            blob.digest,
+            unit::format_size(blob.size)
+        );
+    }
+    println!(
+        "{} {}, {} remaining",
+        reclaim,
+        unit::format_size(report.reclaimed),
+        unit::format_size(report.remaining)
+    );
+    Ok(())
+}
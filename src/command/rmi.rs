@@ -0,0 +1,42 @@
+use crate::result;
+use crate::storage;
+use crate::storage::{images, index, reference};
+
+pub fn rmi(references: Vec<String>) -> result::Result<()> {
+    storage::initialize()?;
+    let index_path = index::path();
+    let mut index = index::Index::load(&index_path)?;
+    let images_path = images::path();
+    let mut database = images::ImageDatabase::load(&images_path)?;
+
+    // all of them first, so that nothing is removed if one is unknown
+    let resolved = references
+        .iter()
+        .map(|reference| reference::resolve(&index, reference))
+        .collect::<result::Result<Vec<_>>>()?;
+    let mut removed = false;
+    for resolved in resolved {
+        let untagged = match &resolved {
+            reference::Resolved::Tag {
+                repository, tag, ..
+            } => vec![(repository.clone(), tag.clone())],
+            reference::Resolved::Digest { digest } => reference::tags_of(&index, digest),
+        };
+        for (repository, tag) in untagged {
+            index.untag(&repository, &tag);
+            println!("untagged {}:{}", repository, tag);
+        }
+        let digest = resolved.digest();
+        if reference::tags_of(&index, digest).is_empty() {
+            database.images.remove(digest);
+            println!("removed {} from the image database", digest);
+            removed = true;
+        }
+    }
+    index.save(&index_path)?;
+    database.save(&images_path)?;
+    if removed {
+        println!("run `amethyst prune` to delete the blobs no longer used");
+    }
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use crate::result;
+use crate::rootfs;
+use crate::storage;
+use crate::storage::{index, reference};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path;
+
+#[derive(Debug)]
+struct NonEmptyDirectoryError {
+    directory: String,
+}
+
+impl fmt::Display for NonEmptyDirectoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot export into non-empty directory {}",
+            self.directory
+        )
+    }
+}
+
+impl error::Error for NonEmptyDirectoryError {}
+
+pub fn rootfs(reference: String, directory: String) -> result::Result<()> {
+    storage::initialize()?;
+    let index = index::Index::load(index::path())?;
+    let digest = reference::resolve(&index, &reference)?.digest().to_string();
+    let manifest = storage::read_manifest(&digest)?;
+
+    let root = path::Path::new(&directory);
+    fs::create_dir_all(root)?;
+    if fs::read_dir(root)?.next().is_some() {
+        return Err(Box::new(NonEmptyDirectoryError { directory }));
+    }
+    rootfs::materialize(root, &manifest.layers)?;
+    println!("exported {} to {}", digest, directory);
+    Ok(())
+}
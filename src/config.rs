@@ -1,6 +1,9 @@
+pub mod graph;
+pub mod ignore;
 pub mod image;
 pub mod module;
 pub mod scriptlet;
+pub mod secret;
 
 use crate::result;
 use serde::{Deserialize, Deserializer, Serialize};
@@ -20,6 +23,9 @@ pub struct Config<Script> {
     #[serde(deserialize_with = "deserialize_images")]
     #[serde(bound(deserialize = "Script: Deserialize<'de>"))]
     pub images: Vec<image::Image<Script>>,
+    /// Unix time clamping the timestamps of built images, overridden by `SOURCE_DATE_EPOCH`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_date_epoch: Option<u64>,
 }
 
 fn deserialize_images<'de, Script, D>(
@@ -77,25 +83,24 @@ fn load() -> result::Result<Config<module::Module>> {
 }
 
 pub fn build() -> result::Result<Config<scriptlet::Scriptlet>> {
-    let config = match load() {
-        Ok(config) => config,
-        Err(err) => return Err(err),
-    };
+    let config = load()?;
     let mut images = vec![];
     for image in config.images {
-        let scriptlets = match image.slurp_scriptlets() {
-            Ok(scriptlets) => scriptlets,
-            Err(err) => return Err(err),
-        };
+        let scriptlets = image.slurp_scriptlets()?;
         let image = image::Image {
             scripts: scriptlets,
             base_image: image.base_image,
             name: image.name,
             tag: image.tag,
+            compression: image.compression,
+            secrets: image.secrets,
         };
         images.push(image);
     }
-    Ok(Config { images })
+    Ok(Config {
+        images: graph::order(images)?,
+        source_date_epoch: config.source_date_epoch,
+    })
 }
 
 // `std::env::set_current_dir` function can only be configured on a per-process
@@ -0,0 +1,489 @@
+use super::image::{self, typ};
+use super::scriptlet;
+use crate::glob;
+use crate::result;
+use std::error;
+use std::fmt;
+
+#[derive(Debug)]
+struct UnknownLocalImageError {
+    image: String,
+    base: String,
+}
+
+impl fmt::Display for UnknownLocalImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} uses local image {}, which is not configured",
+            self.image, self.base
+        )
+    }
+}
+
+impl error::Error for UnknownLocalImageError {}
+
+#[derive(Debug)]
+struct AmbiguousLocalImageError {
+    image: String,
+    base: String,
+    candidates: Vec<String>,
+}
+
+impl fmt::Display for AmbiguousLocalImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} uses local image {}, which may be any of {}: set its tag",
+            self.image,
+            self.base,
+            self.candidates.join(", ")
+        )
+    }
+}
+
+impl error::Error for AmbiguousLocalImageError {}
+
+#[derive(Debug)]
+struct DependencyCycleError {
+    /// labels of the images in the cycle, the first one repeated at the end
+    cycle: Vec<String>,
+}
+
+impl fmt::Display for DependencyCycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "images are based on each other: {}",
+            self.cycle.join(" -> ")
+        )
+    }
+}
+
+impl error::Error for DependencyCycleError {}
+
+/// Step of an image which may use another image besides its base image.
+pub trait Step {
+    fn image(&self) -> Option<&typ::ImageType>;
+    fn image_mut(&mut self) -> Option<&mut typ::ImageType>;
+}
+
+impl Step for scriptlet::Scriptlet {
+    fn image(&self) -> Option<&typ::ImageType> {
+        match self {
+            Self::CopyFrom { from, .. } => Some(from),
+            _ => None,
+        }
+    }
+
+    fn image_mut(&mut self) -> Option<&mut typ::ImageType> {
+        match self {
+            Self::CopyFrom { from, .. } => Some(from),
+            _ => None,
+        }
+    }
+}
+
+/// Base image and images used by the steps of `image`.
+fn uses<Script: Step>(image: &image::Image<Script>) -> Vec<&typ::ImageType> {
+    let mut uses = vec![&image.base_image];
+    uses.extend(image.scripts.iter().filter_map(Step::image));
+    uses
+}
+
+fn uses_mut<Script: Step>(image: &mut image::Image<Script>) -> Vec<&mut typ::ImageType> {
+    let mut uses = vec![&mut image.base_image];
+    uses.extend(image.scripts.iter_mut().filter_map(Step::image_mut));
+    uses
+}
+
+fn label<Script>(image: &image::Image<Script>) -> String {
+    format!("{}:{}", image.name, image.tag)
+}
+
+/// Index of the image a local image used by `image` refers to.
+fn resolve<Script>(
+    images: &[image::Image<Script>],
+    image: &image::Image<Script>,
+    name: &str,
+    tag: &Option<String>,
+) -> result::Result<usize> {
+    let candidates = images
+        .iter()
+        .enumerate()
+        .filter(|(_, candidate)| {
+            candidate.name == name && tag.as_ref().is_none_or(|tag| &candidate.tag == tag)
+        })
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let base = match tag {
+        Some(tag) => format!("{}:{}", name, tag),
+        None => name.to_string(),
+    };
+    match candidates.as_slice() {
+        [index] => Ok(*index),
+        [] => Err(Box::new(UnknownLocalImageError {
+            image: label(image),
+            base,
+        })),
+        _ => Err(Box::new(AmbiguousLocalImageError {
+            image: label(image),
+            base,
+            candidates: candidates
+                .iter()
+                .map(|index| label(&images[*index]))
+                .collect(),
+        })),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    Unvisited,
+    Visiting,
+    Visited,
+}
+
+fn visit(
+    index: usize,
+    dependencies: &[Vec<usize>],
+    marks: &mut [Mark],
+    path: &mut Vec<usize>,
+    order: &mut Vec<usize>,
+) -> Result<(), Vec<usize>> {
+    match marks[index] {
+        Mark::Visited => return Ok(()),
+        Mark::Visiting => {
+            let start = path.iter().position(|visiting| *visiting == index).unwrap();
+            let mut cycle = path[start..].to_vec();
+            cycle.push(index);
+            return Err(cycle);
+        }
+        Mark::Unvisited => {}
+    }
+    marks[index] = Mark::Visiting;
+    path.push(index);
+    for dependency in &dependencies[index] {
+        visit(*dependency, dependencies, marks, path, order)?;
+    }
+    path.pop();
+    marks[index] = Mark::Visited;
+    order.push(index);
+    Ok(())
+}
+
+/// Orders `images` so that local images come before the images based on or
+/// copying from them, keeping the configured order otherwise, and sets the
+/// tag of every local image.
+pub fn order<Script: Step>(
+    mut images: Vec<image::Image<Script>>,
+) -> result::Result<Vec<image::Image<Script>>> {
+    let mut dependencies = vec![];
+    let mut tags = vec![];
+    for image in &images {
+        let mut image_dependencies = vec![];
+        let mut image_tags = vec![];
+        for used in uses(image) {
+            if let typ::ImageType::Local { name, tag } = used {
+                let dependency = resolve(&images, image, name, tag)?;
+                image_tags.push(images[dependency].tag.clone());
+                if !image_dependencies.contains(&dependency) {
+                    image_dependencies.push(dependency);
+                }
+            }
+        }
+        dependencies.push(image_dependencies);
+        tags.push(image_tags);
+    }
+    for (image, tags) in images.iter_mut().zip(tags) {
+        let locals = uses_mut(image).into_iter().filter_map(|used| match used {
+            typ::ImageType::Local { tag, .. } => Some(tag),
+            _ => None,
+        });
+        for (tag, resolved) in locals.zip(tags) {
+            *tag = Some(resolved);
+        }
+    }
+
+    let mut marks = vec![Mark::Unvisited; images.len()];
+    let mut order = vec![];
+    for index in 0..images.len() {
+        if let Err(cycle) = visit(index, &dependencies, &mut marks, &mut vec![], &mut order) {
+            return Err(Box::new(DependencyCycleError {
+                cycle: cycle.iter().map(|index| label(&images[*index])).collect(),
+            }));
+        }
+    }
+    let mut images = images.into_iter().map(Some).collect::<Vec<_>>();
+    Ok(order
+        .into_iter()
+        .filter_map(|index| images[index].take())
+        .collect())
+}
+
+#[derive(Debug)]
+struct UnmatchedTargetError {
+    pattern: String,
+}
+
+impl fmt::Display for UnmatchedTargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no configured image matches {:?}", self.pattern)
+    }
+}
+
+impl error::Error for UnmatchedTargetError {}
+
+/// Keeps the images of `images`, as returned by `order`, whose name or
+/// `name:tag` matches any of `patterns`, and the local images they use.
+pub fn select<Script: Step>(
+    images: Vec<image::Image<Script>>,
+    patterns: &[String],
+) -> result::Result<Vec<image::Image<Script>>> {
+    if patterns.is_empty() {
+        return Ok(images);
+    }
+    let dependencies = dependencies(&images);
+    let mut selected = vec![false; images.len()];
+    for pattern in patterns {
+        let mut matched = false;
+        for (index, image) in images.iter().enumerate() {
+            if !glob::matches(pattern, &image.name) && !glob::matches(pattern, &label(image)) {
+                continue;
+            }
+            matched = true;
+            let mut pending = vec![index];
+            while let Some(index) = pending.pop() {
+                if !selected[index] {
+                    selected[index] = true;
+                    pending.extend(&dependencies[index]);
+                }
+            }
+        }
+        if !matched {
+            return Err(Box::new(UnmatchedTargetError {
+                pattern: pattern.clone(),
+            }));
+        }
+    }
+    Ok(images
+        .into_iter()
+        .zip(selected)
+        .filter_map(|(image, selected)| selected.then_some(image))
+        .collect())
+}
+
+/// Indices of the local images used by every image of `images`, as returned by `order`.
+pub fn dependencies<Script: Step>(images: &[image::Image<Script>]) -> Vec<Vec<usize>> {
+    images
+        .iter()
+        .map(|image| {
+            let mut dependencies = vec![];
+            for used in uses(image) {
+                let dependency = match used {
+                    typ::ImageType::Local { name, tag } => images.iter().position(|candidate| {
+                        &candidate.name == name
+                            && tag.as_ref().is_none_or(|tag| &candidate.tag == tag)
+                    }),
+                    _ => None,
+                };
+                if let Some(dependency) = dependency {
+                    if !dependencies.contains(&dependency) {
+                        dependencies.push(dependency);
+                    }
+                }
+            }
+            dependencies
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dependencies, order, select, Step};
+    use crate::config::image::{typ, Image};
+    use crate::config::scriptlet::Scriptlet;
+
+    impl Step for () {
+        fn image(&self) -> Option<&typ::ImageType> {
+            None
+        }
+
+        fn image_mut(&mut self) -> Option<&mut typ::ImageType> {
+            None
+        }
+    }
+
+    fn image(name: &str, base_image: typ::ImageType) -> Image<()> {
+        image_with(name, base_image, vec![])
+    }
+
+    fn image_with<Script>(
+        name: &str,
+        base_image: typ::ImageType,
+        scripts: Vec<Script>,
+    ) -> Image<Script> {
+        Image {
+            scripts,
+            base_image,
+            name: name.to_string(),
+            tag: "latest".to_string(),
+            compression: None,
+            secrets: Default::default(),
+        }
+    }
+
+    fn local(name: &str) -> typ::ImageType {
+        typ::ImageType::Local {
+            name: name.to_string(),
+            tag: None,
+        }
+    }
+
+    fn names<Script>(images: &[Image<Script>]) -> Vec<&str> {
+        images.iter().map(|image| image.name.as_str()).collect()
+    }
+
+    #[test]
+    fn build_local_base_images_first() {
+        let images = order(vec![
+            image("app", local("runtime")),
+            image("tools", typ::ImageType::Scratch),
+            image("runtime", local("builder")),
+            image("builder", typ::ImageType::Scratch),
+        ])
+        .unwrap();
+
+        assert_eq!(names(&images), vec!["builder", "runtime", "app", "tools"]);
+        assert_eq!(
+            dependencies(&images),
+            vec![vec![], vec![0], vec![1], vec![]]
+        );
+        assert_eq!(
+            images[2].base_image,
+            typ::ImageType::Local {
+                name: "runtime".to_string(),
+                tag: Some("latest".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn select_targets_with_their_base_images() {
+        let images = order(vec![
+            image("app", local("runtime")),
+            image("tools", typ::ImageType::Scratch),
+            image("runtime", local("builder")),
+            image("builder", typ::ImageType::Scratch),
+            image("app-debug", local("runtime")),
+        ])
+        .unwrap();
+
+        let selected = select(images, &["app".to_string()]).unwrap();
+        assert_eq!(names(&selected), vec!["builder", "runtime", "app"]);
+        assert_eq!(dependencies(&selected), vec![vec![], vec![0], vec![1]]);
+    }
+
+    #[test]
+    fn build_copied_images_first() {
+        let copy_from = |from: &str| Scriptlet::CopyFrom {
+            from: local(from),
+            source: "/opt".to_string(),
+            destination: "/opt".to_string(),
+            owner: None,
+            mode: None,
+        };
+        let images = order(vec![
+            image_with(
+                "app",
+                local("runtime"),
+                vec![
+                    copy_from("builder"),
+                    copy_from("assets"),
+                    copy_from("builder"),
+                ],
+            ),
+            image_with("runtime", typ::ImageType::Scratch, vec![]),
+            image_with("assets", typ::ImageType::Scratch, vec![]),
+            image_with("builder", typ::ImageType::Scratch, vec![]),
+        ])
+        .unwrap();
+
+        assert_eq!(names(&images), vec!["runtime", "builder", "assets", "app"]);
+        assert_eq!(
+            dependencies(&images),
+            vec![vec![], vec![], vec![], vec![0, 1, 2]]
+        );
+        assert_eq!(
+            images[3].scripts[1].image(),
+            Some(&typ::ImageType::Local {
+                name: "assets".to_string(),
+                tag: Some("latest".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn select_targets_by_glob() {
+        let images = vec![
+            image("app", typ::ImageType::Scratch),
+            image("app-debug", typ::ImageType::Scratch),
+            image("tools", typ::ImageType::Scratch),
+        ];
+
+        let selected = select(images, &["app*".to_string()]).unwrap();
+        assert_eq!(names(&selected), vec!["app", "app-debug"]);
+    }
+
+    #[test]
+    fn cannot_select_unknown_target() {
+        let images = vec![image("app", typ::ImageType::Scratch)];
+
+        assert!(select(images, &["tools:*".to_string()]).is_err());
+    }
+
+    #[test]
+    fn keep_order_without_local_base_images() {
+        let images = order(vec![
+            image("b", typ::ImageType::Scratch),
+            image("a", typ::ImageType::Scratch),
+        ])
+        .unwrap();
+
+        assert_eq!(names(&images), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn detect_cycle() {
+        let error = order(vec![
+            image("tools", typ::ImageType::Scratch),
+            image("a", local("b")),
+            image("b", local("c")),
+            image("c", local("a")),
+        ])
+        .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "images are based on each other: a:latest -> b:latest -> c:latest -> a:latest"
+        );
+    }
+
+    #[test]
+    fn cannot_order_unknown_local_base_image() {
+        assert!(order(vec![image("app", local("runtime"))]).is_err());
+    }
+
+    #[test]
+    fn cannot_order_ambiguous_local_base_image() {
+        let mut other = image("runtime", typ::ImageType::Scratch);
+        other.tag = "1.0".to_string();
+        let images = vec![
+            image("app", local("runtime")),
+            image("runtime", typ::ImageType::Scratch),
+            other,
+        ];
+
+        assert!(order(images).is_err());
+    }
+}
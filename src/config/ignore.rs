@@ -0,0 +1,124 @@
+use crate::glob;
+use crate::result;
+use std::fs;
+use std::io;
+use std::path;
+
+/// Paths of the configuration directory excluded from `add` scriptlets, one
+/// pattern per line as in `.gitignore`.
+pub const IGNORE_FILE_NAME: &str = ".amethystignore";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    /// matched against the whole path relative to the configuration directory
+    pattern: String,
+    /// includes the matching paths again
+    negated: bool,
+    directory_only: bool,
+}
+
+/// Rules of an ignore file, where the last rule matching a path decides.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ignore {
+    rules: Vec<Rule>,
+}
+
+impl Ignore {
+    /// Rules of `IGNORE_FILE_NAME` in `directory`, none if it does not exist.
+    pub fn load(directory: &path::Path) -> result::Result<Self> {
+        match fs::read_to_string(directory.join(IGNORE_FILE_NAME)) {
+            Ok(content) => Ok(Self::parse(&content)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(Box::new(err)),
+        }
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let rules = content
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (negated, line) = match line.strip_prefix('!') {
+                    Some(line) => (true, line),
+                    None => (false, line.strip_prefix('\\').unwrap_or(line)),
+                };
+                let (directory_only, line) = match line.strip_suffix('/') {
+                    Some(line) => (true, line),
+                    None => (false, line),
+                };
+                // as in `.gitignore`, patterns without a slash match at any depth
+                let pattern = match line.strip_prefix('/') {
+                    Some(line) => line.to_string(),
+                    None if line.contains('/') => line.to_string(),
+                    None => format!("**/{}", line),
+                };
+                Rule {
+                    pattern,
+                    negated,
+                    directory_only,
+                }
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Whether `path`, relative to the configuration directory, is excluded,
+    /// either itself or as it is in an excluded directory.
+    pub fn is_ignored(&self, path: &path::Path, directory: bool) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+        let components = path
+            .components()
+            .filter_map(|component| match component {
+                path::Component::Normal(name) => Some(name.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        (1..=components.len()).any(|length| {
+            let is_directory = length < components.len() || directory;
+            self.matches(&components[..length].join("/"), is_directory)
+        })
+    }
+
+    fn matches(&self, path: &str, directory: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                (directory || !rule.directory_only) && glob::matches_path(&rule.pattern, path)
+            })
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Ignore;
+    use std::path::Path;
+
+    #[test]
+    fn ignore_as_gitignore() {
+        let ignore = Ignore::parse(
+            r#"
+            # build outputs
+            target/
+            *.log
+            !important.log
+            /secrets
+            docs/**/*.md
+            "#,
+        );
+
+        assert!(ignore.is_ignored(Path::new("target"), true));
+        assert!(ignore.is_ignored(Path::new("app/target/debug/app"), false));
+        assert!(!ignore.is_ignored(Path::new("target"), false));
+        assert!(ignore.is_ignored(Path::new("logs/build.log"), false));
+        assert!(!ignore.is_ignored(Path::new("logs/important.log"), false));
+        assert!(ignore.is_ignored(Path::new("secrets/token"), false));
+        assert!(!ignore.is_ignored(Path::new("app/secrets"), false));
+        assert!(ignore.is_ignored(Path::new("docs/api/index.md"), false));
+        assert!(!ignore.is_ignored(Path::new("./src/main.rs"), false));
+    }
+}
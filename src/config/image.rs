@@ -1,8 +1,11 @@
 pub mod tag;
 pub mod typ;
 
+use super::secret;
+use crate::layer::compression;
 use crate::result;
 use serde::{de, Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Deserialize, Serialize)]
 #[cfg_attr(test, derive(PartialEq))]
@@ -14,11 +17,18 @@ pub struct Image<Script> {
     pub name: String,
     #[serde(default = "default_tag")]
     pub tag: String,
+    /// codec of the layers of this image, instead of the one of the build
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<compression::Compression>,
+    /// secrets `run` scriptlets may mount by id, unless `--secret` declares the same id
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub secrets: BTreeMap<String, secret::Source>,
 }
 
 impl Image<super::module::Module> {
     pub fn slurp_scriptlets(&self) -> result::Result<Vec<super::scriptlet::Scriptlet>> {
-        let scriptlets = (&self.scripts)
+        let scriptlets = self
+            .scripts
             .iter()
             .map(|module| module.to_scriptlets())
             .collect::<Result<Vec<_>, _>>()?
@@ -112,6 +122,8 @@ mod tests {
                     name: image_name.to_string(),
                     scripts,
                     tag: tag::LATEST_TAG.to_string(),
+                    compression: None,
+                    secrets: Default::default(),
                 };
                 let deserialized_image = serde_yaml::from_str::<Image<i32>>(&original_string);
 
@@ -153,6 +165,8 @@ mod tests {
                     name: image_name.to_string(),
                     scripts,
                     tag: image_tag.to_string(),
+                    compression: None,
+                    secrets: Default::default(),
                 };
                 let deserialized_image = serde_yaml::from_str::<Image<i32>>(&original_string);
 
@@ -171,6 +185,8 @@ mod tests {
             scripts: vec![1],
             name: image_name.to_string(),
             tag: image_tag.to_string(),
+            compression: None,
+            secrets: Default::default(),
         };
         let expected_string = format!(
             r#"---
@@ -212,6 +228,8 @@ tag: {}
                 tag: "tag".to_string(),
                 scripts,
                 name: "name".to_string(),
+                compression: None,
+                secrets: Default::default(),
             };
 
             assert!(image.slurp_scriptlets().is_err());
@@ -220,14 +238,19 @@ tag: {}
         #[test]
         fn scriptlets_slurpable() {
             let scripts = vec![module::Module::Inline(scriptlet::Scriptlet::Add {
-                source: "source".to_string(),
+                source: "source".to_string().into(),
                 destination: "destination".to_string(),
+                owner: None,
+                mode: None,
+                preserve_timestamps: false,
             })];
             let image = Image::<module::Module> {
                 base_image: typ::ImageType::Scratch,
                 tag: "tag".to_string(),
                 scripts,
                 name: "name".to_string(),
+                compression: None,
+                secrets: Default::default(),
             };
 
             assert!(image.slurp_scriptlets().is_ok());
@@ -1,17 +1,46 @@
 use serde::{de, ser::SerializeStruct, Deserialize, Serialize};
-use std::default;
+use std::fmt;
 
 use super::tag;
 
-#[derive(Debug)]
-#[cfg_attr(test, derive(PartialEq))]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub enum ImageType {
+    #[default]
     Scratch,
-    BaseImage { name: String, tag: String },
+    BaseImage {
+        name: String,
+        tag: String,
+    },
+    /// Another image of the same configuration, built before this one
+    Local {
+        name: String,
+        /// needed only if several images are named `name`
+        tag: Option<String>,
+    },
+}
+
+impl fmt::Display for ImageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageType::Scratch => write!(f, "{}", SCRATCH_IMAGE_NAME),
+            ImageType::BaseImage { name, tag } => write!(f, "{}:{}", name, tag),
+            ImageType::Local { name, tag: None } => write!(f, "{}", name),
+            ImageType::Local {
+                name,
+                tag: Some(tag),
+            } => write!(f, "{}:{}", name, tag),
+        }
+    }
 }
 
 const NAME_ATTRIBUTE_NAME: &str = "name";
 const TAG_ATTRIBUTE_NAME: &str = "tag";
+const LOCAL_ATTRIBUTE_NAME: &str = "local";
+const FIELDS: &[&str] = &[
+    NAME_ATTRIBUTE_NAME,
+    TAG_ATTRIBUTE_NAME,
+    LOCAL_ATTRIBUTE_NAME,
+];
 pub const SCRATCH_IMAGE_NAME: &str = "scratch";
 
 impl<'de> Deserialize<'de> for ImageType {
@@ -23,6 +52,7 @@ impl<'de> Deserialize<'de> for ImageType {
         enum Field {
             Name,
             Tag,
+            Local,
         }
         impl<'de> Deserialize<'de> for Field {
             fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
@@ -35,7 +65,7 @@ impl<'de> Deserialize<'de> for ImageType {
                     type Value = Field;
 
                     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                        formatter.write_str("`name` required")
+                        formatter.write_str("`name` or `local` required")
                     }
 
                     fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
@@ -45,10 +75,8 @@ impl<'de> Deserialize<'de> for ImageType {
                         match v {
                             NAME_ATTRIBUTE_NAME => Ok(Field::Name),
                             TAG_ATTRIBUTE_NAME => Ok(Field::Tag),
-                            _ => Err(de::Error::unknown_field(
-                                v,
-                                &[NAME_ATTRIBUTE_NAME, TAG_ATTRIBUTE_NAME],
-                            )),
+                            LOCAL_ATTRIBUTE_NAME => Ok(Field::Local),
+                            _ => Err(de::Error::unknown_field(v, FIELDS)),
                         }
                     }
                 }
@@ -71,6 +99,7 @@ impl<'de> Deserialize<'de> for ImageType {
             {
                 let mut name = None;
                 let mut tag = None;
+                let mut local = None;
 
                 while let Some(key) = map.next_key()? {
                     match key {
@@ -86,8 +115,22 @@ impl<'de> Deserialize<'de> for ImageType {
                             }
                             tag = map.next_value::<Option<String>>()?;
                         }
+                        Field::Local => {
+                            if local.is_some() {
+                                return Err(de::Error::duplicate_field(LOCAL_ATTRIBUTE_NAME));
+                            }
+                            local = map.next_value::<Option<String>>()?;
+                        }
                     }
                 }
+                if let Some(local) = local {
+                    if name.is_some() {
+                        return Err(de::Error::custom(
+                            "`name` and `local` cannot be used together",
+                        ));
+                    }
+                    return Ok(ImageType::Local { name: local, tag });
+                }
                 let name = name.ok_or_else(|| de::Error::missing_field(NAME_ATTRIBUTE_NAME))?;
                 let name = match name.as_str() {
                     SCRATCH_IMAGE_NAME => return Ok(ImageType::Scratch),
@@ -98,11 +141,7 @@ impl<'de> Deserialize<'de> for ImageType {
             }
         }
 
-        deserializer.deserialize_struct(
-            "ImageType",
-            &[NAME_ATTRIBUTE_NAME, TAG_ATTRIBUTE_NAME],
-            ImageTypeVisitor,
-        )
+        deserializer.deserialize_struct("ImageType", FIELDS, ImageTypeVisitor)
     }
 }
 
@@ -123,16 +162,18 @@ impl Serialize for ImageType {
                 state.serialize_field(TAG_ATTRIBUTE_NAME, tag)?;
                 state.end()
             }
+            ImageType::Local { name, tag } => {
+                let mut state = serializer.serialize_struct("ImageType", 2)?;
+                state.serialize_field(LOCAL_ATTRIBUTE_NAME, name)?;
+                if let Some(tag) = tag {
+                    state.serialize_field(TAG_ATTRIBUTE_NAME, tag)?;
+                }
+                state.end()
+            }
         }
     }
 }
 
-impl default::Default for ImageType {
-    fn default() -> Self {
-        Self::Scratch
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use std::default;
@@ -187,6 +228,20 @@ tag: {}
     }
 
     mod deserializability {
+        mod undeserializable {
+            use super::super::super::ImageType;
+
+            #[test]
+            fn both_registry_and_local_image() {
+                let original_string = r#"---
+                    name: ubuntu
+                    local: builder
+                    "#;
+
+                assert!(serde_yaml::from_str::<ImageType>(original_string).is_err());
+            }
+        }
+
         mod deserializable {
             use super::super::super::{tag, ImageType, SCRATCH_IMAGE_NAME};
 
@@ -226,6 +281,21 @@ tag: {}
                 assert_eq!(image_type, deserialized_image_type.unwrap());
             }
 
+            #[test]
+            fn local_image() {
+                let original_string = r#"---
+                    local: builder
+                    "#;
+                let image_type = ImageType::Local {
+                    name: "builder".to_string(),
+                    tag: None,
+                };
+                let deserialized_image_type = serde_yaml::from_str(original_string);
+
+                assert!(deserialized_image_type.is_ok());
+                assert_eq!(image_type, deserialized_image_type.unwrap());
+            }
+
             #[test]
             fn latest_non_scrach_image() {
                 let name = "base_image_name";
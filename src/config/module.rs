@@ -17,7 +17,7 @@ impl Module {
     pub fn to_scriptlets(&self) -> result::Result<Vec<crate::config::scriptlet::Scriptlet>> {
         match self {
             Self::File(path) => {
-                let raw_scriptlets = match std::fs::read_to_string(&path) {
+                let raw_scriptlets = match std::fs::read_to_string(path) {
                     Ok(raw_scriptlets) => raw_scriptlets,
                     Err(err) => return Err(scriptlet_load_error(path, Box::new(err))),
                 };
@@ -104,12 +104,18 @@ mod tests {
                     tempfile::NamedTempFile::new().expect("temporary file created");
                 let original_scriptlets = vec![
                     scriptlet::Scriptlet::Add {
-                        source: "source1.yaml".to_string(),
+                        source: "source1.yaml".to_string().into(),
                         destination: "destination1.yaml".to_string(),
+                        owner: None,
+                        mode: None,
+                        preserve_timestamps: false,
                     },
                     scriptlet::Scriptlet::Add {
-                        source: "source2.yaml".to_string(),
+                        source: "source2.yaml".to_string().into(),
                         destination: "destination2.yaml".to_string(),
+                        owner: None,
+                        mode: None,
+                        preserve_timestamps: false,
                     },
                 ];
                 let content = serde_yaml::to_string(&original_scriptlets)
@@ -153,8 +159,11 @@ mod tests {
             #[test]
             fn to_scriptlets() {
                 let original_scriptlet = scriptlet::Scriptlet::Add {
-                    source: "source.yaml".to_string(),
+                    source: "source.yaml".to_string().into(),
                     destination: "destination.yaml".to_string(),
+                    owner: None,
+                    mode: None,
+                    preserve_timestamps: false,
                 };
                 let module = Module::Inline(original_scriptlet.clone());
                 let parsed_scriptlets = module.to_scriptlets();
@@ -187,8 +196,11 @@ mod tests {
             assert_eq!(
                 deserialized_module.unwrap(),
                 Module::Inline(scriptlet::Scriptlet::Add {
-                    source: source_path.to_string(),
+                    source: source_path.to_string().into(),
                     destination: destination_path.to_string(),
+                    owner: None,
+                    mode: None,
+                    preserve_timestamps: false,
                 })
             );
         }
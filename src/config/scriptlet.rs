@@ -1,11 +1,591 @@
-use serde::{Deserialize, Serialize};
+use super::image::typ;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-#[cfg_attr(test, derive(PartialEq))]
+/// Permission bits written as an octal string, e.g. `"0755"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode(pub u32);
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04o}", self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Mode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mode = String::deserialize(deserializer)?;
+        match u32::from_str_radix(&mode, 8) {
+            Ok(bits) if bits <= 0o7777 => Ok(Mode(bits)),
+            _ => Err(de::Error::invalid_value(
+                de::Unexpected::Str(&mode),
+                &"octal permission bits such as \"0755\"",
+            )),
+        }
+    }
+}
+
+impl Serialize for Mode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Paths or glob patterns relative to the configuration directory, written as
+/// one string or a list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sources(pub Vec<String>);
+
+impl From<String> for Sources {
+    fn from(source: String) -> Self {
+        Self(vec![source])
+    }
+}
+
+impl fmt::Display for Sources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.join(" "))
+    }
+}
+
+impl<'de> Deserialize<'de> for Sources {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Form {
+            One(String),
+            Many(Vec<String>),
+        }
+        match Form::deserialize(deserializer)? {
+            Form::One(source) => Ok(Self(vec![source])),
+            Form::Many(sources) if !sources.is_empty() => Ok(Self(sources)),
+            Form::Many(_) => Err(de::Error::invalid_length(0, &"at least one source")),
+        }
+    }
+}
+
+impl Serialize for Sources {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.0.as_slice() {
+            [source] => serializer.serialize_str(source),
+            sources => sources.serialize(serializer),
+        }
+    }
+}
+
+/// Command of a container, either a list of arguments run as is or a string
+/// run by the shell set with `Scriptlet::Shell`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Command {
+    Exec(Vec<String>),
+    Shell(String),
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exec(arguments) => write!(
+                f,
+                "{}",
+                serde_json::to_string(arguments).map_err(|_| fmt::Error)?
+            ),
+            Self::Shell(command) => write!(f, "{}", command),
+        }
+    }
+}
+
+/// Deserializes a command to run, which needs at least a program in its
+/// exec form.
+fn runnable_command<'de, D>(deserializer: D) -> Result<Command, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Command::deserialize(deserializer)? {
+        Command::Exec(arguments) if arguments.is_empty() => {
+            Err(de::Error::invalid_length(0, &"a program to run"))
+        }
+        command => Ok(command),
+    }
+}
+
+/// Network of the commands run while building.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    /// the network of the host
+    Host,
+    /// only a loopback interface
+    None,
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Host => write!(f, "host"),
+            Self::None => write!(f, "none"),
+        }
+    }
+}
+
+/// Directory of secrets mounted as files without a `target`, as with Docker.
+pub const SECRETS_DIRECTORY: &str = "/run/secrets";
+
+/// Secret made available to the command of a `run` scriptlet, as a read-only
+/// file at `target`, as the environment variable `env`, or both. Its value is
+/// never stored in the layer, the history or the cache.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct SecretMount {
+    pub id: String,
+    /// `SECRETS_DIRECTORY/<id>` unless set, or unless only `env` is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+}
+
+impl SecretMount {
+    /// Path of the file holding the secret, if any.
+    pub fn file(&self) -> Option<String> {
+        match (&self.target, &self.env) {
+            (Some(target), _) => Some(target.clone()),
+            (None, Some(_)) => None,
+            (None, None) => Some(format!("{}/{}", SECRETS_DIRECTORY, self.id)),
+        }
+    }
+}
+
+impl fmt::Display for SecretMount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "id={}", self.id)?;
+        if let Some(target) = &self.target {
+            write!(f, ",target={}", target)?;
+        }
+        if let Some(env) = &self.env {
+            write!(f, ",env={}", env)?;
+        }
+        Ok(())
+    }
+}
+
+/// How `run` scriptlets mounting the same cache at the same time share it, as
+/// with Docker.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sharing {
+    /// every command uses it at once
+    Shared,
+    /// commands wait for the one using it
+    Locked,
+    /// commands use another instance of it while one is in use
+    Private,
+}
+
+impl fmt::Display for Sharing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Shared => write!(f, "shared"),
+            Self::Locked => write!(f, "locked"),
+            Self::Private => write!(f, "private"),
+        }
+    }
+}
+
+/// Directory of the storage kept across builds, such as the cache of a package
+/// manager, mounted at `target` for the command of a `run` scriptlet. Its
+/// content is never stored in the layer nor part of the cache key.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct CacheMount {
+    /// `target` unless set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub target: String,
+    /// `shared` unless set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sharing: Option<Sharing>,
+}
+
+impl CacheMount {
+    pub fn id(&self) -> &str {
+        self.id.as_deref().unwrap_or(&self.target)
+    }
+}
+
+impl fmt::Display for CacheMount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(id) = &self.id {
+            write!(f, "id={},", id)?;
+        }
+        write!(f, "target={}", self.target)?;
+        if let Some(sharing) = &self.sharing {
+            write!(f, ",sharing={}", sharing)?;
+        }
+        Ok(())
+    }
+}
+
+/// Exposed port written as `<number>[/<protocol>]`, e.g. `8080` or `"53/udp"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Port {
+    pub number: u16,
+    pub protocol: String,
+}
+
+const PROTOCOLS: &[&str] = &["tcp", "udp", "sctp"];
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.number, self.protocol)
+    }
+}
+
+impl<'de> Deserialize<'de> for Port {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u16),
+            String(String),
+        }
+
+        let port = match Raw::deserialize(deserializer)? {
+            Raw::Number(number) => {
+                return Ok(Port {
+                    number,
+                    protocol: PROTOCOLS[0].to_string(),
+                })
+            }
+            Raw::String(port) => port,
+        };
+        let (number, protocol) = port.split_once('/').unwrap_or((&port, PROTOCOLS[0]));
+        match number.parse() {
+            Ok(number) if PROTOCOLS.contains(&protocol) => Ok(Port {
+                number,
+                protocol: protocol.to_string(),
+            }),
+            _ => Err(de::Error::invalid_value(
+                de::Unexpected::Str(&port),
+                &"a port such as \"8080\" or \"53/udp\"",
+            )),
+        }
+    }
+}
+
+impl Serialize for Port {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Units of `Duration`, longest first.
+const DURATION_UNITS: &[(&str, u64)] = &[
+    ("h", 3_600_000_000_000),
+    ("m", 60_000_000_000),
+    ("s", 1_000_000_000),
+    ("ms", 1_000_000),
+    ("us", 1_000),
+    ("ns", 1),
+];
+
+/// Duration in nanoseconds written as numbers with units, e.g. `"1m30s"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Duration(pub u64);
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            return write!(f, "0s");
+        }
+        let mut rest = self.0;
+        for (unit, nanoseconds) in DURATION_UNITS {
+            if rest >= *nanoseconds {
+                write!(f, "{}{}", rest / nanoseconds, unit)?;
+                rest %= nanoseconds;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses `duration`, or returns `None` if it is not a valid one.
+fn parse_duration(duration: &str) -> Option<u64> {
+    let mut rest = duration;
+    let mut total: u64 = 0;
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let number: u64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let unit_length = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (_, nanoseconds) = DURATION_UNITS
+            .iter()
+            .find(|(unit, _)| *unit == &rest[..unit_length])?;
+        total = total.checked_add(number.checked_mul(*nanoseconds)?)?;
+        rest = &rest[unit_length..];
+    }
+    Some(total)
+}
+
+impl<'de> Deserialize<'de> for Duration {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let duration = String::deserialize(deserializer)?;
+        parse_duration(&duration).map(Duration).ok_or_else(|| {
+            de::Error::invalid_value(
+                de::Unexpected::Str(&duration),
+                &"a duration such as \"30s\" or \"1m30s\"",
+            )
+        })
+    }
+}
+
+impl Serialize for Duration {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 #[serde(tag = "type")]
 pub enum Scriptlet {
+    /// Copies the files, directories or glob matches of `source`, except
+    /// those excluded by the ignore file, to `destination`, a directory if
+    /// there are several
     #[serde(rename = "add")]
-    Add { source: String, destination: String },
+    Add {
+        source: Sources,
+        destination: String,
+        /// `user[:group]`, by name in the built image or by number
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        owner: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<Mode>,
+        /// keeps the modification times of the sources instead of the time of the copy
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        preserve_timestamps: bool,
+    },
+    /// Copies `source` of the root filesystem of another image
+    #[serde(rename = "copy_from")]
+    CopyFrom {
+        from: typ::ImageType,
+        source: String,
+        destination: String,
+        /// `user[:group]`, by name in the built image or by number
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        owner: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<Mode>,
+    },
+    /// Runs `command` in the root filesystem with the environment, working
+    /// directory and user of the image configuration
+    #[serde(rename = "run")]
+    Run {
+        #[serde(deserialize_with = "runnable_command")]
+        command: Command,
+        /// `host` unless set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        network: Option<Network>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        secrets: Vec<SecretMount>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        caches: Vec<CacheMount>,
+    },
+    /// Sets environment variables of containers
+    #[serde(rename = "env")]
+    Env { variables: BTreeMap<String, String> },
+    /// Sets the working directory of containers, relative to the previous one
+    #[serde(rename = "workdir")]
+    Workdir { path: String },
+    /// Sets the `user[:group]` running containers
+    #[serde(rename = "user")]
+    User { user: String },
+    #[serde(rename = "entrypoint")]
+    Entrypoint { command: Command },
+    /// Sets the default command, or the default arguments of the entrypoint
+    #[serde(rename = "cmd")]
+    Cmd { command: Command },
+    #[serde(rename = "label")]
+    Label { labels: BTreeMap<String, String> },
+    #[serde(rename = "expose")]
+    Expose { ports: Vec<Port> },
+    #[serde(rename = "volume")]
+    Volume { paths: Vec<String> },
+    #[serde(rename = "stop_signal")]
+    StopSignal { signal: String },
+    /// Sets how to check whether containers are healthy, or disables the check
+    /// of the base image without `command`
+    #[serde(rename = "healthcheck")]
+    Healthcheck {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        command: Option<Command>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interval: Option<Duration>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout: Option<Duration>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        start_period: Option<Duration>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retries: Option<u32>,
+    },
+    /// Sets the shell running the string form of later commands
+    #[serde(rename = "shell")]
+    Shell { command: Vec<String> },
+}
+
+impl Scriptlet {
+    /// Whether this changes the root filesystem, rather than only the image configuration.
+    pub fn changes_filesystem(&self) -> bool {
+        matches!(
+            self,
+            Self::Add { .. } | Self::CopyFrom { .. } | Self::Run { .. }
+        )
+    }
+}
+
+/// Writes `map` as space separated `key=value` pairs.
+fn write_pairs(f: &mut fmt::Formatter<'_>, map: &BTreeMap<String, String>) -> fmt::Result {
+    let pairs = map
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>();
+    write!(f, "{}", pairs.join(" "))
+}
+
+/// One line description, as logged while building and recorded in the image history.
+impl fmt::Display for Scriptlet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Add {
+                source,
+                destination,
+                owner,
+                mode,
+                preserve_timestamps,
+            } => {
+                write!(f, "add")?;
+                if let Some(owner) = owner {
+                    write!(f, " --owner {}", owner)?;
+                }
+                if let Some(mode) = mode {
+                    write!(f, " --mode {}", mode)?;
+                }
+                if *preserve_timestamps {
+                    write!(f, " --preserve-timestamps")?;
+                }
+                write!(f, " {} {}", source, destination)
+            }
+            Self::CopyFrom {
+                from,
+                source,
+                destination,
+                owner,
+                mode,
+            } => {
+                write!(f, "copy_from {}", from)?;
+                if let Some(owner) = owner {
+                    write!(f, " --owner {}", owner)?;
+                }
+                if let Some(mode) = mode {
+                    write!(f, " --mode {}", mode)?;
+                }
+                write!(f, " {} {}", source, destination)
+            }
+            Self::Run {
+                command,
+                network,
+                secrets,
+                caches,
+            } => {
+                write!(f, "run")?;
+                if let Some(network) = network {
+                    write!(f, " --network {}", network)?;
+                }
+                for secret in secrets {
+                    write!(f, " --secret {}", secret)?;
+                }
+                for cache in caches {
+                    write!(f, " --cache {}", cache)?;
+                }
+                write!(f, " {}", command)
+            }
+            Self::Env { variables } => {
+                write!(f, "env ")?;
+                write_pairs(f, variables)
+            }
+            Self::Workdir { path } => write!(f, "workdir {}", path),
+            Self::User { user } => write!(f, "user {}", user),
+            Self::Entrypoint { command } => write!(f, "entrypoint {}", command),
+            Self::Cmd { command } => write!(f, "cmd {}", command),
+            Self::Label { labels } => {
+                write!(f, "label ")?;
+                write_pairs(f, labels)
+            }
+            Self::Expose { ports } => {
+                write!(f, "expose")?;
+                for port in ports {
+                    write!(f, " {}", port)?;
+                }
+                Ok(())
+            }
+            Self::Volume { paths } => write!(f, "volume {}", paths.join(" ")),
+            Self::StopSignal { signal } => write!(f, "stop_signal {}", signal),
+            Self::Healthcheck {
+                command,
+                interval,
+                timeout,
+                start_period,
+                retries,
+            } => {
+                write!(f, "healthcheck")?;
+                let options = [
+                    ("interval", interval),
+                    ("timeout", timeout),
+                    ("start-period", start_period),
+                ];
+                for (name, duration) in options {
+                    if let Some(duration) = duration {
+                        write!(f, " --{} {}", name, duration)?;
+                    }
+                }
+                if let Some(retries) = retries {
+                    write!(f, " --retries {}", retries)?;
+                }
+                match command {
+                    Some(command) => write!(f, " {}", command),
+                    None => write!(f, " none"),
+                }
+            }
+            Self::Shell { command } => write!(f, "shell {}", Command::Exec(command.clone())),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -13,13 +593,29 @@ mod tests {
     mod add {
         use super::super::Scriptlet;
 
+        #[test]
+        fn displayable() {
+            let scriptlet = Scriptlet::Add {
+                source: "source".to_string().into(),
+                destination: "destination".to_string(),
+                owner: None,
+                mode: None,
+                preserve_timestamps: false,
+            };
+
+            assert_eq!(scriptlet.to_string(), "add source destination");
+        }
+
         #[test]
         fn serializable() {
             let source = "source";
             let destination = "destination";
             let scriptlet = Scriptlet::Add {
-                source: source.to_string(),
+                source: source.to_string().into(),
                 destination: destination.to_string(),
+                owner: None,
+                mode: None,
+                preserve_timestamps: false,
             };
             let expected_string = format!(
                 r#"---
@@ -52,13 +648,33 @@ destination: {}
                 assert!(deserialized_scriptlet.is_ok());
                 assert_eq!(
                     Scriptlet::Add {
-                        source: source.to_string(),
+                        source: source.to_string().into(),
                         destination: destination.to_string(),
+                        owner: None,
+                        mode: None,
+                        preserve_timestamps: false,
                     },
                     deserialized_scriptlet.unwrap(),
                 );
             }
 
+            #[test]
+            fn deserializable_sources() {
+                let deserialized_scriptlet = serde_yaml::from_str::<Scriptlet>(
+                    "{ type: add, source: [Cargo.toml, 'src/*.rs'], destination: /app/ }",
+                )
+                .unwrap();
+
+                assert_eq!(
+                    deserialized_scriptlet.to_string(),
+                    "add Cargo.toml src/*.rs /app/"
+                );
+                assert!(serde_yaml::from_str::<Scriptlet>(
+                    "{ type: add, source: [], destination: /app/ }"
+                )
+                .is_err());
+            }
+
             #[test]
             fn undeserializable() {
                 let original_string = "{{ type: add, source: source }}";
@@ -68,4 +684,176 @@ destination: {}
             }
         }
     }
+
+    mod copy_from {
+        use super::super::{Mode, Scriptlet};
+        use crate::config::image::typ;
+
+        fn scriptlet() -> Scriptlet {
+            Scriptlet::CopyFrom {
+                from: typ::ImageType::Local {
+                    name: "builder".to_string(),
+                    tag: None,
+                },
+                source: "/usr/local/bin/app".to_string(),
+                destination: "/usr/bin/app".to_string(),
+                owner: Some("app:app".to_string()),
+                mode: Some(Mode(0o750)),
+            }
+        }
+
+        #[test]
+        fn displayable() {
+            assert_eq!(
+                scriptlet().to_string(),
+                "copy_from builder --owner app:app --mode 0750 /usr/local/bin/app /usr/bin/app"
+            );
+        }
+
+        #[test]
+        fn deserializable() {
+            let original_string = r#"
+                type: copy_from
+                from:
+                  local: builder
+                source: /usr/local/bin/app
+                destination: /usr/bin/app
+                owner: app:app
+                mode: "0750"
+                "#;
+            let deserialized_scriptlet = serde_yaml::from_str::<Scriptlet>(original_string);
+
+            assert!(deserialized_scriptlet.is_ok());
+            assert_eq!(scriptlet(), deserialized_scriptlet.unwrap());
+        }
+
+        #[test]
+        fn round_trip() {
+            let serialized_string = serde_yaml::to_string(&scriptlet()).unwrap();
+
+            assert_eq!(
+                serde_yaml::from_str::<Scriptlet>(&serialized_string).unwrap(),
+                scriptlet()
+            );
+        }
+
+        #[test]
+        fn undeserializable_mode() {
+            let original_string = r#"
+                type: copy_from
+                from:
+                  name: golang
+                source: /go/bin/app
+                destination: /app
+                mode: "rwx"
+                "#;
+
+            assert!(serde_yaml::from_str::<Scriptlet>(original_string).is_err());
+        }
+    }
+
+    mod configuration {
+        use super::super::{Command, Duration, Port, Scriptlet};
+
+        #[test]
+        fn displayable() {
+            let scriptlets = serde_yaml::from_str::<Vec<Scriptlet>>(
+                r#"
+                - { type: run, command: make install }
+                - { type: run, command: [make, test], network: none }
+                - type: run
+                  command: npm ci
+                  secrets: [{ id: npmrc, target: /root/.npmrc }, { id: token, env: TOKEN }]
+                - type: run
+                  command: apt-get install -y gcc
+                  caches: [{ target: /var/cache/apt, sharing: locked }, { id: lists, target: /var/lib/apt/lists }]
+                - { type: env, variables: { PATH: /usr/bin, LANG: C.UTF-8 } }
+                - { type: workdir, path: /app }
+                - { type: entrypoint, command: [app, --verbose] }
+                - { type: cmd, command: exec app }
+                - { type: expose, ports: [8080, 53/udp] }
+                - { type: healthcheck, command: [check], interval: 1m30s, retries: 3 }
+                - { type: healthcheck }
+                "#,
+            )
+            .unwrap();
+
+            assert_eq!(
+                scriptlets
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>(),
+                vec![
+                    "run make install",
+                    r#"run --network none ["make","test"]"#,
+                    "run --secret id=npmrc,target=/root/.npmrc --secret id=token,env=TOKEN npm ci",
+                    "run --cache target=/var/cache/apt,sharing=locked --cache id=lists,target=/var/lib/apt/lists apt-get install -y gcc",
+                    "env LANG=C.UTF-8 PATH=/usr/bin",
+                    "workdir /app",
+                    r#"entrypoint ["app","--verbose"]"#,
+                    "cmd exec app",
+                    "expose 8080/tcp 53/udp",
+                    r#"healthcheck --interval 1m30s --retries 3 ["check"]"#,
+                    "healthcheck none",
+                ]
+            );
+        }
+
+        #[test]
+        fn deserialize_command_forms() {
+            assert_eq!(
+                serde_yaml::from_str::<Command>("[app, run]").unwrap(),
+                Command::Exec(vec!["app".to_string(), "run".to_string()])
+            );
+            assert_eq!(
+                serde_yaml::from_str::<Command>("app run").unwrap(),
+                Command::Shell("app run".to_string())
+            );
+        }
+
+        #[test]
+        fn cannot_deserialize_empty_run_command() {
+            assert!(serde_yaml::from_str::<Scriptlet>("{ type: run, command: [] }").is_err());
+            assert!(serde_yaml::from_str::<Scriptlet>("{ type: entrypoint, command: [] }").is_ok());
+        }
+
+        #[test]
+        fn deserialize_ports() {
+            assert_eq!(
+                serde_yaml::from_str::<Vec<Port>>("[80, \"443\", 53/udp]").unwrap(),
+                vec![
+                    Port {
+                        number: 80,
+                        protocol: "tcp".to_string(),
+                    },
+                    Port {
+                        number: 443,
+                        protocol: "tcp".to_string(),
+                    },
+                    Port {
+                        number: 53,
+                        protocol: "udp".to_string(),
+                    },
+                ]
+            );
+            assert!(serde_yaml::from_str::<Port>("80/http").is_err());
+            assert!(serde_yaml::from_str::<Port>("70000").is_err());
+        }
+
+        #[test]
+        fn deserialize_durations() {
+            assert_eq!(
+                serde_yaml::from_str::<Duration>("1m30s").unwrap(),
+                Duration(90_000_000_000)
+            );
+            assert_eq!(
+                serde_yaml::from_str::<Duration>("500ms").unwrap(),
+                Duration(500_000_000)
+            );
+            assert_eq!(Duration(3_600_500_000_000).to_string(), "1h500ms");
+            for invalid in ["", "30", "s", "1d", "-1s"] {
+                assert!(serde_yaml::from_str::<Duration>(&format!("{:?}", invalid)).is_err());
+            }
+        }
+    }
 }
@@ -0,0 +1,162 @@
+use crate::result;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path;
+
+#[derive(Debug)]
+struct InvalidSecretError {
+    value: String,
+}
+
+impl fmt::Display for InvalidSecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid secret {:?}: expected id=<id>,src=<path> or id=<id>,env=<variable>",
+            self.value
+        )
+    }
+}
+
+impl error::Error for InvalidSecretError {}
+
+#[derive(Debug)]
+struct UnreadableSecretError {
+    id: String,
+    source: Source,
+    reason: String,
+}
+
+impl fmt::Display for UnreadableSecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot read secret {} from {}: {}",
+            self.id, self.source, self.reason
+        )
+    }
+}
+
+impl error::Error for UnreadableSecretError {}
+
+/// Where the value of a build secret is read from, only while running the
+/// commands mounting it.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// file, relative to the configuration directory
+    File(path::PathBuf),
+    /// environment variable of amethyst
+    Env(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "file {}", path.display()),
+            Self::Env(variable) => write!(f, "environment variable {}", variable),
+        }
+    }
+}
+
+impl Source {
+    /// Value of the secret `id`.
+    pub fn read(&self, id: &str) -> result::Result<Vec<u8>> {
+        let value = match self {
+            Self::File(path) => fs::read(path).map_err(|err| err.to_string()),
+            Self::Env(variable) => env::var_os(variable)
+                .map(|value| value.into_encoded_bytes())
+                .ok_or_else(|| "not set".to_string()),
+        };
+        value.map_err(|reason| {
+            Box::new(UnreadableSecretError {
+                id: id.to_string(),
+                source: self.clone(),
+                reason,
+            }) as result::BoxedError
+        })
+    }
+}
+
+/// Parses `id=<id>,src=<path>` or `id=<id>,env=<variable>`, as `--secret` of
+/// `docker build`, with paths relative to the current directory.
+pub fn parse(value: &str) -> result::Result<(String, Source)> {
+    let invalid = || {
+        Box::new(InvalidSecretError {
+            value: value.to_string(),
+        })
+    };
+    let (mut id, mut source) = (None, None);
+    for field in value.split(',') {
+        match field.split_once('=').ok_or_else(invalid)? {
+            ("id", value) if !value.is_empty() && id.is_none() => id = Some(value.to_string()),
+            ("src" | "source", path) if !path.is_empty() && source.is_none() => {
+                source = Some(Source::File(env::current_dir()?.join(path)))
+            }
+            ("env", variable) if !variable.is_empty() && source.is_none() => {
+                source = Some(Source::Env(variable.to_string()))
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok((id.ok_or_else(invalid)?, source.ok_or_else(invalid)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Source};
+    use std::env;
+
+    #[test]
+    fn parse_file_secret() {
+        assert_eq!(
+            parse("id=npmrc,src=secrets/npmrc").unwrap(),
+            (
+                "npmrc".to_string(),
+                Source::File(env::current_dir().unwrap().join("secrets/npmrc"))
+            )
+        );
+        assert_eq!(
+            parse("id=npmrc,src=/run/npmrc").unwrap().1,
+            Source::File("/run/npmrc".into())
+        );
+    }
+
+    #[test]
+    fn parse_environment_secret() {
+        assert_eq!(
+            parse("env=TOKEN,id=token").unwrap(),
+            ("token".to_string(), Source::Env("TOKEN".to_string()))
+        );
+    }
+
+    #[test]
+    fn cannot_parse_invalid_secret() {
+        for value in [
+            "",
+            "id=token",
+            "env=TOKEN",
+            "id=token,env=",
+            "id=token,env=TOKEN,src=token",
+            "id=token,env=TOKEN,mode=0400",
+            "token",
+        ] {
+            assert!(parse(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn deserialize_sources() {
+        assert_eq!(
+            serde_yaml::from_str::<Source>("file: ./npmrc").unwrap(),
+            Source::File("./npmrc".into())
+        );
+        assert_eq!(
+            serde_yaml::from_str::<Source>("env: TOKEN").unwrap(),
+            Source::Env("TOKEN".to_string())
+        );
+    }
+}
@@ -0,0 +1,88 @@
+/// Whether `text` matches `pattern`, in which `*` stands for any characters
+/// and `?` for any single character.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // position after the last `*` and the text position it was tried at
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, tried)) => {
+                    p = star;
+                    t = tried + 1;
+                    backtrack = Some((star, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Whether the `/` separated `path` matches `pattern`, in which every
+/// component is matched as with `matches` except `**`, which stands for any
+/// number of components.
+pub fn matches_path(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.split('/').collect::<Vec<_>>();
+    let path = path.split('/').collect::<Vec<_>>();
+    matches_components(&pattern, &path)
+}
+
+fn matches_components(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| matches_components(rest, &path[skip..])),
+        Some((component, rest)) => match path.split_first() {
+            Some((first, path)) => matches(component, first) && matches_components(rest, path),
+            None => false,
+        },
+    }
+}
+
+/// Whether `component` of a path pattern has wildcards.
+pub fn has_wildcards(component: &str) -> bool {
+    component.contains(['*', '?'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{matches, matches_path};
+
+    #[test]
+    fn match_literally() {
+        assert!(matches("app", "app"));
+        assert!(!matches("app", "apps"));
+        assert!(!matches("apps", "app"));
+    }
+
+    #[test]
+    fn match_wildcards() {
+        assert!(matches("app-*", "app-runtime"));
+        assert!(matches("*:1.?", "runtime:1.0"));
+        assert!(matches("*-*-test", "app-runtime-test"));
+        assert!(matches("*", ""));
+        assert!(!matches("app-?", "app-runtime"));
+        assert!(!matches("*-test", "app-runtime"));
+    }
+
+    #[test]
+    fn match_paths() {
+        assert!(matches_path("src/*.rs", "src/main.rs"));
+        assert!(!matches_path("src/*.rs", "src/build/copy.rs"));
+        assert!(matches_path("src/**/*.rs", "src/main.rs"));
+        assert!(matches_path("src/**/*.rs", "src/build/copy.rs"));
+        assert!(matches_path("**/target", "target"));
+        assert!(matches_path("**/target", "crates/app/target"));
+        assert!(!matches_path("*", "src/main.rs"));
+    }
+}
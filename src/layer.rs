@@ -0,0 +1,51 @@
+pub mod archive;
+pub mod compression;
+pub mod diff;
+pub mod unpack;
+
+use crate::result;
+use crate::storage;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path;
+
+/// uid and gid of the paths of a root filesystem, relative to it, which are
+/// not owned by root, as seen from the containers using it.
+pub type Owners = BTreeMap<path::PathBuf, (u32, u32)>;
+
+/// Layer written to the blob storage.
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub media_type: String,
+    /// digest of the compressed blob
+    pub digest: String,
+    /// digest of the uncompressed tar archive
+    pub diff_id: String,
+    pub size: u64,
+}
+
+/// Opens the stored layer `digest` as an uncompressed tar stream.
+pub fn open(digest: &str) -> result::Result<Box<dyn io::Read>> {
+    Ok(compression::decoder(fs::File::open(storage::blob_path(
+        digest,
+    ))?)?)
+}
+
+/// Writes a layer with `write` into the temporary storage and moves it into the blob storage.
+pub fn store<F>(options: &archive::Options, write: F) -> result::Result<Layer>
+where
+    F: FnOnce(&mut archive::Archive<fs::File>) -> result::Result<()>,
+{
+    let temporary_storage = storage::temporary_storage();
+    fs::create_dir_all(&temporary_storage)?;
+    let (file, path) = tempfile::Builder::new()
+        .prefix("layer-")
+        .tempfile_in(temporary_storage)?
+        .into_parts();
+    let mut archive = archive::Archive::new(file, options)?;
+    write(&mut archive)?;
+    let (_, layer) = archive.finish()?;
+    storage::import_blob(&path, &layer.digest)?;
+    Ok(layer)
+}
@@ -0,0 +1,207 @@
+use super::compression;
+use crate::result;
+use crate::storage::digest::DigestWriter;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path;
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Options {
+    pub compression: compression::Compression,
+    /// later modification times are replaced by this, as of `SOURCE_DATE_EPOCH`
+    pub source_date_epoch: Option<u64>,
+}
+
+/// Compressed layer archive, hashed while it is written.
+///
+/// Entries are written with uid and gid 0 unless overridden, no user or group names, no access
+/// or change times and only permission bits, so that the same tree always
+/// results in the same archive once modification times are clamped.
+pub struct Archive<W>
+where
+    W: io::Write,
+{
+    builder: tar::Builder<DigestWriter<compression::Encoder<DigestWriter<W>>>>,
+    options: Options,
+    /// paths already in the archive, relative to the root
+    appended: BTreeSet<path::PathBuf>,
+    owners: super::Owners,
+}
+
+impl<W> Archive<W>
+where
+    W: io::Write,
+{
+    pub fn new(writer: W, options: &Options) -> result::Result<Self> {
+        let encoder = options.compression.encoder(DigestWriter::new(writer))?;
+        Ok(Self {
+            builder: tar::Builder::new(DigestWriter::new(encoder)),
+            options: options.clone(),
+            appended: BTreeSet::new(),
+            owners: super::Owners::new(),
+        })
+    }
+
+    /// Writes `name`, a path relative to the root, with `uid` and `gid` once appended.
+    pub fn set_owner(&mut self, name: path::PathBuf, uid: u32, gid: u32) {
+        self.owners.insert(name, (uid, gid));
+    }
+
+    /// Adds `path`, a path inside `root`, to the archive along with its parent directories.
+    pub fn append(&mut self, root: &path::Path, path: &path::Path) -> result::Result<()> {
+        let relative_path = path.strip_prefix(root)?;
+        let mut name = path::PathBuf::new();
+        for component in relative_path.components() {
+            name.push(component);
+            if self.appended.insert(name.clone()) {
+                self.append_entry(&root.join(&name), &name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a whiteout deleting `path`, a path inside `root`, from lower layers.
+    pub fn append_whiteout(&mut self, root: &path::Path, path: &path::Path) -> result::Result<()> {
+        let relative_path = path.strip_prefix(root)?;
+        let file_name = match relative_path.file_name() {
+            Some(file_name) => file_name.to_string_lossy(),
+            None => return Ok(()),
+        };
+        let parent = relative_path
+            .parent()
+            .unwrap_or_else(|| path::Path::new(""));
+        if !parent.as_os_str().is_empty() {
+            self.append(root, &root.join(parent))?;
+        }
+        let name = parent.join(format!("{}{}", super::unpack::WHITEOUT_PREFIX, file_name));
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_size(0);
+        self.builder.append_data(&mut header, name, io::empty())?;
+        Ok(())
+    }
+
+    fn mtime(&self, metadata: &fs::Metadata) -> u64 {
+        let mtime = metadata.mtime().max(0) as u64;
+        match self.options.source_date_epoch {
+            Some(source_date_epoch) => mtime.min(source_date_epoch),
+            None => mtime,
+        }
+    }
+
+    fn append_entry(&mut self, path: &path::Path, name: &path::Path) -> result::Result<()> {
+        let metadata = fs::symlink_metadata(path)?;
+        let file_type = metadata.file_type();
+        let entry_type = if file_type.is_dir() {
+            tar::EntryType::Directory
+        } else if file_type.is_symlink() {
+            tar::EntryType::Symlink
+        } else if file_type.is_file() {
+            tar::EntryType::Regular
+        } else if file_type.is_fifo() {
+            tar::EntryType::Fifo
+        } else {
+            // devices and sockets are never extracted, see `unpack`
+            return Ok(());
+        };
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(metadata.mode() & super::unpack::PERMISSION_MASK);
+        let (uid, gid) = self.owners.get(name).copied().unwrap_or((0, 0));
+        header.set_uid(uid.into());
+        header.set_gid(gid.into());
+        header.set_mtime(self.mtime(&metadata));
+        header.set_size(0);
+        match entry_type {
+            tar::EntryType::Symlink => {
+                let target = fs::read_link(path)?;
+                self.builder.append_link(&mut header, name, target)?;
+            }
+            tar::EntryType::Regular => {
+                header.set_size(metadata.len());
+                self.builder
+                    .append_data(&mut header, name, fs::File::open(path)?)?;
+            }
+            _ => self.builder.append_data(&mut header, name, io::empty())?,
+        }
+        Ok(())
+    }
+
+    /// Completes the archive, returning the writer and the digests of what was written.
+    pub fn finish(self) -> result::Result<(W, super::Layer)> {
+        let (encoder, diff_id, _) = self.builder.into_inner()?.finish();
+        let (mut writer, digest, size) = encoder.finish()?.finish();
+        writer.flush()?;
+        let layer = super::Layer {
+            media_type: self.options.compression.media_type().to_string(),
+            digest,
+            diff_id,
+            size,
+        };
+        Ok((writer, layer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Archive, Options};
+    use std::fs;
+
+    fn archive(root: &std::path::Path, source_date_epoch: Option<u64>) -> (Vec<u8>, String) {
+        let options = Options {
+            source_date_epoch,
+            ..Default::default()
+        };
+        let mut archive = Archive::new(vec![], &options).unwrap();
+        archive
+            .append(root, &root.join("etc").join("hostname"))
+            .unwrap();
+        let (compressed, layer) = archive.finish().unwrap();
+        (compressed, layer.digest)
+    }
+
+    #[test]
+    fn clamp_modification_times() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("etc")).unwrap();
+        fs::write(root.path().join("etc").join("hostname"), "amethyst").unwrap();
+        let (compressed, _) = archive(root.path(), Some(1_000));
+
+        let mut entries = tar::Archive::new(flate2::read::GzDecoder::new(compressed.as_slice()));
+        for entry in entries.entries().unwrap() {
+            let entry = entry.unwrap();
+            assert_eq!(entry.header().mtime().unwrap(), 1_000);
+            assert_eq!(entry.header().uid().unwrap(), 0);
+            assert_eq!(entry.header().username().unwrap(), Some(""));
+        }
+    }
+
+    #[test]
+    fn same_tree_same_digest() {
+        let roots = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        for root in &roots {
+            fs::create_dir(root.path().join("etc")).unwrap();
+            fs::write(root.path().join("etc").join("hostname"), "amethyst").unwrap();
+        }
+        filetime::set_file_mtime(
+            roots[1].path().join("etc").join("hostname"),
+            filetime::FileTime::from_unix_time(0, 0),
+        )
+        .unwrap();
+        filetime::set_file_mtime(
+            roots[0].path().join("etc").join("hostname"),
+            filetime::FileTime::from_unix_time(0, 0),
+        )
+        .unwrap();
+
+        assert_eq!(
+            archive(roots[0].path(), Some(1_000)).1,
+            archive(roots[1].path(), Some(1_000)).1
+        );
+    }
+}
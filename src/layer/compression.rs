@@ -0,0 +1,293 @@
+use crate::oci::media_type;
+use crate::result;
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+
+pub const DEFAULT_GZIP_LEVEL: u32 = 6;
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// `operating_system` of the gzip header for an unknown system, so that it does not vary by host.
+const GZIP_UNKNOWN_OPERATING_SYSTEM: u8 = 255;
+/// Window of the long distance matching mode, 128 MiB as `zstd --long`.
+const ZSTD_LONG_WINDOW_LOG: u32 = 27;
+/// Largest window accepted when decompressing, 2 GiB as `zstd --long=31`.
+const ZSTD_MAXIMUM_WINDOW_LOG: u32 = 31;
+
+fn default_gzip_level() -> u32 {
+    DEFAULT_GZIP_LEVEL
+}
+
+fn default_zstd_level() -> i32 {
+    DEFAULT_ZSTD_LEVEL
+}
+
+/// Codec of produced layers, e.g. `{type: zstd, level: 19, long: true}` in `amethyst.yaml`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Compression {
+    Gzip {
+        #[serde(default = "default_gzip_level")]
+        level: u32,
+    },
+    Zstd {
+        #[serde(default = "default_zstd_level")]
+        level: i32,
+        /// long distance matching, which needs a larger window to decompress
+        #[serde(default)]
+        long: bool,
+    },
+    None,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::Gzip {
+            level: DEFAULT_GZIP_LEVEL,
+        }
+    }
+}
+
+impl Compression {
+    /// Media type of layers compressed this way, Docker ones where they exist.
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            Self::Gzip { .. } => media_type::DOCKER_LAYER_GZIP,
+            Self::Zstd { .. } => media_type::OCI_LAYER_ZSTD,
+            Self::None => media_type::DOCKER_LAYER,
+        }
+    }
+
+    pub fn encoder<W>(&self, writer: W) -> io::Result<Encoder<W>>
+    where
+        W: io::Write,
+    {
+        match *self {
+            Self::Gzip { level } => Ok(Encoder::Gzip(
+                flate2::GzBuilder::new()
+                    .mtime(0)
+                    .operating_system(GZIP_UNKNOWN_OPERATING_SYSTEM)
+                    .write(writer, flate2::Compression::new(level)),
+            )),
+            Self::Zstd { level, long } => {
+                let mut encoder = zstd::stream::write::Encoder::new(writer, level)?;
+                if long {
+                    encoder.long_distance_matching(true)?;
+                    encoder.window_log(ZSTD_LONG_WINDOW_LOG)?;
+                }
+                Ok(Encoder::Zstd(encoder))
+            }
+            Self::None => Ok(Encoder::None(writer)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ParseCompressionError {
+    value: String,
+}
+
+impl fmt::Display for ParseCompressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot parse {:?}: expected gzip[:level], zstd[:level][:long] or none",
+            self.value
+        )
+    }
+}
+
+impl error::Error for ParseCompressionError {}
+
+/// Parses `gzip`, `gzip:9`, `zstd`, `zstd:19`, `zstd:19:long`, `zstd:long` or `none`.
+pub fn parse(value: &str) -> result::Result<Compression> {
+    let error = || -> result::BoxedError {
+        Box::new(ParseCompressionError {
+            value: value.to_string(),
+        })
+    };
+    let mut parts = value.trim().split(':');
+    let compression = match parts.next() {
+        Some("gzip") => {
+            let level = match parts.next() {
+                Some(level) => level.parse().map_err(|_| error())?,
+                None => DEFAULT_GZIP_LEVEL,
+            };
+            if level > 9 {
+                return Err(error());
+            }
+            Compression::Gzip { level }
+        }
+        Some("zstd") => {
+            let options = parts.by_ref().collect::<Vec<_>>();
+            let (level, long) = match options.as_slice() {
+                [] => (DEFAULT_ZSTD_LEVEL, false),
+                ["long"] => (DEFAULT_ZSTD_LEVEL, true),
+                [level] => (level.parse().map_err(|_| error())?, false),
+                [level, "long"] => (level.parse().map_err(|_| error())?, true),
+                _ => return Err(error()),
+            };
+            if !zstd::compression_level_range().contains(&level) {
+                return Err(error());
+            }
+            Compression::Zstd { level, long }
+        }
+        Some("none") => Compression::None,
+        _ => return Err(error()),
+    };
+    if parts.next().is_some() {
+        return Err(error());
+    }
+    Ok(compression)
+}
+
+pub enum Encoder<W>
+where
+    W: io::Write,
+{
+    Gzip(flate2::write::GzEncoder<W>),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+    None(W),
+}
+
+impl<W> Encoder<W>
+where
+    W: io::Write,
+{
+    /// Writes the end of the stream and returns the inner writer.
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Gzip(encoder) => encoder.finish(),
+            Self::Zstd(encoder) => encoder.finish(),
+            Self::None(writer) => Ok(writer),
+        }
+    }
+}
+
+impl<W> io::Write for Encoder<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Gzip(encoder) => encoder.write(buf),
+            Self::Zstd(encoder) => encoder.write(buf),
+            Self::None(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Gzip(encoder) => encoder.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+            Self::None(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Decompresses `reader` according to its magic bytes, whatever the media type claims.
+pub fn decoder<R>(reader: R) -> io::Result<Box<dyn io::Read>>
+where
+    R: io::Read + 'static,
+{
+    let mut reader = io::BufReader::new(reader);
+    let magic = reader.fill_buf()?;
+    if magic.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(flate2::read::GzDecoder::new(reader)))
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        let mut decoder = zstd::stream::read::Decoder::with_buffer(reader)?;
+        decoder.window_log_max(ZSTD_MAXIMUM_WINDOW_LOG)?;
+        Ok(Box::new(decoder))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decoder, parse, Compression};
+    use std::io::{Read, Write};
+
+    #[test]
+    fn parse_compression() {
+        assert_eq!(parse("gzip").unwrap(), Compression::Gzip { level: 6 });
+        assert_eq!(parse("gzip:9").unwrap(), Compression::Gzip { level: 9 });
+        assert_eq!(
+            parse("zstd").unwrap(),
+            Compression::Zstd {
+                level: 3,
+                long: false
+            }
+        );
+        assert_eq!(
+            parse("zstd:19:long").unwrap(),
+            Compression::Zstd {
+                level: 19,
+                long: true
+            }
+        );
+        assert_eq!(
+            parse("zstd:long").unwrap(),
+            Compression::Zstd {
+                level: 3,
+                long: true
+            }
+        );
+        assert_eq!(parse("none").unwrap(), Compression::None);
+    }
+
+    #[test]
+    fn cannot_parse_invalid_compression() {
+        for value in [
+            "",
+            "xz",
+            "gzip:10",
+            "gzip:fast",
+            "zstd:1000",
+            "none:1",
+            "zstd:3:long:1",
+        ] {
+            assert!(parse(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn deserialize_compression() {
+        assert_eq!(
+            serde_yaml::from_str::<Compression>("{type: zstd, long: true}").unwrap(),
+            Compression::Zstd {
+                level: 3,
+                long: true
+            }
+        );
+        assert_eq!(
+            serde_yaml::from_str::<Compression>("type: none").unwrap(),
+            Compression::None
+        );
+    }
+
+    #[test]
+    fn decode_every_compression() {
+        let content = b"amethyst".repeat(1024);
+        for compression in [
+            Compression::default(),
+            parse("zstd:19:long").unwrap(),
+            Compression::None,
+        ] {
+            let mut encoder = compression.encoder(vec![]).unwrap();
+            encoder.write_all(&content).unwrap();
+            let compressed = encoder.finish().unwrap();
+
+            let mut decompressed = vec![];
+            decoder(std::io::Cursor::new(compressed))
+                .unwrap()
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, content, "{:?}", compression);
+        }
+    }
+}
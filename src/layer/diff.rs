@@ -0,0 +1,307 @@
+use super::archive;
+use crate::result;
+use crate::storage::digest;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path;
+
+/// What a path holds, compared in place of modification times.
+#[derive(Debug, PartialEq, Eq)]
+enum Content {
+    Directory,
+    File { size: u64, digest: String },
+    SymbolicLink { target: path::PathBuf },
+    Other { device: u64 },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Entry {
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    content: Content,
+}
+
+/// Metadata and content hash of every path of a directory tree.
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    /// keyed by the path relative to the root
+    entries: BTreeMap<path::PathBuf, Entry>,
+}
+
+impl Snapshot {
+    /// Takes a snapshot of the tree at `root`, with the `owners` its files
+    /// have in containers rather than on the host.
+    pub fn take(root: &path::Path, owners: &super::Owners) -> result::Result<Self> {
+        let mut snapshot = Self::default();
+        snapshot.walk(root, path::Path::new(""), owners)?;
+        Ok(snapshot)
+    }
+
+    fn walk(
+        &mut self,
+        root: &path::Path,
+        directory: &path::Path,
+        owners: &super::Owners,
+    ) -> result::Result<()> {
+        for entry in fs::read_dir(root.join(directory))? {
+            let entry = entry?;
+            let name = directory.join(entry.file_name());
+            let metadata = entry.metadata()?;
+            let file_type = metadata.file_type();
+            let content = if file_type.is_dir() {
+                Content::Directory
+            } else if file_type.is_symlink() {
+                Content::SymbolicLink {
+                    target: fs::read_link(entry.path())?,
+                }
+            } else if file_type.is_file() {
+                Content::File {
+                    size: metadata.len(),
+                    digest: digest::sha256_reader(&mut fs::File::open(entry.path())?)?,
+                }
+            } else {
+                Content::Other {
+                    device: metadata.rdev(),
+                }
+            };
+            let (uid, gid) = owners.get(&name).copied().unwrap_or((0, 0));
+            self.entries.insert(
+                name.clone(),
+                Entry {
+                    mode: metadata.mode(),
+                    uid,
+                    gid,
+                    content,
+                },
+            );
+            if file_type.is_dir() {
+                self.walk(root, &name, owners)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Changes turning one snapshot into another, relative to their roots.
+#[derive(Debug, Default, PartialEq)]
+pub struct Diff {
+    /// paths added or modified, parents first
+    pub changed: Vec<path::PathBuf>,
+    /// paths deleted, without those inside a deleted directory
+    pub deleted: Vec<path::PathBuf>,
+}
+
+impl Diff {
+    pub fn between(lower: &Snapshot, upper: &Snapshot) -> Self {
+        let mut diff = Self::default();
+        for (path, entry) in &upper.entries {
+            if lower.entries.get(path) != Some(entry) {
+                diff.changed.push(path.clone());
+            }
+        }
+        let mut deleted = BTreeSet::new();
+        for path in lower.entries.keys() {
+            if upper.entries.contains_key(path) {
+                continue;
+            }
+            // gone along with a deleted directory, or with one replaced by another type
+            let covered = path.ancestors().skip(1).any(|ancestor| {
+                deleted.contains(ancestor)
+                    || matches!(
+                        upper.entries.get(ancestor),
+                        Some(entry) if entry.content != Content::Directory
+                    )
+            });
+            if !covered {
+                deleted.insert(path.as_path());
+                diff.deleted.push(path.clone());
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.deleted.is_empty()
+    }
+
+    /// Archives the changed paths of the tree at `root` and whiteouts for the
+    /// deleted ones, in path order.
+    pub fn write<W>(
+        &self,
+        root: &path::Path,
+        archive: &mut archive::Archive<W>,
+    ) -> result::Result<()>
+    where
+        W: io::Write,
+    {
+        let mut paths = self
+            .deleted
+            .iter()
+            .map(|path| (path, true))
+            .chain(self.changed.iter().map(|path| (path, false)))
+            .collect::<Vec<_>>();
+        paths.sort();
+        for (path, deleted) in paths {
+            if deleted {
+                archive.append_whiteout(root, &root.join(path))?;
+            } else {
+                archive.append(root, &root.join(path))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Diff, Snapshot};
+    use crate::layer::{archive, Owners};
+    use std::fs;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    use std::path;
+
+    fn diff(lower: &path::Path, upper: &path::Path) -> Diff {
+        Diff::between(
+            &Snapshot::take(lower, &Owners::new()).unwrap(),
+            &Snapshot::take(upper, &Owners::new()).unwrap(),
+        )
+    }
+
+    fn paths(paths: &[&str]) -> Vec<path::PathBuf> {
+        paths.iter().map(path::PathBuf::from).collect()
+    }
+
+    /// Two identical trees: `etc/hostname`, `etc/hosts`, `opt/amethyst/file` and `bin -> usr/bin`.
+    fn trees() -> (tempfile::TempDir, tempfile::TempDir) {
+        let trees = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        for root in [trees.0.path(), trees.1.path()] {
+            fs::create_dir_all(root.join("etc")).unwrap();
+            fs::create_dir_all(root.join("opt").join("amethyst")).unwrap();
+            fs::write(root.join("etc").join("hostname"), "amethyst").unwrap();
+            fs::write(root.join("etc").join("hosts"), "localhost").unwrap();
+            fs::write(root.join("opt").join("amethyst").join("file"), "file").unwrap();
+            symlink("usr/bin", root.join("bin")).unwrap();
+        }
+        trees
+    }
+
+    #[test]
+    fn identical_trees() {
+        let (lower, upper) = trees();
+
+        assert!(diff(lower.path(), upper.path()).is_empty());
+    }
+
+    #[test]
+    fn ignore_modification_time() {
+        let (lower, upper) = trees();
+        let hostname = upper.path().join("etc").join("hostname");
+        filetime::set_file_mtime(&hostname, filetime::FileTime::from_unix_time(0, 0)).unwrap();
+
+        assert!(diff(lower.path(), upper.path()).is_empty());
+    }
+
+    #[test]
+    fn detect_changes() {
+        let (lower, upper) = trees();
+        let upper = upper.path();
+        fs::write(upper.join("etc").join("hostname"), "changed").unwrap();
+        fs::set_permissions(
+            upper.join("etc").join("hosts"),
+            fs::Permissions::from_mode(0o600),
+        )
+        .unwrap();
+        fs::remove_file(upper.join("bin")).unwrap();
+        symlink("usr/sbin", upper.join("bin")).unwrap();
+        fs::write(upper.join("new"), "new").unwrap();
+
+        assert_eq!(
+            diff(lower.path(), upper),
+            Diff {
+                changed: paths(&["bin", "etc/hostname", "etc/hosts", "new"]),
+                deleted: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn detect_owner_changes() {
+        let (lower, upper) = trees();
+        let lower = Snapshot::take(lower.path(), &Owners::new()).unwrap();
+        let owners = Owners::from([(path::PathBuf::from("etc/hosts"), (1000, 1000))]);
+        let upper = Snapshot::take(upper.path(), &owners).unwrap();
+
+        assert_eq!(
+            Diff::between(&lower, &upper),
+            Diff {
+                changed: paths(&["etc/hosts"]),
+                deleted: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn detect_deletions() {
+        let (lower, upper) = trees();
+        let upper = upper.path();
+        fs::remove_file(upper.join("etc").join("hosts")).unwrap();
+        fs::remove_dir_all(upper.join("opt")).unwrap();
+
+        assert_eq!(
+            diff(lower.path(), upper),
+            Diff {
+                changed: vec![],
+                deleted: paths(&["etc/hosts", "opt"]),
+            }
+        );
+    }
+
+    #[test]
+    fn replace_directory_with_file() {
+        let (lower, upper) = trees();
+        let upper = upper.path();
+        fs::remove_dir_all(upper.join("opt").join("amethyst")).unwrap();
+        fs::write(upper.join("opt").join("amethyst"), "file").unwrap();
+
+        assert_eq!(
+            diff(lower.path(), upper),
+            Diff {
+                changed: paths(&["opt/amethyst"]),
+                deleted: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn write_layer() {
+        let (lower, upper) = trees();
+        fs::write(upper.path().join("etc").join("hostname"), "changed").unwrap();
+        fs::remove_dir_all(upper.path().join("opt")).unwrap();
+        let mut archive = archive::Archive::new(vec![], &Default::default()).unwrap();
+        diff(lower.path(), upper.path())
+            .write(upper.path(), &mut archive)
+            .unwrap();
+        let (compressed, layer) = archive.finish().unwrap();
+
+        let mut uncompressed = vec![];
+        std::io::Read::read_to_end(
+            &mut flate2::read::GzDecoder::new(compressed.as_slice()),
+            &mut uncompressed,
+        )
+        .unwrap();
+        assert_eq!(layer.digest, crate::storage::digest::sha256(&compressed));
+        assert_eq!(layer.diff_id, crate::storage::digest::sha256(&uncompressed));
+        assert_eq!(layer.size, compressed.len() as u64);
+
+        let mut entries = tar::Archive::new(uncompressed.as_slice());
+        let entries = entries
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().to_path_buf())
+            .collect::<Vec<_>>();
+        assert_eq!(entries, paths(&["etc", "etc/hostname", ".wh.opt"]));
+    }
+}
@@ -0,0 +1,711 @@
+use crate::result;
+use crate::rootfs;
+use std::cmp;
+use std::collections::HashSet;
+use std::error;
+use std::ffi;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path;
+
+/// Prefix of an entry deleting the file of the same name from lower layers.
+pub const WHITEOUT_PREFIX: &str = ".wh.";
+/// Entry hiding every file of lower layers in its directory.
+pub const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// Permission bits kept when extracting, without the file type.
+pub const PERMISSION_MASK: u32 = 0o7777;
+/// Larger entries are refused rather than filling the disk, 64 GiB.
+pub const MAXIMUM_ENTRY_SIZE: u64 = 64 << 30;
+
+#[derive(Debug)]
+pub enum Refusal {
+    ParentDirectory,
+    InvalidWhiteout,
+    HardLinkOutsideRoot { link_name: String },
+    TooLarge { size: u64 },
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ParentDirectory => write!(f, "path contains `..`"),
+            Self::InvalidWhiteout => write!(f, "whiteout does not name a file"),
+            Self::HardLinkOutsideRoot { link_name } => write!(
+                f,
+                "hard link target {} is not a file in the root filesystem",
+                link_name
+            ),
+            Self::TooLarge { size } => write!(
+                f,
+                "size {} exceeds the limit of {} bytes",
+                size, MAXIMUM_ENTRY_SIZE
+            ),
+        }
+    }
+}
+
+/// Layer entry which would write outside the root filesystem or is otherwise unsafe to extract.
+#[derive(Debug)]
+pub struct UnsafeEntryError {
+    pub entry: String,
+    pub refusal: Refusal,
+}
+
+impl fmt::Display for UnsafeEntryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "refused layer entry {}: {}", self.entry, self.refusal)
+    }
+}
+
+impl error::Error for UnsafeEntryError {}
+
+fn refuse(entry: &path::Path, refusal: Refusal) -> result::BoxedError {
+    Box::new(UnsafeEntryError {
+        entry: entry.to_string_lossy().to_string(),
+        refusal,
+    })
+}
+
+/// Extracts the stored layer `digest` on top of the root filesystem at `root`,
+/// whose `owners` are updated.
+pub fn apply(root: &path::Path, digest: &str, owners: &mut super::Owners) -> result::Result<()> {
+    apply_archive(root, super::open(digest)?, owners)
+}
+
+/// `path` relative to the root, or `None` for the root itself. Absolute paths
+/// are taken relative to the root, as GNU tar does.
+fn normalize(path: &path::Path) -> result::Result<Option<path::PathBuf>> {
+    let mut normalized = path::PathBuf::new();
+    for component in path.components() {
+        match component {
+            path::Component::Normal(component) => normalized.push(component),
+            path::Component::RootDir | path::Component::CurDir => {}
+            path::Component::ParentDir | path::Component::Prefix(_) => {
+                return Err(refuse(path, Refusal::ParentDirectory))
+            }
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(normalized))
+    }
+}
+
+fn remove(path: &path::Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Removes everything in `directory` which was not extracted from the current layer.
+fn clear_lower(directory: &path::Path, extracted: &HashSet<path::PathBuf>) -> io::Result<()> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if !extracted.contains(&path) {
+            remove(&path)?;
+        } else if fs::symlink_metadata(&path)?.is_dir() {
+            clear_lower(&path, extracted)?;
+        }
+    }
+    Ok(())
+}
+
+/// Owner id of an entry, root if its header has none or an invalid one.
+fn id(id: io::Result<u64>) -> u32 {
+    id.ok().and_then(|id| u32::try_from(id).ok()).unwrap_or(0)
+}
+
+struct Unpacker<'a> {
+    root: &'a path::Path,
+    owners: &'a mut super::Owners,
+    /// host paths extracted from the layer so far
+    extracted: HashSet<path::PathBuf>,
+    /// directory permissions and modification times, applied once their content is in place
+    directories: Vec<(path::PathBuf, u32, filetime::FileTime)>,
+}
+
+impl<'a> Unpacker<'a> {
+    /// Host path of the entry `name`, whose last component is never followed.
+    fn target(&self, name: &path::Path) -> result::Result<path::PathBuf> {
+        let parent = match name.parent() {
+            Some(parent) => rootfs::resolve(self.root, parent, true)?,
+            None => self.root.to_path_buf(),
+        };
+        match name.file_name() {
+            Some(file_name) => Ok(parent.join(file_name)),
+            None => Ok(parent),
+        }
+    }
+
+    fn whiteout(&mut self, name: &path::Path, file_name: &str) -> result::Result<()> {
+        let directory = self.target(name)?;
+        let directory = directory.parent().unwrap_or(self.root);
+        if file_name == OPAQUE_WHITEOUT {
+            clear_lower(directory, &self.extracted)?;
+            return Ok(());
+        }
+        let hidden = &file_name[WHITEOUT_PREFIX.len()..];
+        if hidden.is_empty() || hidden == "." || hidden == ".." {
+            return Err(refuse(name, Refusal::InvalidWhiteout));
+        }
+        remove(&directory.join(hidden))?;
+        Ok(())
+    }
+
+    fn extract<R>(&mut self, entry: &mut tar::Entry<R>, name: &path::Path) -> result::Result<()>
+    where
+        R: io::Read,
+    {
+        let header = entry.header();
+        let entry_type = header.entry_type();
+        let mode = header.mode()? & PERMISSION_MASK;
+        let owner = (id(header.uid()), id(header.gid()));
+        let mtime = filetime::FileTime::from_unix_time(header.mtime()? as i64, 0);
+        // with the holes of sparse files, unlike the size in the header
+        let size = entry.size();
+        if size > MAXIMUM_ENTRY_SIZE {
+            return Err(refuse(name, Refusal::TooLarge { size }));
+        }
+        let target = self.target(name)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let existing = fs::symlink_metadata(&target).ok();
+        if let Some(existing) = existing {
+            if !(existing.is_dir() && entry_type.is_dir()) {
+                remove(&target)?;
+            }
+        }
+
+        match entry_type {
+            tar::EntryType::Directory => {
+                if !target.is_dir() {
+                    fs::create_dir(&target)?;
+                }
+                self.directories.push((target.clone(), mode, mtime));
+            }
+            // sparse files are read with their holes filled with zeros
+            tar::EntryType::Regular | tar::EntryType::Continuous | tar::EntryType::GNUSparse => {
+                let mut file = fs::File::create(&target)?;
+                io::copy(entry, &mut file)?;
+                file.set_permissions(fs::Permissions::from_mode(mode))?;
+                filetime::set_file_mtime(&target, mtime)?;
+            }
+            tar::EntryType::Symlink => {
+                if let Some(link_name) = entry.link_name()? {
+                    symlink(link_name, &target)?;
+                    filetime::set_symlink_file_times(&target, mtime, mtime)?;
+                }
+            }
+            tar::EntryType::Link => {
+                if let Some(link_name) = entry.link_name()? {
+                    let outside = || {
+                        refuse(
+                            name,
+                            Refusal::HardLinkOutsideRoot {
+                                link_name: link_name.to_string_lossy().to_string(),
+                            },
+                        )
+                    };
+                    let source = match normalize(&link_name).map_err(|_| outside())? {
+                        Some(link_name) => self.target(&link_name)?,
+                        None => return Err(outside()),
+                    };
+                    match fs::symlink_metadata(&source) {
+                        Ok(metadata) if !metadata.is_dir() => fs::hard_link(source, &target)?,
+                        _ => return Err(outside()),
+                    }
+                }
+            }
+            tar::EntryType::Fifo => {
+                let path = ffi::CString::new(target.as_os_str().as_bytes())?;
+                if unsafe { libc::mkfifo(path.as_ptr(), mode) } == -1 {
+                    return Err(Box::new(io::Error::last_os_error()));
+                }
+                // without the umask applied by `mkfifo`
+                fs::set_permissions(&target, fs::Permissions::from_mode(mode))?;
+                // which, unlike `set_file_mtime`, does not open the fifo and wait for a writer
+                filetime::set_symlink_file_times(&target, mtime, mtime)?;
+            }
+            // devices cannot be created without privileges and have no place
+            // in an image root filesystem anyway
+            _ => return Ok(()),
+        }
+        // not applied to the files, which the build may not be allowed to
+        // own, but recorded as seen from containers
+        let name = target.strip_prefix(self.root)?.to_path_buf();
+        if owner == (0, 0) {
+            self.owners.remove(&name);
+        } else {
+            self.owners.insert(name, owner);
+        }
+        self.extracted.insert(target);
+        Ok(())
+    }
+}
+
+/// Extracts the uncompressed tar stream `layer` on top of the root filesystem
+/// at `root`, whose `owners` are updated.
+pub fn apply_archive<R>(
+    root: &path::Path,
+    layer: R,
+    owners: &mut super::Owners,
+) -> result::Result<()>
+where
+    R: io::Read,
+{
+    let mut unpacker = Unpacker {
+        root,
+        owners: &mut *owners,
+        extracted: HashSet::new(),
+        directories: vec![],
+    };
+    let mut archive = tar::Archive::new(layer);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = match normalize(&entry.path()?)? {
+            Some(name) => name,
+            None => continue,
+        };
+        let file_name = name
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if file_name.starts_with(WHITEOUT_PREFIX) {
+            unpacker.whiteout(&name, &file_name)?;
+        } else {
+            unpacker.extract(&mut entry, &name)?;
+        }
+    }
+    // deepest first, so that a read-only directory is completed before its parent
+    unpacker
+        .directories
+        .sort_by_key(|(directory, _, _)| cmp::Reverse(directory.components().count()));
+    for (directory, mode, mtime) in unpacker.directories {
+        // replaced by a later entry, possibly with a symbolic link leading out of the root
+        match fs::symlink_metadata(&directory) {
+            Ok(metadata) if metadata.is_dir() => {}
+            _ => continue,
+        }
+        fs::set_permissions(&directory, fs::Permissions::from_mode(mode))?;
+        filetime::set_file_mtime(&directory, mtime)?;
+    }
+    // removed by whiteouts or replaced along with a directory
+    owners.retain(|name, _| fs::symlink_metadata(root.join(name)).is_ok());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::apply_archive;
+    use crate::layer::Owners;
+    use std::fs;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::path;
+
+    fn header(entry_type: tar::EntryType, mode: u32, size: u64) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(mode);
+        header.set_size(size);
+        header.set_mtime(1_650_000_000);
+        header
+    }
+
+    struct Layer {
+        builder: tar::Builder<Vec<u8>>,
+    }
+
+    impl Layer {
+        fn new() -> Self {
+            Self {
+                builder: tar::Builder::new(vec![]),
+            }
+        }
+
+        fn directory(mut self, path: &str, mode: u32) -> Self {
+            let mut header = header(tar::EntryType::Directory, mode, 0);
+            self.builder
+                .append_data(&mut header, path, &[][..])
+                .unwrap();
+            self
+        }
+
+        fn file(mut self, path: &str, content: &str) -> Self {
+            let mut header = header(tar::EntryType::Regular, 0o644, content.len() as u64);
+            self.builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+            self
+        }
+
+        fn link(mut self, entry_type: tar::EntryType, path: &str, target: &str) -> Self {
+            let mut header = header(entry_type, 0o777, 0);
+            self.builder.append_link(&mut header, path, target).unwrap();
+            self
+        }
+
+        /// Appends an entry without the path checks of `tar::Builder`.
+        fn raw(
+            mut self,
+            entry_type: tar::EntryType,
+            path: &str,
+            link_name: &str,
+            size: u64,
+        ) -> Self {
+            let mut header = header(entry_type, 0o644, size);
+            let gnu = header.as_gnu_mut().unwrap();
+            gnu.name[..path.len()].copy_from_slice(path.as_bytes());
+            gnu.linkname[..link_name.len()].copy_from_slice(link_name.as_bytes());
+            header.set_cksum();
+            self.builder.append(&header, &[][..]).unwrap();
+            self
+        }
+
+        /// Appends a GNU sparse file of `size` bytes whose only data is
+        /// `content` at `offset`.
+        fn sparse(mut self, path: &str, offset: u64, content: &str, size: u64) -> Self {
+            let mut header = header(tar::EntryType::GNUSparse, 0o644, content.len() as u64);
+            let gnu = header.as_gnu_mut().unwrap();
+            gnu.sparse[0].set_offset(offset);
+            gnu.sparse[0].set_length(content.len() as u64);
+            // the trailing hole, as written by GNU tar
+            gnu.sparse[1].set_offset(size);
+            gnu.sparse[1].set_length(0);
+            gnu.set_real_size(size);
+            self.builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+            self
+        }
+
+        fn fifo(mut self, path: &str) -> Self {
+            let mut header = header(tar::EntryType::Fifo, 0o620, 0);
+            self.builder
+                .append_data(&mut header, path, &[][..])
+                .unwrap();
+            self
+        }
+
+        fn device(mut self, path: &str) -> Self {
+            let mut header = header(tar::EntryType::Char, 0o666, 0);
+            self.builder
+                .append_data(&mut header, path, &[][..])
+                .unwrap();
+            self
+        }
+
+        fn owned(mut self, path: &str, uid: u64, gid: u64) -> Self {
+            let mut header = header(tar::EntryType::Directory, 0o700, 0);
+            header.set_uid(uid);
+            header.set_gid(gid);
+            self.builder
+                .append_data(&mut header, path, &[][..])
+                .unwrap();
+            self
+        }
+
+        fn try_apply(self, root: &path::Path) -> crate::result::Result<Owners> {
+            let layer = self.builder.into_inner().unwrap();
+            let mut owners = Owners::new();
+            apply_archive(root, layer.as_slice(), &mut owners)?;
+            Ok(owners)
+        }
+
+        fn apply(self, root: &path::Path) -> Owners {
+            self.try_apply(root).unwrap()
+        }
+    }
+
+    #[test]
+    fn extract_files() {
+        let root = tempfile::tempdir().unwrap();
+        Layer::new()
+            .directory("./etc/", 0o755)
+            .file("./etc/hostname", "amethyst")
+            .link(tar::EntryType::Symlink, "./etc/name", "hostname")
+            .link(tar::EntryType::Link, "./etc/hardlink", "./etc/hostname")
+            .device("./dev/null")
+            .apply(root.path());
+
+        let etc = root.path().join("etc");
+        assert_eq!(
+            fs::read_to_string(etc.join("hostname")).unwrap(),
+            "amethyst"
+        );
+        assert_eq!(
+            fs::read_link(etc.join("name")).unwrap(),
+            path::Path::new("hostname")
+        );
+        assert_eq!(
+            fs::read_to_string(etc.join("hardlink")).unwrap(),
+            "amethyst"
+        );
+        assert!(!root.path().join("dev").join("null").exists());
+    }
+
+    #[test]
+    fn extract_sparse_file() {
+        let root = tempfile::tempdir().unwrap();
+        Layer::new()
+            .sparse("var/log/lastlog", 4, "data", 12)
+            .apply(root.path());
+
+        assert_eq!(
+            fs::read(root.path().join("var").join("log").join("lastlog")).unwrap(),
+            b"\0\0\0\0data\0\0\0\0"
+        );
+    }
+
+    #[test]
+    fn create_fifo() {
+        let root = tempfile::tempdir().unwrap();
+        Layer::new().fifo("run/initctl").apply(root.path());
+
+        let metadata = fs::symlink_metadata(root.path().join("run").join("initctl")).unwrap();
+        assert!(metadata.file_type().is_fifo());
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o620);
+    }
+
+    #[test]
+    fn apply_permissions_after_content() {
+        let root = tempfile::tempdir().unwrap();
+        Layer::new()
+            .directory("readonly/", 0o555)
+            .file("readonly/file", "content")
+            .apply(root.path());
+
+        let readonly = root.path().join("readonly");
+        assert_eq!(
+            fs::metadata(&readonly).unwrap().permissions().mode() & 0o7777,
+            0o555
+        );
+        assert!(readonly.join("file").exists());
+        fs::set_permissions(&readonly, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn record_owners() {
+        let root = tempfile::tempdir().unwrap();
+        let owners = Layer::new()
+            .directory("home", 0o755)
+            .owned("home/app", 1000, 100)
+            .owned("home/old", 1001, 100)
+            .file("home/.wh.old", "")
+            .apply(root.path());
+
+        assert_eq!(
+            owners.into_iter().collect::<Vec<_>>(),
+            vec![(path::PathBuf::from("home/app"), (1000, 100))]
+        );
+    }
+
+    #[test]
+    fn remove_whiteout_file() {
+        let root = tempfile::tempdir().unwrap();
+        Layer::new()
+            .file("etc/hostname", "amethyst")
+            .file("etc/hosts", "localhost")
+            .apply(root.path());
+        Layer::new().file("etc/.wh.hostname", "").apply(root.path());
+
+        assert!(!root.path().join("etc").join("hostname").exists());
+        assert!(root.path().join("etc").join("hosts").exists());
+    }
+
+    #[test]
+    fn hide_lower_files_of_opaque_directory() {
+        let root = tempfile::tempdir().unwrap();
+        Layer::new()
+            .file("etc/hostname", "amethyst")
+            .file("etc/hosts", "localhost")
+            .apply(root.path());
+        Layer::new()
+            .directory("etc/", 0o755)
+            .file("etc/hosts", "127.0.0.1")
+            .file("etc/.wh..wh..opq", "")
+            .apply(root.path());
+
+        let etc = root.path().join("etc");
+        assert!(!etc.join("hostname").exists());
+        assert_eq!(fs::read_to_string(etc.join("hosts")).unwrap(), "127.0.0.1");
+    }
+
+    #[test]
+    fn replace_directory_with_file() {
+        let root = tempfile::tempdir().unwrap();
+        Layer::new()
+            .file("opt/amethyst/file", "content")
+            .apply(root.path());
+        Layer::new().file("opt/amethyst", "file").apply(root.path());
+
+        assert_eq!(
+            fs::read_to_string(root.path().join("opt").join("amethyst")).unwrap(),
+            "file"
+        );
+    }
+
+    #[test]
+    fn extract_through_symbolic_link_in_root() {
+        let root = tempfile::tempdir().unwrap();
+        Layer::new()
+            .directory("usr/lib/", 0o755)
+            .link(tar::EntryType::Symlink, "lib", "/usr/lib")
+            .file("lib/libc.so", "libc")
+            .apply(root.path());
+
+        assert!(root.path().join("usr").join("lib").join("libc.so").exists());
+    }
+
+    mod unsafe_entry {
+        use super::super::UnsafeEntryError;
+        use super::Layer;
+        use std::fs;
+
+        fn refused(result: crate::result::Result<crate::layer::Owners>, entry: &str) -> bool {
+            match result {
+                Err(err) => err
+                    .downcast_ref::<UnsafeEntryError>()
+                    .map(|err| err.entry == entry)
+                    .unwrap_or(false),
+                Ok(_) => false,
+            }
+        }
+
+        #[test]
+        fn refuse_parent_directory() {
+            let sandbox = tempfile::tempdir().unwrap();
+            let root = sandbox.path().join("root");
+            fs::create_dir(&root).unwrap();
+            let result = Layer::new()
+                .raw(tar::EntryType::Regular, "../escaped", "", 0)
+                .try_apply(&root);
+
+            assert!(refused(result, "../escaped"));
+            assert!(!sandbox.path().join("escaped").exists());
+        }
+
+        #[test]
+        fn extract_absolute_path_into_root() {
+            let root = tempfile::tempdir().unwrap();
+            Layer::new()
+                .raw(tar::EntryType::Regular, "/etc/hostname", "", 0)
+                .apply(root.path());
+
+            assert!(root.path().join("etc").join("hostname").is_file());
+        }
+
+        #[test]
+        fn write_through_escaping_symbolic_link_stays_in_root() {
+            let sandbox = tempfile::tempdir().unwrap();
+            let root = sandbox.path().join("root");
+            fs::create_dir(&root).unwrap();
+            let outside = sandbox.path().join("outside");
+            fs::create_dir(&outside).unwrap();
+            Layer::new()
+                .link(
+                    tar::EntryType::Symlink,
+                    "absolute",
+                    outside.to_str().unwrap(),
+                )
+                .link(tar::EntryType::Symlink, "relative", "../../outside")
+                .file("absolute/file", "content")
+                .file("relative/file", "content")
+                .apply(&root);
+
+            assert!(fs::read_dir(&outside).unwrap().next().is_none());
+            assert!(root
+                .join(outside.strip_prefix("/").unwrap())
+                .join("file")
+                .is_file());
+            assert!(root.join("outside").join("file").is_file());
+        }
+
+        #[test]
+        fn directory_replaced_by_symbolic_link_keeps_outside_permissions() {
+            let sandbox = tempfile::tempdir().unwrap();
+            let root = sandbox.path().join("root");
+            fs::create_dir(&root).unwrap();
+            let outside = sandbox.path().join("outside");
+            fs::create_dir(&outside).unwrap();
+            let mode = |path: &std::path::Path| {
+                std::os::unix::fs::PermissionsExt::mode(&fs::metadata(path).unwrap().permissions())
+            };
+            let original_mode = mode(&outside);
+            Layer::new()
+                .directory("directory/", 0o700)
+                .link(
+                    tar::EntryType::Symlink,
+                    "directory",
+                    outside.to_str().unwrap(),
+                )
+                .apply(&root);
+
+            assert_eq!(mode(&outside), original_mode);
+        }
+
+        #[test]
+        fn refuse_hard_link_outside_root() {
+            let sandbox = tempfile::tempdir().unwrap();
+            let root = sandbox.path().join("root");
+            fs::create_dir(&root).unwrap();
+            fs::write(sandbox.path().join("secret"), "secret").unwrap();
+
+            let result = Layer::new()
+                .raw(tar::EntryType::Link, "secret", "../secret", 0)
+                .try_apply(&root);
+            assert!(refused(result, "secret"));
+
+            let result = Layer::new()
+                .raw(
+                    tar::EntryType::Link,
+                    "secret",
+                    sandbox.path().join("secret").to_str().unwrap(),
+                    0,
+                )
+                .try_apply(&root);
+            assert!(refused(result, "secret"));
+            assert!(!root.join("secret").exists());
+        }
+
+        #[test]
+        fn refuse_invalid_whiteout() {
+            let root = tempfile::tempdir().unwrap();
+            fs::create_dir(root.path().join("etc")).unwrap();
+            let result = Layer::new()
+                .raw(tar::EntryType::Regular, "etc/.wh...", "", 0)
+                .try_apply(root.path());
+
+            assert!(refused(result, "etc/.wh..."));
+            assert!(root.path().join("etc").exists());
+        }
+
+        #[test]
+        fn refuse_absurd_size() {
+            let root = tempfile::tempdir().unwrap();
+            let result = Layer::new()
+                .raw(
+                    tar::EntryType::Regular,
+                    "huge",
+                    "",
+                    super::super::MAXIMUM_ENTRY_SIZE + 1,
+                )
+                .try_apply(root.path());
+
+            assert!(refused(result, "huge"));
+        }
+    }
+}
@@ -1,19 +1,116 @@
+mod build;
 mod command;
 mod config;
+mod glob;
 mod http;
+mod layer;
+mod oci;
 mod registry;
 mod result;
+mod rootfs;
 mod storage;
+mod unit;
 
 use std::process;
+use std::time;
 
 use clap::{Parser, Subcommand};
-use registry::docker_hub;
-use registry::registry::Registry;
+use storage::cache::remote;
+use storage::{fsck, gc};
 
 #[derive(Subcommand)]
 enum Commands {
-    Build { config_directory: String },
+    /// Build the images configured in `amethyst.yaml` and store them
+    Build {
+        config_directory: String,
+        /// Build every image twice and fail unless both builds are identical
+        #[clap(long)]
+        verify_reproducible: bool,
+        /// Codec of layers of images not configuring one: gzip[:level], zstd[:level][:long] or none
+        #[clap(long, default_value = "gzip", parse(try_from_str = layer::compression::parse))]
+        compression: layer::compression::Compression,
+        /// Build every step instead of reusing layers cached by earlier builds
+        #[clap(long)]
+        no_cache: bool,
+        /// Tell which inputs of each rebuilt step changed since it was last cached
+        #[clap(long)]
+        explain_cache: bool,
+        /// Import build cache from a directory or a http(s)://<host>/<repository>[:<tag>] registry
+        #[clap(long, parse(try_from_str = remote::parse))]
+        cache_from: Option<remote::Location>,
+        /// Export the build cache of the built images to a directory or a registry
+        #[clap(long, parse(try_from_str = remote::parse))]
+        cache_to: Option<remote::Location>,
+        /// Number of images built at the same time, one per CPU by default
+        #[clap(long, short)]
+        jobs: Option<usize>,
+        /// Keep building images not based on a failed one instead of stopping at the first failure
+        #[clap(long)]
+        keep_going: bool,
+        /// Build only images matching this name, name:tag or glob, with the images they are based on
+        #[clap(long, visible_alias = "target", value_name = "PATTERN")]
+        image: Vec<String>,
+        /// List the images which would be built, in build order, instead of building them
+        #[clap(long)]
+        list_targets: bool,
+        /// Secret for run scriptlets: id=<id>,src=<path> or id=<id>,env=<variable>
+        #[clap(long, value_name = "SECRET", parse(try_from_str = config::secret::parse))]
+        secret: Vec<(String, config::secret::Source)>,
+    },
+    /// Remove blobs which are not reachable from any tagged image
+    Prune {
+        /// Only report what would be removed
+        #[clap(long)]
+        dry_run: bool,
+        /// Only remove unreachable blobs older than this (e.g. 24h, 7d)
+        #[clap(long, parse(try_from_str = unit::parse_duration))]
+        older_than: Option<time::Duration>,
+        /// Evict least recently pulled images until the storage fits this size (e.g. 10G)
+        #[clap(long, parse(try_from_str = unit::parse_size))]
+        max_size: Option<u64>,
+    },
+    /// Verify the digests of stored blobs and the references of tagged images
+    Fsck {
+        /// Move corrupted blobs into the quarantine directory
+        #[clap(long)]
+        quarantine: bool,
+        /// Fetch missing or corrupted blobs of pulled images from the registry again
+        #[clap(long)]
+        repair: bool,
+    },
+    /// List stored images
+    Images {
+        /// Show full digests
+        #[clap(long)]
+        no_trunc: bool,
+    },
+    /// Show the manifest and configuration of stored images
+    Inspect {
+        /// `repository[:tag]`, digest or digest prefix
+        #[clap(required = true)]
+        images: Vec<String>,
+    },
+    /// Untag stored images by tag or digest, leaving their blobs to `prune`
+    Rmi {
+        /// `repository[:tag]`, digest or digest prefix
+        #[clap(required = true)]
+        images: Vec<String>,
+    },
+    /// Remove the cache mounts of run scriptlets kept across builds
+    ClearCacheMounts {
+        /// Ids of the cache mounts to remove, every one unless given
+        ids: Vec<String>,
+    },
+    /// Export the root filesystem of a stored image into a directory
+    Rootfs {
+        /// `repository[:tag]`, digest or digest prefix
+        image: String,
+        /// Directory to extract into, which must be empty if it exists
+        directory: String,
+    },
+    /// Run a build command inside the namespaces created for it, see `rootfs::sandbox`
+    #[clap(name = rootfs::sandbox::HELPER_COMMAND, hide = true)]
+    SandboxInit,
 }
 
 #[derive(Parser)]
@@ -25,13 +122,58 @@ struct Args {
 }
 
 fn main() {
-    let client = docker_hub::DockerHub::new(None).unwrap();
-    client.download_base_image("ubuntu", "latest").unwrap();
     let args = Args::parse();
-    let command = match &args.command {
-        Commands::Build { config_directory } => || command::build(config_directory.clone()),
+    let result = match args.command {
+        Commands::Build {
+            config_directory,
+            verify_reproducible,
+            compression,
+            no_cache,
+            explain_cache,
+            cache_from,
+            cache_to,
+            jobs,
+            keep_going,
+            image,
+            list_targets,
+            secret,
+        } => command::build(
+            config_directory,
+            build::Options {
+                compression,
+                no_cache,
+                explain_cache,
+                cache_from,
+                cache_to,
+                jobs,
+                keep_going,
+                targets: image,
+                list_targets,
+                secrets: secret.into_iter().collect(),
+                ..Default::default()
+            },
+            verify_reproducible,
+        ),
+        Commands::Prune {
+            dry_run,
+            older_than,
+            max_size,
+        } => command::prune(gc::Options {
+            dry_run,
+            older_than,
+            max_size,
+        }),
+        Commands::Fsck { quarantine, repair } => {
+            command::fsck(fsck::Options { quarantine, repair })
+        }
+        Commands::Images { no_trunc } => command::images(no_trunc),
+        Commands::Inspect { images } => command::inspect(images),
+        Commands::Rmi { images } => command::rmi(images),
+        Commands::ClearCacheMounts { ids } => command::clear_cache_mounts(ids),
+        Commands::Rootfs { image, directory } => command::rootfs(image, directory),
+        Commands::SandboxInit => process::exit(rootfs::sandbox::enter()),
     };
-    if let Err(err) = command() {
+    if let Err(err) = result {
         eprintln!("{}", err);
         process::exit(1);
     }
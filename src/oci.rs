@@ -0,0 +1,3 @@
+pub mod config;
+pub mod manifest;
+pub mod media_type;
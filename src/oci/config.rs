@@ -0,0 +1,145 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const LAYERS_ROOTFS_TYPE: &str = "layers";
+
+/// Uncompressed digests of the layers making up the root filesystem.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RootFs {
+    #[serde(rename = "type")]
+    pub typ: String,
+    #[serde(default)]
+    pub diff_ids: Vec<String>,
+}
+
+impl Default for RootFs {
+    fn default() -> Self {
+        Self {
+            typ: LAYERS_ROOTFS_TYPE.to_string(),
+            diff_ids: vec![],
+        }
+    }
+}
+
+/// How one layer, or one configuration change without a layer, came about.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct History {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// true if no layer of `rootfs.diff_ids` belongs to this entry
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub empty_layer: bool,
+}
+
+/// How to check whether a container is healthy, a Docker extension.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Healthcheck {
+    /// `["NONE"]`, `["CMD", arguments...]` or `["CMD-SHELL", command]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test: Option<Vec<String>>,
+    /// nanoseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<u64>,
+    /// nanoseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// nanoseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_period: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+/// Execution parameters of containers created from the image.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exposed_ports: Option<BTreeMap<String, serde_json::Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entrypoint: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmd: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volumes: Option<BTreeMap<String, serde_json::Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_signal: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub healthcheck: Option<Healthcheck>,
+    /// shell running the string form of commands, a Docker extension
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<Vec<String>>,
+    /// fields amethyst does not handle, kept as they are
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+/// Image configuration blob referred to by `manifest::Manifest::config`.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct ImageConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub architecture: String,
+    #[serde(default)]
+    pub os: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<ContainerConfig>,
+    #[serde(default)]
+    pub rootfs: RootFs,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<History>,
+    /// fields amethyst does not handle, such as `author` or `variant`, kept as they are
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ImageConfig;
+
+    #[test]
+    fn keep_unknown_fields() {
+        let original = serde_json::json!({
+            "created": "2022-04-01T00:00:00Z",
+            "architecture": "arm64",
+            "variant": "v8",
+            "os": "linux",
+            "config": {
+                "Env": ["PATH=/usr/bin"],
+                "Cmd": ["bash"],
+                "ArgsEscaped": true
+            },
+            "rootfs": {"type": "layers", "diff_ids": ["sha256:abcd"]},
+            "history": [
+                {"created": "2022-04-01T00:00:00Z", "created_by": "/bin/sh -c #(nop) CMD [\"bash\"]", "empty_layer": true}
+            ]
+        });
+        let config: ImageConfig = serde_json::from_value(original.clone()).unwrap();
+
+        assert_eq!(
+            config.config.as_ref().unwrap().cmd,
+            Some(vec!["bash".to_string()])
+        );
+        assert!(config.history[0].empty_layer);
+        assert_eq!(serde_json::to_value(&config).unwrap(), original);
+    }
+}
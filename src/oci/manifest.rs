@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Config {
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub size: usize,
+    pub digest: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Layer {
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub size: usize,
+    pub digest: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Manifest {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub config: Config,
+    pub layers: Vec<Layer>,
+}
+
+impl Manifest {
+    /// Size of the config and every layer.
+    pub fn size(&self) -> usize {
+        self.config.size + self.layers.iter().map(|layer| layer.size).sum::<usize>()
+    }
+
+    /// Digests of every blob the manifest refers to.
+    pub fn blob_digests(&self) -> Vec<&str> {
+        let mut digests = vec![self.config.digest.as_str()];
+        digests.extend(self.layers.iter().map(|layer| layer.digest.as_str()));
+        digests
+    }
+}
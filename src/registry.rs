@@ -1,2 +1,4 @@
+pub mod distribution;
 pub mod docker_hub;
+#[allow(clippy::module_inception)]
 pub mod registry;
@@ -0,0 +1,184 @@
+use crate::http;
+use crate::oci::media_type;
+use crate::result;
+use std::error;
+use std::fmt;
+
+#[derive(Debug)]
+struct InvalidRegistryReferenceError {
+    reference: String,
+}
+
+impl fmt::Display for InvalidRegistryReferenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} is not a http(s)://<host>/<repository>[:<tag>] reference",
+            self.reference
+        )
+    }
+}
+
+impl error::Error for InvalidRegistryReferenceError {}
+
+#[derive(Debug)]
+struct MissingUploadLocationError {
+    repository: String,
+}
+
+impl fmt::Display for MissingUploadLocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "registry did not tell where to upload blobs of {}",
+            self.repository
+        )
+    }
+}
+
+impl error::Error for MissingUploadLocationError {}
+
+/// Repository and tag of a registry implementing the OCI distribution API,
+/// accessed anonymously, e.g. `http://localhost:5000/cache/amethyst:latest`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Distribution {
+    /// scheme and authority, e.g. `http://localhost:5000`
+    pub base_url: String,
+    pub repository: String,
+    pub tag: String,
+}
+
+impl Distribution {
+    pub fn parse(reference: &str) -> result::Result<Self> {
+        let error = || -> result::BoxedError {
+            Box::new(InvalidRegistryReferenceError {
+                reference: reference.to_string(),
+            })
+        };
+        let (scheme, rest) = reference.split_once("://").ok_or_else(error)?;
+        if scheme != "http" && scheme != "https" {
+            return Err(error());
+        }
+        let (host, path) = rest.split_once('/').ok_or_else(error)?;
+        let (repository, tag) = match path.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+            _ => (path, "latest"),
+        };
+        if host.is_empty() || repository.is_empty() || tag.is_empty() {
+            return Err(error());
+        }
+        Ok(Self {
+            base_url: format!("{}://{}", scheme, host),
+            repository: repository.to_string(),
+            tag: tag.to_string(),
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/v2/{}/{}", self.base_url, self.repository, path)
+    }
+
+    fn check(
+        response: reqwest::blocking::Response,
+        message: &str,
+    ) -> result::Result<reqwest::blocking::Response> {
+        if !response.status().is_success() {
+            return Err(Box::new(http::HttpError {
+                status_code: response.status(),
+                message: message.to_string(),
+            }));
+        }
+        Ok(response)
+    }
+
+    /// Fetches the OCI manifest of the tag.
+    pub fn fetch_manifest(&self) -> result::Result<bytes::Bytes> {
+        let response = reqwest::blocking::Client::new()
+            .get(self.url(&format!("manifests/{}", self.tag)))
+            .header("Accept", media_type::OCI_MANIFEST)
+            .send()?;
+        Ok(Self::check(response, "cannot fetch manifest from registry")?.bytes()?)
+    }
+
+    pub fn fetch_blob(&self, digest: &str) -> result::Result<bytes::Bytes> {
+        let response = reqwest::blocking::get(self.url(&format!("blobs/{}", digest)))?;
+        Ok(Self::check(response, "cannot fetch blob from registry")?.bytes()?)
+    }
+
+    /// Uploads `content`, hashing to `digest`, unless the repository already has it.
+    pub fn push_blob(&self, digest: &str, content: Vec<u8>) -> result::Result<()> {
+        let client = reqwest::blocking::Client::new();
+        let exists = client.head(self.url(&format!("blobs/{}", digest))).send()?;
+        if exists.status().is_success() {
+            return Ok(());
+        }
+        let response = client.post(self.url("blobs/uploads/")).send()?;
+        let response = Self::check(response, "cannot start blob upload to registry")?;
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or_else(|| MissingUploadLocationError {
+                repository: self.repository.clone(),
+            })?;
+        let location = if location.starts_with('/') {
+            format!("{}{}", self.base_url, location)
+        } else {
+            location.to_string()
+        };
+        let separator = if location.contains('?') { '&' } else { '?' };
+        let response = client
+            .put(format!("{}{}digest={}", location, separator, digest))
+            .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+            .body(content)
+            .send()?;
+        Self::check(response, "cannot upload blob to registry")?;
+        Ok(())
+    }
+
+    /// Tags `manifest`, an OCI manifest whose blobs were pushed already.
+    pub fn push_manifest(&self, manifest: Vec<u8>) -> result::Result<()> {
+        let response = reqwest::blocking::Client::new()
+            .put(self.url(&format!("manifests/{}", self.tag)))
+            .header(reqwest::header::CONTENT_TYPE, media_type::OCI_MANIFEST)
+            .body(manifest)
+            .send()?;
+        Self::check(response, "cannot push manifest to registry")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Distribution;
+
+    #[test]
+    fn parse_reference() {
+        assert_eq!(
+            Distribution::parse("http://localhost:5000/cache/amethyst:main").unwrap(),
+            Distribution {
+                base_url: "http://localhost:5000".to_string(),
+                repository: "cache/amethyst".to_string(),
+                tag: "main".to_string(),
+            }
+        );
+        assert_eq!(
+            Distribution::parse("https://registry.example.com/amethyst")
+                .unwrap()
+                .tag,
+            "latest"
+        );
+    }
+
+    #[test]
+    fn cannot_parse_invalid_reference() {
+        for reference in [
+            "localhost:5000/amethyst",
+            "ftp://localhost/amethyst",
+            "http://localhost",
+            "http:///amethyst",
+        ] {
+            assert!(Distribution::parse(reference).is_err(), "{}", reference);
+        }
+    }
+}
@@ -1,15 +1,13 @@
 mod error;
-mod manifest;
 pub mod token;
 
 use super::registry;
 use crate::http;
+use crate::oci::{manifest, media_type};
 use crate::result;
 use crate::storage;
 
 use serde::Deserialize;
-use std::fs;
-use std::path;
 
 fn registry1_url(path: &str) -> String {
     format!("https://registry-1.docker.io/v2/{}", path)
@@ -24,6 +22,13 @@ impl DockerHub {
         Ok(Self { authorized_token })
     }
 
+    fn token(&self, repository: &str) -> result::Result<token::Token> {
+        match &self.authorized_token {
+            Some(token) => Ok(token.clone()),
+            None => bearer_token(repository),
+        }
+    }
+
     fn manifest(
         &self,
         repositry: &str,
@@ -34,10 +39,7 @@ impl DockerHub {
         let client = reqwest::blocking::Client::new();
         let resp = client
             .get(url)
-            .header(
-                "Accept",
-                "application/vnd.docker.distribution.manifest.v2+json",
-            )
+            .header("Accept", media_type::DOCKER_MANIFEST)
             .header("Authorization", token.to_string())
             .send()?;
         if !resp.status().is_success() {
@@ -109,37 +111,37 @@ fn normalize_repository(repository: &str) -> result::Result<String> {
 }
 
 impl registry::Registry for DockerHub {
-    fn download_base_image(&self, repository: &str, tag: &str) -> result::Result<path::PathBuf> {
+    fn download_base_image(&self, repository: &str, tag: &str) -> result::Result<String> {
         if repository == "scratch" {
             return Err(Box::new(error::ReservedImageError {
                 event: "download image".to_string(),
                 image_name: repository.to_string(),
             }));
         }
+        storage::initialize()?;
         let repository = normalize_repository(repository)?;
-        let token = match &self.authorized_token {
-            Some(token) => token.clone(),
-            None => bearer_token(repository.as_str())?,
-        };
-        let manifest_storage = manifest::storage().join(&repository).join(tag);
-        fs::create_dir_all(&manifest_storage)?;
-
+        let token = self.token(repository.as_str())?;
         let manifest = self.manifest(repository.as_str(), tag, &token)?;
-        fs::write(
-            manifest_storage.join(manifest::MANIFEST_FILENAME),
-            &manifest,
-        )?;
+        let manifest_digest = storage::write_blob(&manifest)?;
         let manifest: manifest::Manifest = serde_json::from_slice(&manifest)?;
 
-        let blob_storage = storage::blob_storage();
-        fs::create_dir_all(&blob_storage)?;
         for layer in manifest.layers {
             let blob = self.blob(repository.as_str(), layer.digest.as_str(), &token)?;
-            fs::write(blob_storage.join(layer.digest), blob)?;
+            storage::write_verified_blob(&layer.digest, &blob)?;
         }
         let blob = self.blob(repository.as_str(), manifest.config.digest.as_str(), &token)?;
-        fs::write(blob_storage.join(manifest.config.digest), blob)?;
+        storage::write_verified_blob(&manifest.config.digest, &blob)?;
+
+        storage::tag_image(&repository, tag, &manifest_digest, false)?;
+
+        Ok(manifest_digest)
+    }
+
+    fn fetch_manifest(&self, repository: &str, reference: &str) -> result::Result<bytes::Bytes> {
+        self.manifest(repository, reference, &self.token(repository)?)
+    }
 
-        Ok(manifest_storage)
+    fn fetch_blob(&self, repository: &str, digest: &str) -> result::Result<bytes::Bytes> {
+        self.blob(repository, digest, &self.token(repository)?)
     }
 }
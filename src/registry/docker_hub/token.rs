@@ -1,16 +1,14 @@
-use std::string;
+use std::fmt;
 
 #[derive(Debug, Clone)]
 pub enum Token {
-    JWT(String),
     Bearer(String),
 }
 
-impl string::ToString for Token {
-    fn to_string(&self) -> String {
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Bearer(token) => format!("Bearer {}", token),
-            Self::JWT(token) => format!("JWT {}", token),
+            Self::Bearer(token) => write!(f, "Bearer {}", token),
         }
     }
 }
@@ -1,6 +1,10 @@
 use crate::result;
-use std::path;
 
-pub trait Registry {
-    fn download_base_image(&self, image_name: &str, tag: &str) -> result::Result<path::PathBuf>;
+/// `Sync` as images are built concurrently.
+pub trait Registry: Sync {
+    /// Downloads the image and returns the digest of its manifest.
+    fn download_base_image(&self, image_name: &str, tag: &str) -> result::Result<String>;
+    /// Fetches the manifest `reference`, a tag or a digest, of a normalized repository.
+    fn fetch_manifest(&self, repository: &str, reference: &str) -> result::Result<bytes::Bytes>;
+    fn fetch_blob(&self, repository: &str, digest: &str) -> result::Result<bytes::Bytes>;
 }
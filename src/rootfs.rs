@@ -0,0 +1,200 @@
+pub mod sandbox;
+pub mod user;
+
+use crate::layer::{self, unpack};
+use crate::oci::manifest;
+use crate::result;
+use crate::storage;
+use std::collections::VecDeque;
+use std::convert;
+use std::error;
+use std::ffi;
+use std::fmt;
+use std::fs;
+use std::path;
+
+/// Symbolic links followed while resolving a single path, as `MAXSYMLINKS` on Linux.
+const MAXIMUM_SYMBOLIC_LINKS: usize = 40;
+
+#[derive(Debug)]
+struct TooManySymbolicLinksError {
+    path: String,
+}
+
+impl fmt::Display for TooManySymbolicLinksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "too many levels of symbolic links in {}", self.path)
+    }
+}
+
+impl error::Error for TooManySymbolicLinksError {}
+
+/// Resolves `path` as seen from inside a container whose root is `root`.
+///
+/// Symbolic links are followed relative to `root`, except the last component
+/// when `follow_last` is false, and `..` never leaves `root`, as after `chroot`.
+pub fn resolve<P>(root: &path::Path, path: P, follow_last: bool) -> result::Result<path::PathBuf>
+where
+    P: convert::AsRef<path::Path>,
+{
+    let mut pending = path
+        .as_ref()
+        .components()
+        .map(|component| component.as_os_str().to_os_string())
+        .collect::<VecDeque<_>>();
+    let mut resolved: Vec<ffi::OsString> = vec![];
+    let mut links = 0;
+    while let Some(component) = pending.pop_front() {
+        if component == "/" || component == "." {
+            continue;
+        }
+        if component == ".." {
+            resolved.pop();
+            continue;
+        }
+        let candidate = resolved
+            .iter()
+            .fold(root.to_path_buf(), |path, component| path.join(component))
+            .join(&component);
+        let is_symbolic_link = fs::symlink_metadata(&candidate)
+            .map(|metadata| metadata.file_type().is_symlink())
+            .unwrap_or(false);
+        if !is_symbolic_link || (pending.is_empty() && !follow_last) {
+            resolved.push(component);
+            continue;
+        }
+        links += 1;
+        if links > MAXIMUM_SYMBOLIC_LINKS {
+            return Err(Box::new(TooManySymbolicLinksError {
+                path: path.as_ref().to_string_lossy().to_string(),
+            }));
+        }
+        let target = fs::read_link(&candidate)?;
+        if target.is_absolute() {
+            resolved.clear();
+        }
+        for component in target.components().rev() {
+            pending.push_front(component.as_os_str().to_os_string());
+        }
+    }
+    Ok(resolved
+        .iter()
+        .fold(root.to_path_buf(), |path, component| path.join(component)))
+}
+
+/// Applies `layers`, lowest first, onto the root filesystem at `root`, and
+/// returns the owners of its files.
+pub fn materialize(root: &path::Path, layers: &[manifest::Layer]) -> result::Result<layer::Owners> {
+    let mut owners = layer::Owners::new();
+    for layer in layers {
+        unpack::apply(root, &layer.digest, &mut owners)?;
+    }
+    Ok(owners)
+}
+
+/// Root filesystem of an image being built, removed when dropped.
+pub struct Rootfs {
+    directory: tempfile::TempDir,
+}
+
+impl Rootfs {
+    /// Creates an empty root filesystem in the temporary storage.
+    pub fn new() -> result::Result<Self> {
+        let temporary_storage = storage::temporary_storage();
+        fs::create_dir_all(&temporary_storage)?;
+        let directory = tempfile::Builder::new()
+            .prefix("rootfs-")
+            .tempdir_in(temporary_storage)?;
+        Ok(Self { directory })
+    }
+
+    pub fn path(&self) -> &path::Path {
+        self.directory.path()
+    }
+
+    /// Resolves `path` inside the root filesystem, following every symbolic link.
+    pub fn resolve<P>(&self, path: P) -> result::Result<path::PathBuf>
+    where
+        P: convert::AsRef<path::Path>,
+    {
+        resolve(self.path(), path, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod resolve {
+        use super::super::resolve;
+        use std::fs;
+        use std::os::unix::fs::symlink;
+
+        #[test]
+        fn resolve_plain_path() {
+            let root = tempfile::tempdir().unwrap();
+
+            assert_eq!(
+                resolve(root.path(), "/etc/./hostname", true).unwrap(),
+                root.path().join("etc").join("hostname")
+            );
+        }
+
+        #[test]
+        fn parent_directory_stays_in_root() {
+            let root = tempfile::tempdir().unwrap();
+
+            assert_eq!(
+                resolve(root.path(), "../../etc/../../hostname", true).unwrap(),
+                root.path().join("hostname")
+            );
+        }
+
+        #[test]
+        fn absolute_symbolic_link_stays_in_root() {
+            let root = tempfile::tempdir().unwrap();
+            fs::create_dir(root.path().join("usr")).unwrap();
+            symlink("/usr/lib", root.path().join("lib")).unwrap();
+            symlink("/", root.path().join("escape")).unwrap();
+
+            assert_eq!(
+                resolve(root.path(), "/lib/libc.so", true).unwrap(),
+                root.path().join("usr").join("lib").join("libc.so")
+            );
+            assert_eq!(
+                resolve(root.path(), "/escape/../etc", true).unwrap(),
+                root.path().join("etc")
+            );
+        }
+
+        #[test]
+        fn relative_symbolic_link_stays_in_root() {
+            let root = tempfile::tempdir().unwrap();
+            fs::create_dir(root.path().join("bin")).unwrap();
+            symlink("../../../../sbin", root.path().join("bin").join("sbin")).unwrap();
+
+            assert_eq!(
+                resolve(root.path(), "/bin/sbin/init", true).unwrap(),
+                root.path().join("sbin").join("init")
+            );
+        }
+
+        #[test]
+        fn keep_last_symbolic_link() {
+            let root = tempfile::tempdir().unwrap();
+            symlink("/target", root.path().join("link")).unwrap();
+
+            assert_eq!(
+                resolve(root.path(), "/link", false).unwrap(),
+                root.path().join("link")
+            );
+        }
+
+        #[test]
+        fn cannot_resolve_symbolic_link_loop() {
+            let root = tempfile::tempdir().unwrap();
+            symlink("/b", root.path().join("a")).unwrap();
+            symlink("/a", root.path().join("b")).unwrap();
+
+            assert!(resolve(root.path(), "/a", true).is_err());
+        }
+    }
+}
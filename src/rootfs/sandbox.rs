@@ -0,0 +1,733 @@
+use crate::layer::{self, unpack};
+use crate::result;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::error;
+use std::ffi;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path;
+use std::process;
+use std::ptr;
+use std::thread;
+
+/// Hidden subcommand of amethyst running as the helper inside the namespaces.
+pub const HELPER_COMMAND: &str = "sandbox-init";
+
+/// First line written by the helper once it is in its namespaces.
+const READY: &str = "amethyst-sandbox-ready";
+
+/// File descriptor on which the helper writes its `Report` once the command
+/// exited, apart from the output of the command.
+const REPORT_FD: RawFd = 3;
+
+/// Exit code of the helper when the sandbox cannot be set up, as with `docker run`,
+/// told apart from the same exit code of the command by `Report::error`.
+const SETUP_FAILURE: i32 = 125;
+
+/// `PATH` of commands unless the image configuration sets one.
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Host devices bind mounted into `/dev`.
+const DEVICES: &[&str] = &["null", "zero", "full", "random", "urandom"];
+
+const RESOLV_CONF_PATH: &str = "etc/resolv.conf";
+
+const LOOPBACK: &str = "lo";
+
+/// Host ids to which the ids of the namespaces are mapped when amethyst runs
+/// as root and has no subordinate ids, far from those usually given to users.
+const ROOT_SUBORDINATE_IDS: (u32, u32) = (1 << 30, 65536);
+
+#[derive(Debug)]
+struct CommandFailedError {
+    command: String,
+    code: i32,
+}
+
+impl fmt::Display for CommandFailedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} exited with code {}", self.command, self.code)
+    }
+}
+
+impl error::Error for CommandFailedError {}
+
+#[derive(Debug)]
+struct SetupFailedError {
+    command: String,
+    error: String,
+}
+
+impl fmt::Display for SetupFailedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot run {}: cannot set up the sandbox: {}",
+            self.command, self.error
+        )
+    }
+}
+
+impl error::Error for SetupFailedError {}
+
+#[derive(Debug)]
+struct ProgramError {
+    program: String,
+    err: io::Error,
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot run {}: {}", self.program, self.err)
+    }
+}
+
+impl error::Error for ProgramError {}
+
+#[derive(Debug)]
+struct EmptyCommandError;
+
+impl fmt::Display for EmptyCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot run an empty command")
+    }
+}
+
+impl error::Error for EmptyCommandError {}
+
+#[derive(Debug)]
+struct NamespaceNotReadyError;
+
+impl fmt::Display for NamespaceNotReadyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "user namespace was not set up by amethyst")
+    }
+}
+
+impl error::Error for NamespaceNotReadyError {}
+
+/// File bind mounted read-only for the command only.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Secret {
+    /// absolute path outside the root filesystem
+    pub source: path::PathBuf,
+    /// absolute path inside the root filesystem
+    pub target: String,
+}
+
+/// Directory bind mounted read-write for the command only.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Cache {
+    /// absolute path outside the root filesystem
+    pub source: path::PathBuf,
+    /// absolute path inside the root filesystem
+    pub target: String,
+    /// owners of the files in it as seen from the command
+    #[serde(default)]
+    pub owners: layer::Owners,
+}
+
+/// Owners of the files as seen from the command once it exited.
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq)]
+pub struct Report {
+    /// of the root filesystem
+    pub owners: layer::Owners,
+    /// of each of `Spec::caches`, in order
+    pub caches: Vec<layer::Owners>,
+    /// why the sandbox could not be set up, if so
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What to run and how, passed from the build to the helper through its
+/// stdin, so that secrets never show in its arguments.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Spec {
+    /// absolute path of the root filesystem
+    pub root: path::PathBuf,
+    pub arguments: Vec<String>,
+    /// `KEY=value` pairs
+    pub env: Vec<String>,
+    /// absolute path inside the root filesystem
+    pub working_dir: String,
+    pub uid: u32,
+    pub gid: u32,
+    /// shares the network of the host instead of having only a loopback interface
+    pub network: bool,
+    pub hostname: String,
+    #[serde(default)]
+    pub secrets: Vec<Secret>,
+    #[serde(default)]
+    pub caches: Vec<Cache>,
+    /// owners of the files of the root filesystem as seen from the command
+    #[serde(default)]
+    pub owners: layer::Owners,
+}
+
+fn check(value: libc::c_int) -> io::Result<()> {
+    if value == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn c_path(path: &path::Path) -> io::Result<ffi::CString> {
+    ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+/// Name of the host user `uid`, if any.
+fn user_name(uid: u32) -> Option<String> {
+    fs::read_to_string("/etc/passwd")
+        .ok()?
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 2 && fields[2] == uid.to_string())
+        .map(|fields| fields[0].to_string())
+}
+
+/// First range of subordinate ids of `id` or `name` in `/etc/subuid` or `/etc/subgid`.
+fn subordinate_ids(database: &str, id: u32, name: Option<&str>) -> Option<(u32, u32)> {
+    fs::read_to_string(database)
+        .ok()?
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split(':').collect::<Vec<_>>();
+            if fields.len() != 3 || (fields[0] != id.to_string() && Some(fields[0]) != name) {
+                return None;
+            }
+            Some((fields[1].parse().ok()?, fields[2].parse().ok()?))
+        })
+        .next()
+}
+
+/// Host uids and gids of the namespaces when amethyst runs as root, which are
+/// not root itself so that commands cannot act as root on the host.
+fn root_subordinate_ids() -> ((u32, u32), (u32, u32)) {
+    (
+        subordinate_ids("/etc/subuid", 0, Some("root")).unwrap_or(ROOT_SUBORDINATE_IDS),
+        subordinate_ids("/etc/subgid", 0, Some("root")).unwrap_or(ROOT_SUBORDINATE_IDS),
+    )
+}
+
+/// Maps ids of the user namespace of `pid` to the host: to the subordinate
+/// ids of root for root, to the user and its subordinate ids through
+/// `newuidmap` and `newgidmap` if it has some, or else only root to the user.
+fn map_ids(pid: u32) -> result::Result<()> {
+    let directory = path::PathBuf::from(format!("/proc/{}", pid));
+    let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+    if uid == 0 {
+        let ((uid, uids), (gid, gids)) = root_subordinate_ids();
+        fs::write(directory.join("uid_map"), format!("0 {} {}\n", uid, uids))?;
+        fs::write(directory.join("gid_map"), format!("0 {} {}\n", gid, gids))?;
+        return Ok(());
+    }
+    let name = user_name(uid);
+    let subordinates = (
+        subordinate_ids("/etc/subuid", uid, name.as_deref()),
+        subordinate_ids("/etc/subgid", gid, name.as_deref()),
+    );
+    if let (Some(subuids), Some(subgids)) = subordinates {
+        let mut mapped = true;
+        for (program, id, (start, count)) in
+            [("newuidmap", uid, subuids), ("newgidmap", gid, subgids)]
+        {
+            let status = process::Command::new(program)
+                .args([pid, 0, id, 1, 1, start, count].map(|value| value.to_string()))
+                .status();
+            mapped &= matches!(status, Ok(status) if status.success());
+        }
+        if mapped {
+            return Ok(());
+        }
+    }
+    fs::write(directory.join("setgroups"), "deny")?;
+    fs::write(directory.join("uid_map"), format!("0 {} 1\n", uid))?;
+    fs::write(directory.join("gid_map"), format!("0 {} 1\n", gid))?;
+    Ok(())
+}
+
+/// Runs `spec.arguments` in new user, mount, PID, UTS and IPC namespaces, and
+/// a network one unless `spec.network`, passing every line it writes to
+/// stdout or stderr to `output`, and returns the owners of the files of the
+/// root filesystem and of the caches as seen from the command once it exited.
+///
+/// The namespaces are created by a helper, amethyst itself run as
+/// `HELPER_COMMAND`, which tells it is in them, waits until its ids are mapped
+/// and then runs the command as PID 1 with `spec.root` as its root, the one of
+/// the host being unmounted. Every file, caches included, is owned by root
+/// afterwards, whatever the ids it was given.
+pub fn run<F>(spec: &Spec, mut output: F) -> result::Result<Report>
+where
+    F: FnMut(&str),
+{
+    if spec.arguments.is_empty() {
+        return Err(Box::new(EmptyCommandError));
+    }
+    // by the build, so that root of the namespaces owns it
+    fs::create_dir_all(super::resolve(&spec.root, &spec.working_dir, true)?)?;
+    if unsafe { libc::geteuid() } == 0 {
+        let ((uid, _), (gid, _)) = root_subordinate_ids();
+        chown_tree(&spec.root, uid, gid)?;
+        for source in spec.secrets.iter().map(|secret| &secret.source) {
+            lchown(source, uid, gid)?;
+        }
+        for source in spec.caches.iter().map(|cache| &cache.source) {
+            lchown(source, uid, gid)?;
+        }
+    }
+    let (reader, writer) = io::pipe()?;
+    let (mut report_reader, report_writer) = io::pipe()?;
+    let mut command = process::Command::new(env::current_exe()?);
+    command
+        .arg(HELPER_COMMAND)
+        .stdin(process::Stdio::piped())
+        .stdout(writer.try_clone()?)
+        .stderr(writer);
+    unsafe {
+        command.pre_exec(move || {
+            let fd = report_writer.as_raw_fd();
+            if fd == REPORT_FD {
+                check(libc::fcntl(fd, libc::F_SETFD, 0))
+            } else {
+                check(libc::dup2(fd, REPORT_FD))
+            }
+        });
+    }
+    let mut child = command.spawn()?;
+    // closes the write ends of the pipes held by `command`
+    drop(command);
+    // the helper may not write the whole report until its output is read
+    let report = thread::spawn(move || {
+        let mut report = vec![];
+        report_reader.read_to_end(&mut report).map(|_| report)
+    });
+    let mut stdin = child.stdin.take().expect("stdin of the helper is piped");
+    let mut spec_line = serde_json::to_vec(spec)?;
+    spec_line.push(b'\n');
+    // the helper exits on errors before reading it, which are logged below
+    let _ = stdin.write_all(&spec_line);
+
+    let mut reader = io::BufReader::new(reader);
+    let mut line = vec![];
+    reader.read_until(b'\n', &mut line)?;
+    if line == format!("{}\n", READY).as_bytes() {
+        line.clear();
+        if let Err(err) = map_ids(child.id()) {
+            // the helper gives up once its stdin is closed
+            drop(stdin);
+            child.wait()?;
+            return Err(err);
+        }
+        stdin.write_all(b"\n")?;
+    }
+    drop(stdin);
+
+    loop {
+        if !line.is_empty() {
+            output(String::from_utf8_lossy(&line).trim_end_matches('\n'));
+        }
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+    }
+    let status = child.wait()?;
+    let report = report.join().expect("reading the report does not panic")?;
+    // missing if the helper was killed
+    let report: Option<Report> = if report.is_empty() {
+        None
+    } else {
+        Some(serde_json::from_slice(&report)?)
+    };
+    let command = serde_json::to_string(&spec.arguments)?;
+    match (status.code(), report) {
+        (
+            _,
+            Some(Report {
+                error: Some(error), ..
+            }),
+        ) => Err(Box::new(SetupFailedError { command, error })),
+        (Some(0), Some(report)) => Ok(report),
+        (code, _) => Err(Box::new(CommandFailedError {
+            command,
+            code: code.unwrap_or_else(|| 128 + status.signal().unwrap_or(0)),
+        })),
+    }
+}
+
+/// Mount points made in the root filesystem, undone once the command exited.
+#[derive(Default)]
+struct Mounts {
+    mounted: Vec<path::PathBuf>,
+    /// files and directories made only to mount on, removed afterwards
+    created: Vec<path::PathBuf>,
+}
+
+impl Mounts {
+    /// Makes the missing parent directories of `path`.
+    fn create_parents(&mut self, path: &path::Path) -> io::Result<()> {
+        let mut missing = path
+            .ancestors()
+            .skip(1)
+            .take_while(|ancestor| fs::symlink_metadata(ancestor).is_err())
+            .collect::<Vec<_>>();
+        missing.reverse();
+        for directory in missing {
+            self.create(directory, true)?;
+        }
+        Ok(())
+    }
+
+    /// Makes `path` a mount point, a directory or an empty file, unless it exists.
+    fn create(&mut self, path: &path::Path, directory: bool) -> io::Result<()> {
+        if fs::symlink_metadata(path).is_ok() {
+            return Ok(());
+        }
+        if directory {
+            fs::create_dir(path)?;
+        } else {
+            fs::File::create(path)?;
+        }
+        self.created.push(path.to_path_buf());
+        Ok(())
+    }
+
+    fn bind(&mut self, source: &path::Path, target: &path::Path) -> io::Result<()> {
+        self.create(target, source.is_dir())?;
+        let (source_path, target_path) = (c_path(source)?, c_path(target)?);
+        check(unsafe {
+            libc::mount(
+                source_path.as_ptr(),
+                target_path.as_ptr(),
+                ptr::null(),
+                libc::MS_BIND,
+                ptr::null(),
+            )
+        })?;
+        self.mounted.push(target.to_path_buf());
+        Ok(())
+    }
+
+    fn bind_read_only(&mut self, source: &path::Path, target: &path::Path) -> io::Result<()> {
+        self.create_parents(target)?;
+        self.bind(source, target)?;
+        let target = c_path(target)?;
+        check(unsafe {
+            libc::mount(
+                ptr::null(),
+                target.as_ptr(),
+                ptr::null(),
+                libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY,
+                ptr::null(),
+            )
+        })
+    }
+
+    fn undo(&mut self) {
+        for path in self.mounted.drain(..).rev() {
+            if let Ok(path) = c_path(&path) {
+                unsafe { libc::umount2(path.as_ptr(), libc::MNT_DETACH) };
+            }
+        }
+        for path in self.created.drain(..).rev() {
+            let _ = if path.is_dir() {
+                fs::remove_dir(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+        }
+    }
+}
+
+/// Changes the owner of `path` without following it, keeping the set-user-ID
+/// and set-group-ID bits which are cleared along.
+fn lchown(path: &path::Path, uid: u32, gid: u32) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    std::os::unix::fs::lchown(path, Some(uid), Some(gid))?;
+    if !metadata.file_type().is_symlink() && metadata.mode() & 0o6000 != 0 {
+        let mode = metadata.mode() & unpack::PERMISSION_MASK;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+/// Gives `path` and everything in it to `uid` and `gid`.
+fn chown_tree(path: &path::Path, uid: u32, gid: u32) -> io::Result<()> {
+    lchown(path, uid, gid)?;
+    if fs::symlink_metadata(path)?.is_dir() {
+        for entry in fs::read_dir(path)? {
+            chown_tree(&entry?.path(), uid, gid)?;
+        }
+    }
+    Ok(())
+}
+
+/// Gives the files of the root filesystem or cache at `root` their `owners`, and
+/// returns those which cannot be given as their ids are not mapped.
+fn apply_owners(root: &path::Path, owners: &layer::Owners) -> result::Result<layer::Owners> {
+    let mut unapplied = layer::Owners::new();
+    for (name, (uid, gid)) in owners {
+        let path = super::resolve(root, name, false)?;
+        if lchown(&path, *uid, *gid).is_err() {
+            unapplied.insert(name.clone(), (*uid, *gid));
+        }
+    }
+    Ok(unapplied)
+}
+
+/// Gives every file of the root filesystem or cache at `root` to root, so that the
+/// build can read and remove them whatever ids they were given, and returns
+/// the owners they had along with the `unapplied` ones of existing files.
+fn reset_owners(root: &path::Path, unapplied: layer::Owners) -> result::Result<layer::Owners> {
+    let mut owners = unapplied;
+    owners.retain(|name, _| fs::symlink_metadata(root.join(name)).is_ok());
+    reset_directory(root, path::Path::new(""), &mut owners)?;
+    Ok(owners)
+}
+
+fn reset_directory(
+    root: &path::Path,
+    directory: &path::Path,
+    owners: &mut layer::Owners,
+) -> result::Result<()> {
+    for entry in fs::read_dir(root.join(directory))? {
+        let entry = entry?;
+        let name = directory.join(entry.file_name());
+        let metadata = entry.metadata()?;
+        let owner = (metadata.uid(), metadata.gid());
+        if owner != (0, 0) {
+            lchown(&entry.path(), 0, 0)?;
+            owners.insert(name.clone(), owner);
+        }
+        if metadata.is_dir() {
+            reset_directory(root, &name, owners)?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the helper, reading its `Spec` from stdin, writes its `Report` and
+/// returns its exit code.
+pub fn enter() -> i32 {
+    let mut report = Report::default();
+    let code = match try_enter(&mut report) {
+        Ok(code) => code,
+        Err(err) => {
+            report.error = Some(err.to_string());
+            SETUP_FAILURE
+        }
+    };
+    let file = unsafe { fs::File::from_raw_fd(REPORT_FD) };
+    if let Err(err) = serde_json::to_writer(file, &report) {
+        eprintln!("cannot report to amethyst: {}", err);
+        return SETUP_FAILURE;
+    }
+    code
+}
+
+/// Brings up the loopback interface of the network namespace, down when created.
+fn bring_up_loopback() -> io::Result<()> {
+    let socket = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    check(socket)?;
+    let socket = unsafe { OwnedFd::from_raw_fd(socket) };
+    let mut request: libc::ifreq = unsafe { mem::zeroed() };
+    for (byte, name) in request.ifr_name.iter_mut().zip(LOOPBACK.bytes()) {
+        *byte = name as libc::c_char;
+    }
+    check(unsafe { libc::ioctl(socket.as_raw_fd(), libc::SIOCGIFFLAGS, &mut request) })?;
+    unsafe { request.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short };
+    check(unsafe { libc::ioctl(socket.as_raw_fd(), libc::SIOCSIFFLAGS, &request) })
+}
+
+fn try_enter(report: &mut Report) -> result::Result<i32> {
+    // not inherited by the command
+    check(unsafe { libc::fcntl(REPORT_FD, libc::F_SETFD, libc::FD_CLOEXEC) })?;
+    let mut spec = String::new();
+    io::stdin().read_line(&mut spec)?;
+    let spec: Spec = serde_json::from_str(&spec)?;
+    let mut flags = libc::CLONE_NEWUSER
+        | libc::CLONE_NEWNS
+        | libc::CLONE_NEWPID
+        | libc::CLONE_NEWUTS
+        | libc::CLONE_NEWIPC;
+    if !spec.network {
+        flags |= libc::CLONE_NEWNET;
+    }
+    // not before exec, which would drop the capabilities of the unmapped user
+    check(unsafe { libc::unshare(flags) })?;
+    println!("{}", READY);
+    if io::stdin().read(&mut [0])? == 0 {
+        return Err(Box::new(NamespaceNotReadyError));
+    }
+    if !spec.network {
+        bring_up_loopback()?;
+    }
+    check(unsafe {
+        libc::sethostname(
+            spec.hostname.as_ptr() as *const libc::c_char,
+            spec.hostname.len(),
+        )
+    })?;
+    let root = c_path(path::Path::new("/"))?;
+    check(unsafe {
+        libc::mount(
+            ptr::null(),
+            root.as_ptr(),
+            ptr::null(),
+            libc::MS_REC | libc::MS_PRIVATE,
+            ptr::null(),
+        )
+    })?;
+
+    let unapplied = apply_owners(&spec.root, &spec.owners)?;
+    let unapplied_caches = spec
+        .caches
+        .iter()
+        .map(|cache| apply_owners(&cache.source, &cache.owners))
+        .collect::<result::Result<Vec<_>>>()?;
+    let mut mounts = Mounts::default();
+    let result = spawn(&spec, &mut mounts);
+    mounts.undo();
+    // whatever the outcome, so that the build can remove every file and
+    // amethyst can clear the caches
+    report.owners = reset_owners(&spec.root, unapplied)?;
+    report.caches = spec
+        .caches
+        .iter()
+        .zip(unapplied_caches)
+        .map(|(cache, unapplied)| reset_owners(&cache.source, unapplied))
+        .collect::<result::Result<Vec<_>>>()?;
+    result
+}
+
+fn spawn(spec: &Spec, mounts: &mut Mounts) -> result::Result<i32> {
+    let (program, arguments) = spec.arguments.split_first().ok_or(EmptyCommandError)?;
+    let dev = spec.root.join("dev");
+    mounts.create(&dev, true)?;
+    for device in DEVICES {
+        mounts.bind(&path::Path::new("/dev").join(device), &dev.join(device))?;
+    }
+    if spec.network && spec.root.join("etc").is_dir() {
+        let resolv_conf = path::Path::new("/").join(RESOLV_CONF_PATH);
+        let target = super::resolve(&spec.root, RESOLV_CONF_PATH, true)?;
+        // best effort, as the image may link it to a directory which does not exist
+        if resolv_conf.is_file() && mounts.bind(&resolv_conf, &target).is_err() {
+            eprintln!("cannot provide /{} to the command", RESOLV_CONF_PATH);
+        }
+    }
+    // before secrets, which may be mounted in caches
+    for cache in &spec.caches {
+        let target = super::resolve(&spec.root, &cache.target, true)?;
+        mounts.create_parents(&target)?;
+        mounts.bind(&cache.source, &target)?;
+    }
+    for secret in &spec.secrets {
+        let target = super::resolve(&spec.root, &secret.target, true)?;
+        mounts.bind_read_only(&secret.source, &target)?;
+    }
+    // mounted by the command in a mount namespace of its own
+    let proc = spec.root.join("proc");
+    mounts.create(&proc, true)?;
+
+    let proc = c_path(&proc)?;
+    let proc_type = ffi::CString::new("proc")?;
+    let root = c_path(&spec.root)?;
+    let current_directory = ffi::CString::new(".")?;
+    let working_dir = ffi::CString::new(spec.working_dir.as_bytes())?;
+    let (uid, gid) = (spec.uid, spec.gid);
+    let mut command = process::Command::new(program);
+    command
+        .args(arguments)
+        .env_clear()
+        .stdin(process::Stdio::null());
+    if !spec
+        .env
+        .iter()
+        .any(|variable| variable.starts_with("PATH="))
+    {
+        command.env("PATH", DEFAULT_PATH);
+    }
+    for variable in &spec.env {
+        let (key, value) = variable.split_once('=').unwrap_or((variable, ""));
+        command.env(key, value);
+    }
+    // only async-signal-safe calls between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            // rather than `chroot`, which root of the namespaces could leave
+            // as long as the root of the host is mounted
+            check(libc::unshare(libc::CLONE_NEWNS))?;
+            check(libc::mount(
+                root.as_ptr(),
+                root.as_ptr(),
+                ptr::null(),
+                libc::MS_BIND | libc::MS_REC,
+                ptr::null(),
+            ))?;
+            // as PID 1 of the new PID namespace; without it, e.g. when the
+            // host masks parts of its /proc, commands just see no /proc
+            libc::mount(
+                proc_type.as_ptr(),
+                proc.as_ptr(),
+                proc_type.as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                ptr::null(),
+            );
+            check(libc::chdir(root.as_ptr()))?;
+            check(libc::syscall(
+                libc::SYS_pivot_root,
+                current_directory.as_ptr(),
+                current_directory.as_ptr(),
+            ) as libc::c_int)?;
+            // the root of the host, stacked on the new one
+            check(libc::umount2(current_directory.as_ptr(), libc::MNT_DETACH))?;
+            check(libc::chdir(working_dir.as_ptr()))?;
+            // denied with a single mapped id, which leaves no groups to drop anyway
+            libc::setgroups(0, ptr::null());
+            check(libc::setgid(gid))?;
+            check(libc::setuid(uid))?;
+            Ok(())
+        });
+    }
+    let status = command.status().map_err(|err| ProgramError {
+        program: program.clone(),
+        err,
+    })?;
+    Ok(status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::subordinate_ids;
+    use std::fs;
+
+    #[test]
+    fn find_subordinate_ids() {
+        let directory = tempfile::tempdir().unwrap();
+        let database = directory.path().join("subuid");
+        fs::write(
+            &database,
+            "other:100000:65536\nuser:165536:65536\n1001:231072:1000\n",
+        )
+        .unwrap();
+        let database = database.to_str().unwrap();
+
+        assert_eq!(
+            subordinate_ids(database, 1000, Some("user")),
+            Some((165536, 65536))
+        );
+        assert_eq!(subordinate_ids(database, 1001, None), Some((231072, 1000)));
+        assert_eq!(subordinate_ids(database, 1002, Some("nobody")), None);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::result;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path;
+
+const PASSWD_PATH: &str = "/etc/passwd";
+const GROUP_PATH: &str = "/etc/group";
+
+#[derive(Debug)]
+struct UnknownUserError {
+    kind: &'static str,
+    name: String,
+}
+
+impl fmt::Display for UnknownUserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "no {} named {:?} in the root filesystem",
+            self.kind, self.name
+        )
+    }
+}
+
+impl error::Error for UnknownUserError {}
+
+/// Fields of the `/etc/passwd` or `/etc/group` line named `name`, if any.
+fn lookup(root: &path::Path, database: &str, name: &str) -> result::Result<Option<Vec<String>>> {
+    let content = match fs::read_to_string(super::resolve(root, database, true)?) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(Box::new(err)),
+    };
+    Ok(content
+        .lines()
+        .map(|line| line.split(':').map(str::to_string).collect::<Vec<_>>())
+        .find(|fields| fields.len() >= 4 && fields[0] == name))
+}
+
+fn unknown(kind: &'static str, name: &str) -> result::BoxedError {
+    Box::new(UnknownUserError {
+        kind,
+        name: name.to_string(),
+    })
+}
+
+/// Resolves `owner`, `user[:group]` by name or number, to a uid and gid using
+/// the user and group databases of the root filesystem at `root`. Without a
+/// group, the primary group of a named user or the gid equal to a uid is used.
+pub fn owner(root: &path::Path, owner: &str) -> result::Result<(u32, u32)> {
+    let (user, group) = match owner.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (owner, None),
+    };
+    let (uid, primary_gid) = match user.parse::<u32>() {
+        Ok(uid) => (uid, uid),
+        Err(_) => {
+            let fields = lookup(root, PASSWD_PATH, user)?.ok_or_else(|| unknown("user", user))?;
+            let uid = fields[2].parse().map_err(|_| unknown("user", user))?;
+            let gid = fields[3].parse().map_err(|_| unknown("user", user))?;
+            (uid, gid)
+        }
+    };
+    let gid = match group {
+        None => primary_gid,
+        Some(group) => match group.parse::<u32>() {
+            Ok(gid) => gid,
+            Err(_) => {
+                let fields =
+                    lookup(root, GROUP_PATH, group)?.ok_or_else(|| unknown("group", group))?;
+                fields[2].parse().map_err(|_| unknown("group", group))?
+            }
+        },
+    };
+    Ok((uid, gid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::owner;
+    use std::fs;
+
+    fn root() -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("etc")).unwrap();
+        fs::write(
+            root.path().join("etc").join("passwd"),
+            "root:x:0:0:root:/root:/bin/sh\napp:x:1000:1001::/home/app:/bin/sh\n",
+        )
+        .unwrap();
+        fs::write(
+            root.path().join("etc").join("group"),
+            "root:x:0:\napp:x:1001:\nstaff:x:50:app\n",
+        )
+        .unwrap();
+        root
+    }
+
+    #[test]
+    fn resolve_numeric_owner() {
+        let root = tempfile::tempdir().unwrap();
+
+        assert_eq!(owner(root.path(), "1000").unwrap(), (1000, 1000));
+        assert_eq!(owner(root.path(), "1000:50").unwrap(), (1000, 50));
+    }
+
+    #[test]
+    fn resolve_named_owner() {
+        let root = root();
+
+        assert_eq!(owner(root.path(), "app").unwrap(), (1000, 1001));
+        assert_eq!(owner(root.path(), "app:staff").unwrap(), (1000, 50));
+        assert_eq!(owner(root.path(), "root:1001").unwrap(), (0, 1001));
+    }
+
+    #[test]
+    fn cannot_resolve_unknown_owner() {
+        let root = root();
+
+        assert!(owner(root.path(), "nobody").is_err());
+        assert!(owner(root.path(), "app:wheel").is_err());
+        assert!(owner(tempfile::tempdir().unwrap().path(), "app").is_err());
+    }
+}
@@ -1,10 +1,197 @@
+pub mod cache;
+pub mod cache_mount;
+pub mod digest;
+pub mod fsck;
+pub mod gc;
+pub mod images;
+pub mod index;
+pub mod layout;
+pub mod reference;
+
+use crate::oci::manifest;
+use crate::result;
+use serde::{de::DeserializeOwned, Serialize};
+use std::convert;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
 use std::path;
+use std::sync;
+
+/// Overrides the storage root, e.g. to keep a per-user or per-test storage.
+pub const STORAGE_ENVIRONMENT_VARIABLE: &str = "AMETHYST_STORAGE";
 
 #[cfg(target_os = "linux")]
 pub fn storage() -> std::path::PathBuf {
-    path::PathBuf::from("/var/lib/amethyst")
+    match env::var_os(STORAGE_ENVIRONMENT_VARIABLE) {
+        Some(storage) => path::PathBuf::from(storage),
+        None => path::PathBuf::from("/var/lib/amethyst"),
+    }
 }
 
 pub fn blob_storage() -> std::path::PathBuf {
-    storage().join("blob")
+    storage().join(layout::BLOB_DIRECTORY)
+}
+
+/// `blobs/<algorithm>/<encoded>`, named as in an OCI image layout; the storage
+/// itself is not one, as tags are kept in `tags.json` rather than an image index.
+pub fn blob_path(digest: &str) -> path::PathBuf {
+    layout::blob_path(&blob_storage(), digest)
+}
+
+/// Scratch space on the same filesystem as the blob storage.
+pub fn temporary_storage() -> path::PathBuf {
+    storage().join("tmp")
+}
+
+static UPDATE_LOCK: sync::Mutex<()> = sync::Mutex::new(());
+
+/// Serializes updates of the JSON documents of the storage by the threads of
+/// this process, such as concurrent builds.
+pub fn lock() -> sync::MutexGuard<'static, ()> {
+    UPDATE_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Tags the stored image `manifest_digest`, `built` or pulled, as
+/// `repository:tag` and adds it to the image database.
+pub fn tag_image(
+    repository: &str,
+    tag: &str,
+    manifest_digest: &str,
+    built: bool,
+) -> result::Result<()> {
+    let _lock = lock();
+    let index_path = index::path();
+    let mut index = index::Index::load(&index_path)?;
+    index
+        .tag(repository, tag, manifest_digest, chrono::Utc::now())
+        .built = built;
+    index.save(&index_path)?;
+    images::record(manifest_digest)
+}
+
+/// Prepares the storage, migrating it from an older layout if necessary.
+pub fn initialize() -> result::Result<()> {
+    layout::migrate(&storage())
+}
+
+fn write_blob_file(digest: &str, content: &[u8]) -> result::Result<()> {
+    digest::validate(digest)?;
+    let path = blob_path(digest);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Stores `content` in the blob storage and returns its digest.
+pub fn write_blob(content: &[u8]) -> result::Result<String> {
+    let digest = digest::sha256(content);
+    write_blob_file(&digest, content)?;
+    Ok(digest)
+}
+
+/// Moves the file at `path`, whose content hashes to `digest`, into the blob storage.
+pub fn import_blob<P>(path: P, digest: &str) -> result::Result<()>
+where
+    P: convert::AsRef<path::Path>,
+{
+    digest::validate(digest)?;
+    let blob_path = blob_path(digest);
+    if let Some(parent) = blob_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(path, blob_path)?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct DigestMismatchError {
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for DigestMismatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "content of {} hashes to {}", self.expected, self.actual)
+    }
+}
+
+impl error::Error for DigestMismatchError {}
+
+/// Stores `content` under `digest` after verifying that `content` matches it.
+pub fn write_verified_blob(digest: &str, content: &[u8]) -> result::Result<()> {
+    let actual = digest::sha256(content);
+    if actual != digest {
+        return Err(Box::new(DigestMismatchError {
+            expected: digest.to_string(),
+            actual,
+        }));
+    }
+    write_blob_file(digest, content)
+}
+
+/// Digests of all blobs in the blob storage.
+pub fn blobs() -> result::Result<Vec<String>> {
+    let blob_storage = blob_storage();
+    if !blob_storage.exists() {
+        return Ok(vec![]);
+    }
+    let mut digests = vec![];
+    for algorithm in fs::read_dir(blob_storage)? {
+        let algorithm = algorithm?;
+        if !algorithm.file_type()?.is_dir() {
+            continue;
+        }
+        for blob in fs::read_dir(algorithm.path())? {
+            let blob = blob?;
+            if blob.file_type()?.is_file() {
+                digests.push(format!(
+                    "{}:{}",
+                    algorithm.file_name().to_string_lossy(),
+                    blob.file_name().to_string_lossy()
+                ));
+            }
+        }
+    }
+    digests.sort();
+    Ok(digests)
+}
+
+pub fn read_manifest(digest: &str) -> result::Result<manifest::Manifest> {
+    Ok(serde_json::from_slice(&fs::read(blob_path(digest))?)?)
+}
+
+/// Loads a JSON document of the storage, or its default if it does not exist yet.
+pub fn load_json<P, T>(path: P) -> result::Result<T>
+where
+    P: convert::AsRef<path::Path>,
+    T: DeserializeOwned + Default,
+{
+    match fs::read(&path) {
+        Ok(content) => Ok(serde_json::from_slice(&content)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        Err(err) => Err(Box::new(err)),
+    }
+}
+
+/// Replaces a JSON document of the storage atomically.
+pub fn save_json<P, T>(path: P, value: &T) -> result::Result<()>
+where
+    P: convert::AsRef<path::Path>,
+    T: Serialize,
+{
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temporary_path = path.with_extension("json.tmp");
+    fs::write(&temporary_path, serde_json::to_vec_pretty(value)?)?;
+    fs::rename(&temporary_path, path)?;
+    Ok(())
 }
@@ -0,0 +1,310 @@
+pub mod remote;
+
+use crate::config::scriptlet;
+use crate::layer::archive;
+use crate::oci::{config, manifest};
+use crate::result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert;
+use std::fmt;
+use std::path;
+
+pub const CACHE_FILENAME: &str = "cache.json";
+
+pub fn path() -> path::PathBuf {
+    super::storage().join(CACHE_FILENAME)
+}
+
+/// File read by a scriptlet, identified by content so that touching it keeps the cache.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Source {
+    pub path: String,
+    pub digest: String,
+    pub mode: u32,
+    /// modification time in seconds, only if the scriptlet keeps it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<i64>,
+}
+
+/// Everything the layer of a build step depends on, hashed into its cache key.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Inputs {
+    /// chain ID of the layers below, empty on scratch
+    pub parent: String,
+    pub scriptlet: scriptlet::Scriptlet,
+    pub sources: Vec<Source>,
+    pub archive: archive::Options,
+    /// environment, working directory, user and shell commands are run with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<config::ContainerConfig>,
+}
+
+impl Inputs {
+    pub fn key(&self) -> result::Result<String> {
+        Ok(super::digest::sha256(&serde_json::to_vec(self)?))
+    }
+}
+
+/// Layer produced by a build step, reusable by any step with the same key.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CacheEntry {
+    pub layer: manifest::Layer,
+    pub diff_id: String,
+    pub created_at: DateTime<Utc>,
+    /// `name:tag` of the image the step belonged to
+    pub image: String,
+    /// position of the step in the scripts of the image
+    pub step: usize,
+    /// manifest digest of the base image, `scratch` if none
+    pub base: String,
+    pub inputs: Inputs,
+}
+
+impl CacheEntry {
+    /// Describes how `inputs` on `base` differ from those of this entry.
+    pub fn explain(&self, base: &str, inputs: &Inputs) -> Vec<String> {
+        let mut changes = vec![];
+        if self.base != base {
+            changes.push(format!("base image changed from {} to {}", self.base, base));
+        } else if self.inputs.parent != inputs.parent {
+            changes.push(format!(
+                "layers below changed from {} to {}",
+                display_chain_id(&self.inputs.parent),
+                display_chain_id(&inputs.parent)
+            ));
+        }
+        if self.inputs.scriptlet != inputs.scriptlet {
+            changes.push(format!(
+                "scriptlet changed from `{}` to `{}`",
+                self.inputs.scriptlet, inputs.scriptlet
+            ));
+        }
+        for source in &inputs.sources {
+            let previous = self
+                .inputs
+                .sources
+                .iter()
+                .find(|previous| previous.path == source.path);
+            match previous {
+                None => changes.push(format!("source {} is new", source.path)),
+                Some(previous) => {
+                    if previous.digest != source.digest {
+                        changes.push(format!(
+                            "source {} content changed from {} to {}",
+                            source.path, previous.digest, source.digest
+                        ));
+                    }
+                    if previous.mode != source.mode {
+                        changes.push(format!(
+                            "source {} mode changed from {:o} to {:o}",
+                            source.path, previous.mode, source.mode
+                        ));
+                    }
+                    if previous.mtime != source.mtime {
+                        changes.push(format!(
+                            "source {} modification time changed from {:?} to {:?}",
+                            source.path, previous.mtime, source.mtime
+                        ));
+                    }
+                }
+            }
+        }
+        for previous in &self.inputs.sources {
+            if !inputs
+                .sources
+                .iter()
+                .any(|source| source.path == previous.path)
+            {
+                changes.push(format!("source {} is no longer read", previous.path));
+            }
+        }
+        if self.inputs.config != inputs.config {
+            changes.push(format!(
+                "configuration changed from {} to {}",
+                display_config(&self.inputs.config),
+                display_config(&inputs.config)
+            ));
+        }
+        if self.inputs.archive.compression != inputs.archive.compression {
+            changes.push(format!(
+                "compression changed from {:?} to {:?}",
+                self.inputs.archive.compression, inputs.archive.compression
+            ));
+        }
+        if self.inputs.archive.source_date_epoch != inputs.archive.source_date_epoch {
+            changes.push(format!(
+                "SOURCE_DATE_EPOCH changed from {:?} to {:?}",
+                self.inputs.archive.source_date_epoch, inputs.archive.source_date_epoch
+            ));
+        }
+        changes
+    }
+}
+
+fn display_config(config: &Option<config::ContainerConfig>) -> String {
+    match config {
+        Some(config) => serde_json::to_string(config).unwrap_or_default(),
+        None => "none".to_string(),
+    }
+}
+
+fn display_chain_id(chain_id: &str) -> &str {
+    if chain_id.is_empty() {
+        "scratch"
+    } else {
+        chain_id
+    }
+}
+
+/// Why a step could not reuse a cached layer.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Miss {
+    Disabled,
+    NotCached,
+    /// the layer was cached but its blob is gone, e.g. by `prune`
+    LayerRemoved {
+        digest: String,
+    },
+}
+
+impl fmt::Display for Miss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Disabled => write!(f, "cache disabled by --no-cache"),
+            Self::NotCached => write!(f, "no layer cached for this step"),
+            Self::LayerRemoved { digest } => write!(f, "cached layer {} was removed", digest),
+        }
+    }
+}
+
+/// Maps build step keys to the layers they produced.
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct Cache {
+    #[serde(default)]
+    pub entries: BTreeMap<String, CacheEntry>,
+}
+
+impl Cache {
+    pub fn load<P>(path: P) -> result::Result<Self>
+    where
+        P: convert::AsRef<path::Path>,
+    {
+        super::load_json(path)
+    }
+
+    pub fn save<P>(&self, path: P) -> result::Result<()>
+    where
+        P: convert::AsRef<path::Path>,
+    {
+        super::save_json(path, self)
+    }
+
+    /// Returns the entry of `key` if its layer is still in the blob storage.
+    pub fn lookup(&self, key: &str) -> Result<&CacheEntry, Miss> {
+        let entry = self.entries.get(key).ok_or(Miss::NotCached)?;
+        if !super::blob_path(&entry.layer.digest).is_file() {
+            return Err(Miss::LayerRemoved {
+                digest: entry.layer.digest.clone(),
+            });
+        }
+        Ok(entry)
+    }
+
+    /// Most recently cached entry of the `step`th step of `image`.
+    pub fn latest(&self, image: &str, step: usize) -> Option<&CacheEntry> {
+        self.entries
+            .values()
+            .filter(|entry| entry.image == image && entry.step == step)
+            .max_by_key(|entry| entry.created_at)
+    }
+
+    pub fn insert(&mut self, key: String, entry: CacheEntry) {
+        self.entries.insert(key, entry);
+    }
+}
+
+/// Adds `entry` to the cache at `path`, reloading it so that entries recorded
+/// by concurrent builds are kept.
+pub fn record<P>(path: P, key: String, entry: CacheEntry) -> result::Result<()>
+where
+    P: convert::AsRef<path::Path>,
+{
+    let _lock = super::lock();
+    let mut cache = Cache::load(&path)?;
+    cache.insert(key, entry);
+    cache.save(&path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CacheEntry, Inputs, Source};
+    use crate::config::scriptlet::Scriptlet;
+
+    fn entry(inputs: Inputs) -> CacheEntry {
+        CacheEntry {
+            layer: crate::oci::manifest::Layer {
+                media_type: crate::oci::media_type::DOCKER_LAYER_GZIP.to_string(),
+                size: 0,
+                digest: "sha256:layer".to_string(),
+            },
+            diff_id: "sha256:diff".to_string(),
+            created_at: chrono::Utc::now(),
+            image: "image:latest".to_string(),
+            step: 0,
+            base: "scratch".to_string(),
+            inputs,
+        }
+    }
+
+    fn inputs(source: &str, destination: &str, digest: &str) -> Inputs {
+        Inputs {
+            parent: String::new(),
+            scriptlet: Scriptlet::Add {
+                source: source.to_string().into(),
+                destination: destination.to_string(),
+                owner: None,
+                mode: None,
+                preserve_timestamps: false,
+            },
+            sources: vec![Source {
+                path: source.to_string(),
+                digest: digest.to_string(),
+                mode: 0o100644,
+                mtime: None,
+            }],
+            archive: Default::default(),
+            config: None,
+        }
+    }
+
+    #[test]
+    fn explain_nothing_for_same_inputs() {
+        let inputs = inputs("./file", "/file", "sha256:a");
+
+        assert!(entry(inputs.clone()).explain("scratch", &inputs).is_empty());
+    }
+
+    #[test]
+    fn explain_changed_inputs() {
+        let previous = entry(inputs("./file", "/file", "sha256:a"));
+
+        assert_eq!(
+            previous.explain("sha256:base", &inputs("./file", "/opt/file", "sha256:b")),
+            vec![
+                "base image changed from scratch to sha256:base",
+                "scriptlet changed from `add ./file /file` to `add ./file /opt/file`",
+                "source ./file content changed from sha256:a to sha256:b",
+            ]
+        );
+        assert_eq!(
+            previous.explain("scratch", &inputs("./other", "/file", "sha256:a")),
+            vec![
+                "scriptlet changed from `add ./file /file` to `add ./other /file`",
+                "source ./other is new",
+                "source ./file is no longer read",
+            ]
+        );
+    }
+}
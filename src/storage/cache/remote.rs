@@ -0,0 +1,258 @@
+use super::Cache;
+use crate::layer::compression;
+use crate::oci::{manifest, media_type};
+use crate::registry::distribution;
+use crate::result;
+use crate::storage;
+use crate::storage::{digest, layout};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path;
+
+const OCI_LAYOUT_FILENAME: &str = "oci-layout";
+const OCI_LAYOUT_VERSION: &str = "1.0.0";
+const INDEX_FILENAME: &str = "index.json";
+
+#[derive(Debug)]
+struct NotACacheError {
+    location: String,
+}
+
+impl fmt::Display for NotACacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} holds no build cache", self.location)
+    }
+}
+
+impl error::Error for NotACacheError {}
+
+/// Where build cache is exported to and imported from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    /// OCI image layout directory
+    Directory(path::PathBuf),
+    /// OCI artifact tagged in a registry
+    Registry(distribution::Distribution),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Directory(directory) => write!(f, "{}", directory.display()),
+            Self::Registry(registry) => write!(
+                f,
+                "{}/{}:{}",
+                registry.base_url, registry.repository, registry.tag
+            ),
+        }
+    }
+}
+
+/// Parses `http(s)://<host>/<repository>[:<tag>]` as a registry and anything
+/// else as a directory relative to the current one.
+pub fn parse(value: &str) -> result::Result<Location> {
+    if value.contains("://") {
+        Ok(Location::Registry(distribution::Distribution::parse(
+            value,
+        )?))
+    } else {
+        Ok(Location::Directory(env::current_dir()?.join(value)))
+    }
+}
+
+/// `index.json` of an OCI image layout.
+#[derive(Debug, Deserialize, Serialize)]
+struct LayoutIndex {
+    #[serde(rename = "schemaVersion")]
+    schema_version: u32,
+    manifests: Vec<manifest::Config>,
+}
+
+#[derive(Debug, Serialize)]
+struct LayoutVersion {
+    #[serde(rename = "imageLayoutVersion")]
+    image_layout_version: &'static str,
+}
+
+impl Location {
+    fn read_manifest(&self) -> result::Result<Vec<u8>> {
+        match self {
+            Self::Directory(directory) => {
+                let index: LayoutIndex =
+                    serde_json::from_slice(&fs::read(directory.join(INDEX_FILENAME))?)?;
+                let descriptor = index.manifests.first().ok_or_else(|| NotACacheError {
+                    location: self.to_string(),
+                })?;
+                self.read_blob(&descriptor.digest)
+            }
+            Self::Registry(registry) => Ok(registry.fetch_manifest()?.to_vec()),
+        }
+    }
+
+    fn read_blob(&self, digest: &str) -> result::Result<Vec<u8>> {
+        match self {
+            Self::Directory(directory) => Ok(fs::read(layout::blob_path(
+                &directory.join(layout::BLOB_DIRECTORY),
+                digest,
+            ))?),
+            Self::Registry(registry) => Ok(registry.fetch_blob(digest)?.to_vec()),
+        }
+    }
+
+    fn write_blob(&self, digest: &str, content: Vec<u8>) -> result::Result<()> {
+        match self {
+            Self::Directory(directory) => {
+                digest::validate(digest)?;
+                let path = layout::blob_path(&directory.join(layout::BLOB_DIRECTORY), digest);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(path, content)?;
+                Ok(())
+            }
+            Self::Registry(registry) => registry.push_blob(digest, content),
+        }
+    }
+
+    fn write_manifest(&self, content: Vec<u8>) -> result::Result<()> {
+        match self {
+            Self::Directory(directory) => {
+                let descriptor = manifest::Config {
+                    media_type: media_type::OCI_MANIFEST.to_string(),
+                    size: content.len(),
+                    digest: digest::sha256(&content),
+                };
+                self.write_blob(&descriptor.digest, content)?;
+                let index = LayoutIndex {
+                    schema_version: 2,
+                    manifests: vec![descriptor],
+                };
+                fs::write(
+                    directory.join(OCI_LAYOUT_FILENAME),
+                    serde_json::to_vec(&LayoutVersion {
+                        image_layout_version: OCI_LAYOUT_VERSION,
+                    })?,
+                )?;
+                storage::save_json(directory.join(INDEX_FILENAME), &index)
+            }
+            Self::Registry(registry) => registry.push_manifest(content),
+        }
+    }
+}
+
+/// Writes the local cache entries `keys` and their layers to `location` and
+/// returns how many were exported.
+pub fn export(location: &Location, keys: &[String]) -> result::Result<usize> {
+    let cache = Cache::load(super::path())?;
+    let exported = Cache {
+        entries: keys
+            .iter()
+            .filter_map(|key| Some((key.clone(), cache.entries.get(key)?.clone())))
+            .collect(),
+    };
+    let mut layers = BTreeMap::new();
+    for entry in exported.entries.values() {
+        layers.insert(entry.layer.digest.clone(), entry.layer.clone());
+    }
+    for digest in layers.keys() {
+        location.write_blob(digest, fs::read(storage::blob_path(digest))?)?;
+    }
+    let config = serde_json::to_vec(&exported)?;
+    let config_digest = digest::sha256(&config);
+    let config_size = config.len();
+    location.write_blob(&config_digest, config)?;
+    let manifest = manifest::Manifest {
+        schema_version: 2,
+        media_type: media_type::OCI_MANIFEST.to_string(),
+        config: manifest::Config {
+            media_type: media_type::AMETHYST_CACHE_CONFIG.to_string(),
+            size: config_size,
+            digest: config_digest,
+        },
+        layers: layers
+            .into_values()
+            .map(|mut layer| {
+                layer.media_type = media_type::to_oci_layer(&layer.media_type).to_string();
+                layer
+            })
+            .collect(),
+    };
+    location.write_manifest(serde_json::to_vec(&manifest)?)?;
+    Ok(exported.entries.len())
+}
+
+/// Cache entries read by `import`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Imported {
+    /// not known yet, added to the local cache
+    pub count: usize,
+    /// whose key is not the one of their inputs or whose layer has another diff ID
+    pub skipped: usize,
+}
+
+/// Diff ID of the layer `content`, compressed or not.
+fn diff_id<R>(content: R) -> result::Result<String>
+where
+    R: io::Read + 'static,
+{
+    Ok(digest::sha256_reader(&mut compression::decoder(content)?)?)
+}
+
+/// Adds the cache entries at `location`, and their layers, to the local cache,
+/// unless they were not made by the step they claim to, and returns how many
+/// were added and skipped.
+pub fn import(location: &Location) -> result::Result<Imported> {
+    let manifest: manifest::Manifest = serde_json::from_slice(&location.read_manifest()?)?;
+    if manifest.config.media_type != media_type::AMETHYST_CACHE_CONFIG {
+        return Err(Box::new(NotACacheError {
+            location: location.to_string(),
+        }));
+    }
+    let config = location.read_blob(&manifest.config.digest)?;
+    let actual = digest::sha256(&config);
+    if actual != manifest.config.digest {
+        return Err(Box::new(storage::DigestMismatchError {
+            expected: manifest.config.digest,
+            actual,
+        }));
+    }
+    let imported: Cache = serde_json::from_slice(&config)?;
+
+    let path = super::path();
+    let _lock = storage::lock();
+    let mut cache = Cache::load(&path)?;
+    let mut result = Imported::default();
+    for (key, entry) in imported.entries {
+        if cache.lookup(&key).is_ok() {
+            continue;
+        }
+        if entry.inputs.key()? != key {
+            result.skipped += 1;
+            continue;
+        }
+        let blob_path = storage::blob_path(&entry.layer.digest);
+        let diff_id = if blob_path.is_file() {
+            diff_id(fs::File::open(&blob_path)?)?
+        } else {
+            let layer = location.read_blob(&entry.layer.digest)?;
+            let diff_id = diff_id(io::Cursor::new(layer.clone()))?;
+            if diff_id == entry.diff_id {
+                storage::write_verified_blob(&entry.layer.digest, &layer)?;
+            }
+            diff_id
+        };
+        if diff_id != entry.diff_id {
+            result.skipped += 1;
+            continue;
+        }
+        cache.insert(key, entry);
+        result.count += 1;
+    }
+    cache.save(&path)?;
+    Ok(result)
+}
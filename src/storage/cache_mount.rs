@@ -0,0 +1,204 @@
+use super::storage;
+use crate::config::scriptlet::Sharing;
+use crate::layer;
+use crate::result;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path;
+
+/// `<hash of the id>/id` holds the id and `<hash of the id>/<n>` the `n`th
+/// instance of the cache, locked through `<hash of the id>/<n>.lock`, whose
+/// files are owned by root and have the owners in `<hash of the id>/<n>.owners.json`
+/// as seen from commands.
+pub const CACHE_MOUNT_DIRECTORY: &str = "cache-mounts";
+
+const ID_FILENAME: &str = "id";
+
+#[derive(Debug)]
+struct UnknownCacheMountError {
+    id: String,
+}
+
+impl fmt::Display for UnknownCacheMountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no cache mount {}", self.id)
+    }
+}
+
+impl error::Error for UnknownCacheMountError {}
+
+#[derive(Debug)]
+struct CacheMountInUseError {
+    id: String,
+}
+
+impl fmt::Display for CacheMountInUseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cache mount {} is used by a running build", self.id)
+    }
+}
+
+impl error::Error for CacheMountInUseError {}
+
+pub fn directory() -> path::PathBuf {
+    storage().join(CACHE_MOUNT_DIRECTORY)
+}
+
+fn id_directory(directory: &path::Path, id: &str) -> path::PathBuf {
+    let digest = super::digest::sha256(id.as_bytes());
+    let (_, encoded) = super::digest::split(&digest).expect("sha256 digests are valid");
+    directory.join(encoded)
+}
+
+/// Takes a lock of `file`, waiting for it unless `wait` is false, in which
+/// case it returns whether it took it.
+fn lock(file: &fs::File, operation: libc::c_int, wait: bool) -> io::Result<bool> {
+    let operation = if wait {
+        operation
+    } else {
+        operation | libc::LOCK_NB
+    };
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    if err.kind() == io::ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(err)
+    }
+}
+
+fn open_lock(directory: &path::Path, instance: usize) -> io::Result<fs::File> {
+    fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(directory.join(format!("{}.lock", instance)))
+}
+
+/// Instance of a cache mount used by one command, until dropped.
+pub struct Lease {
+    path: path::PathBuf,
+    owners_path: path::PathBuf,
+    /// released when closed
+    _lock: fs::File,
+}
+
+impl Lease {
+    pub fn path(&self) -> &path::Path {
+        &self.path
+    }
+
+    pub fn owners(&self) -> result::Result<layer::Owners> {
+        super::load_json(&self.owners_path)
+    }
+
+    pub fn save_owners(&self, owners: &layer::Owners) -> result::Result<()> {
+        super::save_json(&self.owners_path, owners)
+    }
+}
+
+/// Instance of the cache mount `id` of `directory` for a command mounting it
+/// with `sharing`, created if needed.
+pub fn acquire(directory: &path::Path, id: &str, sharing: Sharing) -> result::Result<Lease> {
+    let directory = id_directory(directory, id);
+    fs::create_dir_all(&directory)?;
+    fs::write(directory.join(ID_FILENAME), id)?;
+    let mut instance = 0;
+    let lock_file = loop {
+        let file = open_lock(&directory, instance)?;
+        match sharing {
+            Sharing::Shared => {
+                lock(&file, libc::LOCK_SH, true)?;
+                break file;
+            }
+            Sharing::Locked => {
+                lock(&file, libc::LOCK_EX, true)?;
+                break file;
+            }
+            Sharing::Private => {
+                if lock(&file, libc::LOCK_EX, false)? {
+                    break file;
+                }
+                instance += 1;
+            }
+        }
+    };
+    let path = directory.join(instance.to_string());
+    fs::create_dir_all(&path)?;
+    Ok(Lease {
+        path,
+        owners_path: directory.join(format!("{}.owners.json", instance)),
+        _lock: lock_file,
+    })
+}
+
+/// Ids of the cache mounts of `directory`.
+pub fn list(directory: &path::Path) -> result::Result<Vec<String>> {
+    if !directory.exists() {
+        return Ok(vec![]);
+    }
+    let mut ids = vec![];
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        if let Ok(id) = fs::read_to_string(entry.path().join(ID_FILENAME)) {
+            ids.push(id);
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+/// Removes every instance of the cache mount `id` of `directory`, unless a
+/// build uses one.
+pub fn remove(directory: &path::Path, id: &str) -> result::Result<()> {
+    let directory = id_directory(directory, id);
+    if !directory.join(ID_FILENAME).exists() {
+        return Err(Box::new(UnknownCacheMountError { id: id.to_string() }));
+    }
+    // held until removed, so that no build starts using it meanwhile
+    let mut locks = vec![];
+    for entry in fs::read_dir(&directory)? {
+        let name = entry?.file_name();
+        let instance = match name.to_str().and_then(|name| name.parse().ok()) {
+            Some(instance) => instance,
+            None => continue,
+        };
+        let file = open_lock(&directory, instance)?;
+        if !lock(&file, libc::LOCK_EX, false)? {
+            return Err(Box::new(CacheMountInUseError { id: id.to_string() }));
+        }
+        locks.push(file);
+    }
+    fs::remove_dir_all(&directory)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{acquire, list, remove};
+    use crate::config::scriptlet::Sharing;
+
+    #[test]
+    fn share_instances_by_mode() {
+        let directory = tempfile::tempdir().unwrap();
+        let directory = directory.path();
+
+        let shared = acquire(directory, "/var/cache/apt", Sharing::Shared).unwrap();
+        let also_shared = acquire(directory, "/var/cache/apt", Sharing::Shared).unwrap();
+        assert_eq!(shared.path(), also_shared.path());
+        let private = acquire(directory, "/var/cache/apt", Sharing::Private).unwrap();
+        assert_ne!(private.path(), shared.path());
+        assert!(remove(directory, "/var/cache/apt").is_err());
+
+        drop((shared, also_shared, private));
+        assert_eq!(list(directory).unwrap(), vec!["/var/cache/apt"]);
+        remove(directory, "/var/cache/apt").unwrap();
+        assert!(list(directory).unwrap().is_empty());
+        assert!(remove(directory, "/var/cache/apt").is_err());
+    }
+}
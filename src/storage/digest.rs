@@ -0,0 +1,172 @@
+use crate::result;
+use sha2::{Digest, Sha256};
+use std::error;
+use std::fmt;
+use std::io;
+
+pub const SHA256_ALGORITHM: &str = "sha256";
+
+pub fn sha256(content: &[u8]) -> String {
+    format!(
+        "{}:{}",
+        SHA256_ALGORITHM,
+        hex::encode(Sha256::digest(content))
+    )
+}
+
+pub fn sha256_reader<R>(reader: &mut R) -> io::Result<String>
+where
+    R: io::Read,
+{
+    let mut hasher = Sha256::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(format!(
+        "{}:{}",
+        SHA256_ALGORITHM,
+        hex::encode(hasher.finalize())
+    ))
+}
+
+/// Writer passing everything through to `inner` while computing its sha256 digest.
+pub struct DigestWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<W> DigestWriter<W>
+where
+    W: io::Write,
+{
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    /// Returns the inner writer with the digest and the size of everything written.
+    pub fn finish(self) -> (W, String, u64) {
+        let digest = format!(
+            "{}:{}",
+            SHA256_ALGORITHM,
+            hex::encode(self.hasher.finalize())
+        );
+        (self.inner, digest, self.size)
+    }
+}
+
+impl<W> io::Write for DigestWriter<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Splits `<algorithm>:<encoded>` after checking both parts contain only the
+/// characters the OCI image specification allows, so they are safe as file names.
+pub fn split(digest: &str) -> Option<(&str, &str)> {
+    let (algorithm, encoded) = digest.split_once(':')?;
+    let valid_algorithm = !algorithm.is_empty()
+        && algorithm
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+._-".contains(c))
+        && !algorithm.starts_with('.');
+    let valid_encoded = !encoded.is_empty()
+        && encoded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "=_-".contains(c));
+    if valid_algorithm && valid_encoded {
+        Some((algorithm, encoded))
+    } else {
+        None
+    }
+}
+
+/// Algorithm part of `digest`, e.g. `sha256` of `sha256:<hex>`.
+pub fn algorithm(digest: &str) -> Option<&str> {
+    split(digest).map(|(algorithm, _)| algorithm)
+}
+
+#[derive(Debug)]
+pub struct InvalidDigestError {
+    pub digest: String,
+}
+
+impl fmt::Display for InvalidDigestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid digest: {:?}", self.digest)
+    }
+}
+
+impl error::Error for InvalidDigestError {}
+
+pub fn validate(digest: &str) -> result::Result<()> {
+    match split(digest) {
+        Some(_) => Ok(()),
+        None => Err(Box::new(InvalidDigestError {
+            digest: digest.to_string(),
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    const EMPTY_DIGEST: &str =
+        "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn sha256_of_empty_content() {
+        assert_eq!(super::sha256(b""), EMPTY_DIGEST);
+    }
+
+    #[test]
+    fn sha256_reader_equals_sha256() {
+        let content = b"amethyst";
+        let mut reader = &content[..];
+
+        assert_eq!(
+            super::sha256_reader(&mut reader).unwrap(),
+            super::sha256(content)
+        );
+    }
+
+    #[test]
+    fn algorithm() {
+        assert_eq!(super::algorithm(EMPTY_DIGEST), Some("sha256"));
+        assert_eq!(super::algorithm("no-algorithm"), None);
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(
+            super::split("sha256:0123abcd"),
+            Some(("sha256", "0123abcd"))
+        );
+        assert_eq!(super::split("sha256"), None);
+        assert_eq!(super::split("sha256:"), None);
+        assert_eq!(super::split("sha256:../../etc"), None);
+        assert_eq!(super::split("../sha256:abcd"), None);
+    }
+
+    #[test]
+    fn digest_writer() {
+        let mut writer = super::DigestWriter::new(vec![]);
+        std::io::Write::write_all(&mut writer, b"amethyst").unwrap();
+        let (written, digest, size) = writer.finish();
+
+        assert_eq!(written, b"amethyst");
+        assert_eq!(digest, super::sha256(b"amethyst"));
+        assert_eq!(size, 8);
+    }
+}
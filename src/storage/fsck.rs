@@ -0,0 +1,251 @@
+use super::digest;
+use super::index;
+use crate::oci::manifest;
+use crate::registry::registry;
+use crate::result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path;
+
+pub fn quarantine_storage() -> path::PathBuf {
+    super::storage().join("quarantine")
+}
+
+#[derive(Debug, Default)]
+pub struct Options {
+    /// move corrupted blobs out of the blob storage
+    pub quarantine: bool,
+    /// fetch missing or corrupted blobs of images pulled from a registry again
+    pub repair: bool,
+}
+
+#[derive(Debug)]
+pub enum Problem {
+    Corrupted {
+        digest: String,
+        actual: String,
+    },
+    Unverifiable {
+        digest: String,
+    },
+    Missing {
+        digest: String,
+        referenced_by: String,
+    },
+    UnreadableManifest {
+        digest: String,
+        referenced_by: String,
+        reason: String,
+    },
+}
+
+impl Problem {
+    pub fn digest(&self) -> &str {
+        match self {
+            Self::Corrupted { digest, .. }
+            | Self::Unverifiable { digest }
+            | Self::Missing { digest, .. }
+            | Self::UnreadableManifest { digest, .. } => digest,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Corrupted { digest, actual } => {
+                write!(f, "corrupted {} (content hashes to {})", digest, actual)
+            }
+            Self::Unverifiable { digest } => {
+                write!(f, "unverifiable {} (unsupported digest algorithm)", digest)
+            }
+            Self::Missing {
+                digest,
+                referenced_by,
+            } => write!(f, "missing {} referenced by {}", digest, referenced_by),
+            Self::UnreadableManifest {
+                digest,
+                referenced_by,
+                reason,
+            } => write!(
+                f,
+                "unreadable manifest {} referenced by {} [{}]",
+                digest, referenced_by, reason
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checked: usize,
+    pub problems: Vec<Problem>,
+    pub quarantined: Vec<String>,
+    pub repaired: BTreeSet<String>,
+    /// to repair but not pulled from a registry, e.g. layers made by builds
+    pub unrepairable: BTreeSet<String>,
+}
+
+impl Report {
+    /// Problems which are not repaired.
+    pub fn unresolved(&self) -> impl Iterator<Item = &Problem> {
+        self.problems
+            .iter()
+            .filter(|problem| !self.repaired.contains(problem.digest()))
+    }
+}
+
+fn quarantine(digest: &str) -> result::Result<()> {
+    let path = super::layout::blob_path(&quarantine_storage(), digest);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(super::blob_path(digest), path)?;
+    Ok(())
+}
+
+/// Rehashes the blob, returning the digest its content actually hashes to if it differs.
+fn verify(digest: &str) -> result::Result<Option<String>> {
+    let mut blob = fs::File::open(super::blob_path(digest))?;
+    let actual = digest::sha256_reader(&mut blob)?;
+    if actual == digest {
+        Ok(None)
+    } else {
+        Ok(Some(actual))
+    }
+}
+
+struct Checker<'a> {
+    options: &'a Options,
+    registry: &'a dyn registry::Registry,
+    report: Report,
+    corrupted: BTreeSet<String>,
+    /// repository each blob of the pulled images was pulled from
+    pulled: BTreeMap<String, String>,
+}
+
+impl<'a> Checker<'a> {
+    fn present(&self, digest: &str) -> bool {
+        super::blob_path(digest).is_file() && !self.corrupted.contains(digest)
+    }
+
+    fn repair(&mut self, digest: &str, is_manifest: bool) -> bool {
+        if self.report.repaired.contains(digest) {
+            return true;
+        }
+        if !self.options.repair {
+            return false;
+        }
+        let repository = match self.pulled.get(digest) {
+            Some(repository) => repository,
+            None => {
+                self.report.unrepairable.insert(digest.to_string());
+                return false;
+            }
+        };
+        let content = if is_manifest {
+            self.registry.fetch_manifest(repository, digest)
+        } else {
+            self.registry.fetch_blob(repository, digest)
+        };
+        let repaired = content
+            .and_then(|content| super::write_verified_blob(digest, &content))
+            .is_ok();
+        if repaired {
+            self.corrupted.remove(digest);
+            self.report.repaired.insert(digest.to_string());
+        }
+        repaired
+    }
+
+    fn check_blobs(&mut self) -> result::Result<()> {
+        for digest in super::blobs()? {
+            self.report.checked += 1;
+            if digest::algorithm(&digest) != Some(digest::SHA256_ALGORITHM) {
+                self.report.problems.push(Problem::Unverifiable { digest });
+                continue;
+            }
+            if let Some(actual) = verify(&digest)? {
+                if self.options.quarantine {
+                    quarantine(&digest)?;
+                    self.report.quarantined.push(digest.clone());
+                }
+                self.corrupted.insert(digest.clone());
+                self.report
+                    .problems
+                    .push(Problem::Corrupted { digest, actual });
+            }
+        }
+        Ok(())
+    }
+
+    fn check_reference(&mut self, referenced_by: &str, digest: &str) {
+        if self.present(digest) || self.repair(digest, false) {
+            return;
+        }
+        if !self.corrupted.contains(digest) {
+            self.report.problems.push(Problem::Missing {
+                digest: digest.to_string(),
+                referenced_by: referenced_by.to_string(),
+            });
+        }
+    }
+
+    fn check_image(&mut self, repository: &str, tag: &str, entry: &index::TagEntry) {
+        let manifest_digest = entry.digest.as_str();
+        let referenced_by = format!("{}:{}", repository, tag);
+        if !entry.built {
+            self.pulled
+                .insert(manifest_digest.to_string(), repository.to_string());
+        }
+        if !self.present(manifest_digest) && !self.repair(manifest_digest, true) {
+            if !self.corrupted.contains(manifest_digest) {
+                self.report.problems.push(Problem::Missing {
+                    digest: manifest_digest.to_string(),
+                    referenced_by,
+                });
+            }
+            return;
+        }
+        let manifest: manifest::Manifest = match super::read_manifest(manifest_digest) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                self.report.problems.push(Problem::UnreadableManifest {
+                    digest: manifest_digest.to_string(),
+                    referenced_by,
+                    reason: err.to_string(),
+                });
+                return;
+            }
+        };
+        if !entry.built {
+            for digest in manifest.blob_digests() {
+                self.pulled
+                    .insert(digest.to_string(), repository.to_string());
+            }
+        }
+        for digest in manifest.blob_digests() {
+            self.check_reference(&referenced_by, digest);
+        }
+    }
+}
+
+pub fn check(options: &Options, registry: &dyn registry::Registry) -> result::Result<Report> {
+    let index = index::Index::load(index::path())?;
+    let mut checker = Checker {
+        options,
+        registry,
+        report: Report::default(),
+        corrupted: BTreeSet::new(),
+        pulled: BTreeMap::new(),
+    };
+    checker.check_blobs()?;
+    // pulled images first, so that the base layers of built ones are known to
+    // be pulled when checking them
+    let (pulled, built): (Vec<_>, Vec<_>) = index.entries().partition(|(_, _, entry)| !entry.built);
+    for (repository, tag, entry) in pulled.into_iter().chain(built) {
+        checker.check_image(repository, tag, entry);
+    }
+    Ok(checker.report)
+}
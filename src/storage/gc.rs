@@ -0,0 +1,171 @@
+use super::{images, index};
+use crate::result;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::time;
+
+#[derive(Debug)]
+struct UnreadableManifestError {
+    image: String,
+    digest: String,
+    original_error: result::BoxedError,
+}
+
+impl fmt::Display for UnreadableManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cannot read manifest {} of {}, nothing is removed until it is pulled again or untagged [{}]",
+            self.digest, self.image, self.original_error
+        )
+    }
+}
+
+impl error::Error for UnreadableManifestError {}
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub dry_run: bool,
+    /// only unreachable blobs not modified within this duration are removed
+    pub older_than: Option<time::Duration>,
+    /// images are evicted, least recently pulled first, until the storage fits this size
+    pub max_size: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct RemovedBlob {
+    pub digest: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub removed: Vec<RemovedBlob>,
+    /// `repository:tag` of images evicted to satisfy the size budget
+    pub evicted: Vec<String>,
+    pub reclaimed: u64,
+    pub remaining: u64,
+}
+
+struct Blob {
+    size: u64,
+    modified: time::SystemTime,
+}
+
+/// Digests of the manifests tagged in `index` and of every blob they refer to.
+///
+/// Fails if a tagged manifest cannot be read, as the blobs it refers to are
+/// unknown then.
+pub fn reachable(index: &index::Index) -> result::Result<HashSet<String>> {
+    let mut reachable = HashSet::new();
+    for (repository, tag, entry) in index.entries() {
+        reachable.insert(entry.digest.clone());
+        let manifest = super::read_manifest(&entry.digest).map_err(|original_error| {
+            UnreadableManifestError {
+                image: format!("{}:{}", repository, tag),
+                digest: entry.digest.clone(),
+                original_error,
+            }
+        })?;
+        reachable.extend(manifest.blob_digests().into_iter().map(String::from));
+    }
+    Ok(reachable)
+}
+
+fn blobs() -> result::Result<HashMap<String, Blob>> {
+    let mut blobs = HashMap::new();
+    for digest in super::blobs()? {
+        let metadata = fs::metadata(super::blob_path(&digest))?;
+        let blob = Blob {
+            size: metadata.len(),
+            modified: metadata.modified()?,
+        };
+        blobs.insert(digest, blob);
+    }
+    Ok(blobs)
+}
+
+pub fn collect(options: &Options) -> result::Result<Report> {
+    let index_path = index::path();
+    let mut index = index::Index::load(&index_path)?;
+    let blobs = blobs()?;
+    let now = time::SystemTime::now();
+
+    let mut reachable = reachable(&index)?;
+    let mut removed = BTreeSet::new();
+    for (digest, blob) in &blobs {
+        if reachable.contains(digest) {
+            continue;
+        }
+        let old_enough = match options.older_than {
+            Some(older_than) => now
+                .duration_since(blob.modified)
+                .map(|age| age >= older_than)
+                .unwrap_or(false),
+            None => true,
+        };
+        if old_enough {
+            removed.insert(digest.clone());
+        }
+    }
+
+    let mut evicted = vec![];
+    let mut remaining: u64 = blobs
+        .iter()
+        .filter(|(digest, _)| !removed.contains(*digest))
+        .map(|(_, blob)| blob.size)
+        .sum();
+    if let Some(max_size) = options.max_size {
+        let mut least_recently_pulled = index
+            .entries()
+            .map(|(repository, tag, entry)| {
+                (entry.pulled_at, repository.to_string(), tag.to_string())
+            })
+            .collect::<Vec<_>>();
+        least_recently_pulled.sort();
+        for (_, repository, tag) in least_recently_pulled {
+            if remaining <= max_size {
+                break;
+            }
+            index.untag(&repository, &tag);
+            evicted.push(format!("{}:{}", repository, tag));
+            let still_reachable = self::reachable(&index)?;
+            for digest in reachable.difference(&still_reachable) {
+                if let Some(blob) = blobs.get(digest) {
+                    if removed.insert(digest.clone()) {
+                        remaining -= blob.size;
+                    }
+                }
+            }
+            reachable = still_reachable;
+        }
+    }
+
+    let mut report = Report {
+        evicted,
+        remaining,
+        ..Default::default()
+    };
+    for digest in removed {
+        let size = blobs[&digest].size;
+        if !options.dry_run {
+            fs::remove_file(super::blob_path(&digest))?;
+        }
+        report.reclaimed += size;
+        report.removed.push(RemovedBlob { digest, size });
+    }
+    if !options.dry_run && !report.evicted.is_empty() {
+        index.save(&index_path)?;
+    }
+    if !options.dry_run && !report.removed.is_empty() {
+        let images_path = images::path();
+        let mut database = images::ImageDatabase::load(&images_path)?;
+        for blob in &report.removed {
+            database.images.remove(&blob.digest);
+        }
+        database.save(&images_path)?;
+    }
+    Ok(report)
+}
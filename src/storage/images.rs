@@ -0,0 +1,86 @@
+use crate::oci::config;
+use crate::result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert;
+use std::fs;
+use std::path;
+
+pub const IMAGES_FILENAME: &str = "images.json";
+
+pub fn path() -> path::PathBuf {
+    super::storage().join(IMAGES_FILENAME)
+}
+
+/// What is known about a stored image without reading its blobs.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ImageRecord {
+    /// digest of the image configuration blob
+    pub config: String,
+    /// size of the configuration and every layer
+    pub size: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub architecture: String,
+    #[serde(default)]
+    pub os: String,
+}
+
+impl ImageRecord {
+    /// Reads the manifest `manifest_digest` and its configuration from the blob storage.
+    pub fn describe(manifest_digest: &str) -> result::Result<Self> {
+        let manifest = super::read_manifest(manifest_digest)?;
+        let config: config::ImageConfig =
+            serde_json::from_slice(&fs::read(super::blob_path(&manifest.config.digest))?)?;
+        Ok(Self {
+            config: manifest.config.digest.clone(),
+            size: manifest.size(),
+            created: config.created,
+            architecture: config.architecture,
+            os: config.os,
+        })
+    }
+}
+
+/// Maps manifest digests to their `ImageRecord`.
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct ImageDatabase {
+    #[serde(default)]
+    pub images: BTreeMap<String, ImageRecord>,
+}
+
+impl ImageDatabase {
+    pub fn load<P>(path: P) -> result::Result<Self>
+    where
+        P: convert::AsRef<path::Path>,
+    {
+        super::load_json(path)
+    }
+
+    pub fn save<P>(&self, path: P) -> result::Result<()>
+    where
+        P: convert::AsRef<path::Path>,
+    {
+        super::save_json(path, self)
+    }
+
+    /// Returns the record of `manifest_digest`, describing and adding it if unknown.
+    pub fn get_or_describe(&mut self, manifest_digest: &str) -> result::Result<&ImageRecord> {
+        if !self.images.contains_key(manifest_digest) {
+            let record = ImageRecord::describe(manifest_digest)?;
+            self.images.insert(manifest_digest.to_string(), record);
+        }
+        Ok(&self.images[manifest_digest])
+    }
+}
+
+/// Adds the stored image `manifest_digest` to the image database.
+pub fn record(manifest_digest: &str) -> result::Result<()> {
+    let path = path();
+    let mut database = ImageDatabase::load(&path)?;
+    let record = ImageRecord::describe(manifest_digest)?;
+    database.images.insert(manifest_digest.to_string(), record);
+    database.save(&path)
+}
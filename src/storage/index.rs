@@ -0,0 +1,194 @@
+use crate::result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert;
+use std::path;
+
+pub const INDEX_FILENAME: &str = "tags.json";
+
+pub fn path() -> path::PathBuf {
+    super::storage().join(INDEX_FILENAME)
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct HistoryEntry {
+    pub digest: String,
+    pub pulled_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct TagEntry {
+    pub digest: String,
+    pub pulled_at: DateTime<Utc>,
+    /// previously tagged digests, oldest first
+    #[serde(default)]
+    pub history: Vec<HistoryEntry>,
+    /// made by a build rather than pulled from a registry
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub built: bool,
+}
+
+/// Maps `repository:tag` to the digest of the manifest stored in the blob storage.
+#[derive(Debug, Deserialize, Serialize, Default)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct Index {
+    #[serde(default)]
+    pub repositories: BTreeMap<String, BTreeMap<String, TagEntry>>,
+}
+
+impl Index {
+    pub fn load<P>(path: P) -> result::Result<Self>
+    where
+        P: convert::AsRef<path::Path>,
+    {
+        super::load_json(path)
+    }
+
+    pub fn save<P>(&self, path: P) -> result::Result<()>
+    where
+        P: convert::AsRef<path::Path>,
+    {
+        super::save_json(path, self)
+    }
+
+    /// Points `repository:tag` to `digest`, keeping the previous digest in the
+    /// history, and returns its entry.
+    pub fn tag(
+        &mut self,
+        repository: &str,
+        tag: &str,
+        digest: &str,
+        at: DateTime<Utc>,
+    ) -> &mut TagEntry {
+        let tags = self.repositories.entry(repository.to_string()).or_default();
+        let entry = tags.entry(tag.to_string()).or_insert_with(|| TagEntry {
+            digest: digest.to_string(),
+            pulled_at: at,
+            history: vec![],
+            built: false,
+        });
+        if entry.digest != digest {
+            entry.history.push(HistoryEntry {
+                digest: entry.digest.clone(),
+                pulled_at: entry.pulled_at,
+            });
+            entry.digest = digest.to_string();
+        }
+        entry.pulled_at = at;
+        entry
+    }
+
+    pub fn untag(&mut self, repository: &str, tag: &str) -> Option<TagEntry> {
+        let tags = self.repositories.get_mut(repository)?;
+        let entry = tags.remove(tag);
+        if tags.is_empty() {
+            self.repositories.remove(repository);
+        }
+        entry
+    }
+
+    /// Every `(repository, tag, entry)`, ordered by repository and tag.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str, &TagEntry)> {
+        self.repositories.iter().flat_map(|(repository, tags)| {
+            tags.iter()
+                .map(move |(tag, entry)| (repository.as_str(), tag.as_str(), entry))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Index;
+    use chrono::Utc;
+
+    #[test]
+    fn load_missing_index_as_empty() {
+        let directory = tempfile::tempdir().unwrap();
+        let index = Index::load(directory.path().join("tags.json"));
+
+        assert!(index.is_ok());
+        assert_eq!(index.unwrap(), Index::default());
+    }
+
+    #[test]
+    fn cannot_load_broken_index() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"broken index").unwrap();
+
+        assert!(Index::load(file.path()).is_err());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tags.json");
+        let mut index = Index::default();
+        index.tag("library/ubuntu", "latest", "sha256:a", Utc::now());
+
+        assert!(index.save(&path).is_ok());
+        assert_eq!(Index::load(&path).unwrap(), index);
+    }
+
+    mod tag_method {
+        use super::super::{HistoryEntry, Index};
+        use chrono::{TimeZone, Utc};
+
+        #[test]
+        fn tag_new_repository() {
+            let at = Utc.timestamp_opt(1, 0).unwrap();
+            let mut index = Index::default();
+            index.tag("library/ubuntu", "latest", "sha256:a", at);
+
+            let entry = &index.repositories["library/ubuntu"]["latest"];
+            assert_eq!(entry.digest, "sha256:a");
+            assert_eq!(entry.pulled_at, at);
+            assert!(entry.history.is_empty());
+        }
+
+        #[test]
+        fn retag_with_same_digest() {
+            let first = Utc.timestamp_opt(1, 0).unwrap();
+            let second = Utc.timestamp_opt(2, 0).unwrap();
+            let mut index = Index::default();
+            index.tag("library/ubuntu", "latest", "sha256:a", first);
+            index.tag("library/ubuntu", "latest", "sha256:a", second);
+
+            let entry = &index.repositories["library/ubuntu"]["latest"];
+            assert_eq!(entry.pulled_at, second);
+            assert!(entry.history.is_empty());
+        }
+
+        #[test]
+        fn retag_with_other_digest() {
+            let first = Utc.timestamp_opt(1, 0).unwrap();
+            let second = Utc.timestamp_opt(2, 0).unwrap();
+            let mut index = Index::default();
+            index.tag("library/ubuntu", "latest", "sha256:a", first);
+            index.tag("library/ubuntu", "latest", "sha256:b", second);
+
+            let entry = &index.repositories["library/ubuntu"]["latest"];
+            assert_eq!(entry.digest, "sha256:b");
+            assert_eq!(entry.pulled_at, second);
+            assert_eq!(
+                entry.history,
+                vec![HistoryEntry {
+                    digest: "sha256:a".to_string(),
+                    pulled_at: first,
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn untag_last_tag_of_repository() {
+        let mut index = Index::default();
+        index.tag("library/ubuntu", "latest", "sha256:a", Utc::now());
+
+        assert!(index.untag("library/ubuntu", "latest").is_some());
+        assert!(index.untag("library/ubuntu", "latest").is_none());
+        assert!(index.repositories.is_empty());
+    }
+}
@@ -0,0 +1,281 @@
+use super::digest;
+use super::index;
+use crate::result;
+use chrono::{DateTime, Utc};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path;
+
+pub const BLOB_DIRECTORY: &str = "blobs";
+pub const VERSION_FILENAME: &str = "layout-version";
+pub const CURRENT_VERSION: u32 = 3;
+
+/// `blob/<algorithm>:<encoded>`, used until version 2
+const LEGACY_BLOB_DIRECTORY: &str = "blob";
+/// `docker/<repository>/<tag>/manifest.json`, used until version 2
+const LEGACY_MANIFEST_DIRECTORY: &str = "docker";
+const LEGACY_MANIFEST_FILENAME: &str = "manifest.json";
+/// tag index, used until version 3; renamed as it is not an OCI image index
+const LEGACY_INDEX_FILENAME: &str = "index.json";
+
+/// Migrations from version `n` to `n + 1`, where `n` is the index plus one.
+const MIGRATIONS: [fn(&path::Path) -> result::Result<()>; 2] =
+    [migrate_to_sharded_blobs, migrate_to_tag_index];
+
+#[derive(Debug)]
+struct UnsupportedLayoutError {
+    version: u32,
+}
+
+impl fmt::Display for UnsupportedLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.version > CURRENT_VERSION {
+            write!(
+                f,
+                "storage layout version {} is newer than supported version {}",
+                self.version, CURRENT_VERSION
+            )
+        } else {
+            write!(f, "storage layout version {} does not exist", self.version)
+        }
+    }
+}
+
+impl error::Error for UnsupportedLayoutError {}
+
+pub fn blob_path(blob_storage: &path::Path, digest: &str) -> path::PathBuf {
+    match digest::split(digest) {
+        Some((algorithm, encoded)) => blob_storage.join(algorithm).join(encoded),
+        // never written, see `digest::validate`
+        None => blob_storage.join(digest.replace(|c: char| !c.is_ascii_alphanumeric(), "_")),
+    }
+}
+
+fn read_version(root: &path::Path) -> result::Result<Option<u32>> {
+    match fs::read_to_string(root.join(VERSION_FILENAME)) {
+        Ok(version) => Ok(Some(version.trim().parse()?)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Box::new(err)),
+    }
+}
+
+fn write_version(root: &path::Path, version: u32) -> result::Result<()> {
+    fs::create_dir_all(root)?;
+    fs::write(root.join(VERSION_FILENAME), format!("{}\n", version))?;
+    Ok(())
+}
+
+/// Brings the storage under `root` up to `CURRENT_VERSION`.
+pub fn migrate(root: &path::Path) -> result::Result<()> {
+    let version = match read_version(root)? {
+        Some(version) => version,
+        None if root.join(LEGACY_BLOB_DIRECTORY).exists()
+            || root.join(LEGACY_MANIFEST_DIRECTORY).exists() =>
+        {
+            1
+        }
+        None => return write_version(root, CURRENT_VERSION),
+    };
+    if !(1..=CURRENT_VERSION).contains(&version) {
+        return Err(Box::new(UnsupportedLayoutError { version }));
+    }
+    for version in version..CURRENT_VERSION {
+        MIGRATIONS[version as usize - 1](root)?;
+        write_version(root, version + 1)?;
+    }
+    Ok(())
+}
+
+fn migrate_to_sharded_blobs(root: &path::Path) -> result::Result<()> {
+    let blob_storage = root.join(BLOB_DIRECTORY);
+    let legacy_blob_storage = root.join(LEGACY_BLOB_DIRECTORY);
+    if legacy_blob_storage.exists() {
+        for entry in fs::read_dir(&legacy_blob_storage)? {
+            let entry = entry?;
+            let digest = entry.file_name().to_string_lossy().to_string();
+            if digest::split(&digest).is_none() || !entry.file_type()?.is_file() {
+                continue;
+            }
+            let path = blob_path(&blob_storage, &digest);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(entry.path(), path)?;
+        }
+        // keeps the directory if unknown files are left
+        let _ = fs::remove_dir(&legacy_blob_storage);
+    }
+
+    let legacy_manifest_storage = root.join(LEGACY_MANIFEST_DIRECTORY);
+    if legacy_manifest_storage.exists() {
+        let index_path = root.join(LEGACY_INDEX_FILENAME);
+        let mut index = index::Index::load(&index_path)?;
+        import_legacy_manifests(
+            &legacy_manifest_storage,
+            &legacy_manifest_storage,
+            &blob_storage,
+            &mut index,
+        )?;
+        index.save(&index_path)?;
+        fs::remove_dir_all(&legacy_manifest_storage)?;
+    }
+    Ok(())
+}
+
+fn migrate_to_tag_index(root: &path::Path) -> result::Result<()> {
+    match fs::rename(
+        root.join(LEGACY_INDEX_FILENAME),
+        root.join(index::INDEX_FILENAME),
+    ) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(Box::new(err)),
+        _ => Ok(()),
+    }
+}
+
+fn import_legacy_manifests(
+    legacy_manifest_storage: &path::Path,
+    directory: &path::Path,
+    blob_storage: &path::Path,
+    index: &mut index::Index,
+) -> result::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            import_legacy_manifests(legacy_manifest_storage, &entry.path(), blob_storage, index)?;
+            continue;
+        }
+        if entry.file_name() != LEGACY_MANIFEST_FILENAME {
+            continue;
+        }
+        let tag_directory = match directory.strip_prefix(legacy_manifest_storage) {
+            Ok(tag_directory) => tag_directory,
+            Err(_) => continue,
+        };
+        let (repository, tag) = match (tag_directory.parent(), tag_directory.file_name()) {
+            (Some(repository), Some(tag)) if !repository.as_os_str().is_empty() => (
+                repository.to_string_lossy().to_string(),
+                tag.to_string_lossy().to_string(),
+            ),
+            _ => continue,
+        };
+        let manifest = fs::read(entry.path())?;
+        let digest = digest::sha256(&manifest);
+        let path = blob_path(blob_storage, &digest);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, &manifest)?;
+        let pulled_at: DateTime<Utc> = entry.metadata()?.modified()?.into();
+        index.tag(&repository, &tag, &digest, pulled_at);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{migrate, read_version, CURRENT_VERSION, VERSION_FILENAME};
+    use std::fs;
+
+    #[test]
+    fn initialize_fresh_storage() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path().join("storage");
+
+        assert!(migrate(&root).is_ok());
+        assert_eq!(read_version(&root).unwrap(), Some(CURRENT_VERSION));
+    }
+
+    #[test]
+    fn cannot_migrate_newer_storage() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(
+            root.path().join(VERSION_FILENAME),
+            format!("{}", CURRENT_VERSION + 1),
+        )
+        .unwrap();
+
+        assert!(migrate(root.path()).is_err());
+    }
+
+    #[test]
+    fn cannot_migrate_version_zero() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join(VERSION_FILENAME), "0").unwrap();
+
+        assert!(migrate(root.path()).is_err());
+    }
+
+    #[test]
+    fn migrate_flat_blobs() {
+        let root = tempfile::tempdir().unwrap();
+        let legacy_blob_storage = root.path().join("blob");
+        fs::create_dir_all(&legacy_blob_storage).unwrap();
+        fs::write(legacy_blob_storage.join("sha256:abcd"), "blob").unwrap();
+
+        assert!(migrate(root.path()).is_ok());
+        assert_eq!(
+            fs::read_to_string(root.path().join("blobs").join("sha256").join("abcd")).unwrap(),
+            "blob"
+        );
+        assert!(!legacy_blob_storage.exists());
+        assert_eq!(read_version(root.path()).unwrap(), Some(CURRENT_VERSION));
+    }
+
+    #[test]
+    fn migrate_tag_directories() {
+        let root = tempfile::tempdir().unwrap();
+        let tag_directory = root
+            .path()
+            .join("docker")
+            .join("library")
+            .join("ubuntu")
+            .join("latest");
+        fs::create_dir_all(&tag_directory).unwrap();
+        fs::write(tag_directory.join("manifest.json"), "{}").unwrap();
+
+        assert!(migrate(root.path()).is_ok());
+
+        let index = crate::storage::index::Index::load(root.path().join("tags.json")).unwrap();
+        let digest = crate::storage::digest::sha256(b"{}");
+        assert_eq!(
+            index.repositories["library/ubuntu"]["latest"].digest,
+            digest
+        );
+        assert!(super::blob_path(&root.path().join("blobs"), &digest).exists());
+        assert!(!root.path().join("docker").exists());
+        assert!(!root.path().join("index.json").exists());
+    }
+
+    #[test]
+    fn migrate_tag_index() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join(VERSION_FILENAME), "2").unwrap();
+        fs::write(root.path().join("index.json"), "{}").unwrap();
+
+        assert!(migrate(root.path()).is_ok());
+        assert_eq!(
+            fs::read_to_string(root.path().join("tags.json")).unwrap(),
+            "{}"
+        );
+        assert!(!root.path().join("index.json").exists());
+        assert_eq!(read_version(root.path()).unwrap(), Some(CURRENT_VERSION));
+    }
+
+    #[test]
+    fn keep_migrated_storage() {
+        let root = tempfile::tempdir().unwrap();
+        let blob = root.path().join("blobs").join("sha256").join("abcd");
+        fs::create_dir_all(blob.parent().unwrap()).unwrap();
+        fs::write(&blob, "blob").unwrap();
+        fs::write(
+            root.path().join(VERSION_FILENAME),
+            format!("{}", CURRENT_VERSION),
+        )
+        .unwrap();
+
+        assert!(migrate(root.path()).is_ok());
+        assert!(blob.exists());
+    }
+}
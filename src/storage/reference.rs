@@ -0,0 +1,215 @@
+use super::digest;
+use super::index;
+use crate::config::image::tag;
+use crate::result;
+use std::error;
+use std::fmt;
+
+const OFFICIAL_REPOSITORY_PREFIX: &str = "library/";
+const MINIMUM_DIGEST_PREFIX_LENGTH: usize = 4;
+
+#[derive(Debug)]
+enum ReferenceError {
+    Unknown { reference: String },
+    Ambiguous { reference: String },
+}
+
+impl fmt::Display for ReferenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown { reference } => write!(f, "no such image: {}", reference),
+            Self::Ambiguous { reference } => {
+                write!(f, "{} matches more than one image digest", reference)
+            }
+        }
+    }
+}
+
+impl error::Error for ReferenceError {}
+
+/// Image in the tag index referred to either by one of its tags or by its digest.
+#[derive(Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+pub enum Resolved {
+    Tag {
+        repository: String,
+        tag: String,
+        digest: String,
+    },
+    Digest {
+        digest: String,
+    },
+}
+
+impl Resolved {
+    pub fn digest(&self) -> &str {
+        match self {
+            Self::Tag { digest, .. } | Self::Digest { digest } => digest,
+        }
+    }
+}
+
+/// Splits `repository[:tag]`, keeping a registry port such as `localhost:5000/name` intact.
+pub fn split(reference: &str) -> (&str, &str) {
+    match reference.rsplit_once(':') {
+        Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+        _ => (reference, tag::LATEST_TAG),
+    }
+}
+
+fn resolve_digest(index: &index::Index, reference: &str) -> result::Result<Option<String>> {
+    let prefix = match digest::split(reference) {
+        Some((digest::SHA256_ALGORITHM, encoded)) => encoded,
+        Some(_) => return Ok(None),
+        None => reference,
+    };
+    if prefix.len() < MINIMUM_DIGEST_PREFIX_LENGTH || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Ok(None);
+    }
+    let mut candidates = index
+        .entries()
+        .map(|(_, _, entry)| entry.digest.as_str())
+        .filter(|digest| match digest::split(digest) {
+            Some((digest::SHA256_ALGORITHM, encoded)) => encoded.starts_with(prefix),
+            _ => false,
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_unstable();
+    candidates.dedup();
+    match candidates.as_slice() {
+        [] => Ok(None),
+        [digest] => Ok(Some(digest.to_string())),
+        _ => Err(Box::new(ReferenceError::Ambiguous {
+            reference: reference.to_string(),
+        })),
+    }
+}
+
+/// Resolves `repository[:tag]`, a digest or a unique digest prefix. Docker Hub
+/// official images may be referred to without their `library/` prefix.
+///
+/// Repositories come first, so that one named with hexadecimal digits only is
+/// not taken for the prefix of another image digest.
+pub fn resolve(index: &index::Index, reference: &str) -> result::Result<Resolved> {
+    let (repository, tag) = split(reference);
+    let official_repository = format!("{}{}", OFFICIAL_REPOSITORY_PREFIX, repository);
+    for repository in [repository, official_repository.as_str()] {
+        if let Some(entry) = index
+            .repositories
+            .get(repository)
+            .and_then(|tags| tags.get(tag))
+        {
+            return Ok(Resolved::Tag {
+                repository: repository.to_string(),
+                tag: tag.to_string(),
+                digest: entry.digest.clone(),
+            });
+        }
+    }
+    if let Some(digest) = resolve_digest(index, reference)? {
+        return Ok(Resolved::Digest { digest });
+    }
+    Err(Box::new(ReferenceError::Unknown {
+        reference: reference.to_string(),
+    }))
+}
+
+/// `repository:tag` of every tag pointing to `digest`.
+pub fn tags_of(index: &index::Index, digest: &str) -> Vec<(String, String)> {
+    index
+        .entries()
+        .filter(|(_, _, entry)| entry.digest == digest)
+        .map(|(repository, tag, _)| (repository.to_string(), tag.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve, split, Resolved};
+    use crate::storage::index::Index;
+    use chrono::Utc;
+
+    const UBUNTU_DIGEST: &str =
+        "sha256:aaaa0000000000000000000000000000000000000000000000000000000000000";
+    const DEBIAN_DIGEST: &str =
+        "sha256:aaaa1000000000000000000000000000000000000000000000000000000000000";
+
+    fn index() -> Index {
+        let mut index = Index::default();
+        index.tag("library/ubuntu", "latest", UBUNTU_DIGEST, Utc::now());
+        index.tag("library/debian", "11", DEBIAN_DIGEST, Utc::now());
+        index.tag("localhost:5000/app", "latest", DEBIAN_DIGEST, Utc::now());
+        index
+    }
+
+    #[test]
+    fn split_reference() {
+        assert_eq!(split("ubuntu"), ("ubuntu", "latest"));
+        assert_eq!(split("ubuntu:22.04"), ("ubuntu", "22.04"));
+        assert_eq!(
+            split("localhost:5000/app"),
+            ("localhost:5000/app", "latest")
+        );
+        assert_eq!(split("localhost:5000/app:1"), ("localhost:5000/app", "1"));
+    }
+
+    #[test]
+    fn resolve_official_image_name() {
+        assert_eq!(
+            resolve(&index(), "ubuntu").unwrap(),
+            Resolved::Tag {
+                repository: "library/ubuntu".to_string(),
+                tag: "latest".to_string(),
+                digest: UBUNTU_DIGEST.to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn resolve_repository_with_port() {
+        assert_eq!(
+            resolve(&index(), "localhost:5000/app").unwrap().digest(),
+            DEBIAN_DIGEST
+        );
+    }
+
+    #[test]
+    fn resolve_digest() {
+        assert_eq!(
+            resolve(&index(), UBUNTU_DIGEST).unwrap(),
+            Resolved::Digest {
+                digest: UBUNTU_DIGEST.to_string()
+            }
+        );
+        assert_eq!(resolve(&index(), "aaaa1").unwrap().digest(), DEBIAN_DIGEST);
+    }
+
+    #[test]
+    fn resolve_hexadecimal_repository_before_digest_prefix() {
+        let mut index = index();
+        index.tag("aaaa", "latest", UBUNTU_DIGEST, Utc::now());
+
+        assert_eq!(
+            resolve(&index, "aaaa").unwrap(),
+            Resolved::Tag {
+                repository: "aaaa".to_string(),
+                tag: "latest".to_string(),
+                digest: UBUNTU_DIGEST.to_string(),
+            }
+        );
+        assert_eq!(resolve(&index, "aaaa1").unwrap().digest(), DEBIAN_DIGEST);
+    }
+
+    #[test]
+    fn cannot_resolve_ambiguous_digest_prefix() {
+        assert!(resolve(&index(), "aaaa").is_err());
+        assert!(resolve(&index(), "sha256:aaaa").is_err());
+    }
+
+    #[test]
+    fn cannot_resolve_unknown_image() {
+        assert!(resolve(&index(), "ubuntu:20.04").is_err());
+        assert!(resolve(&index(), "fedora").is_err());
+    }
+}
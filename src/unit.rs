@@ -0,0 +1,144 @@
+use crate::result;
+use std::error;
+use std::fmt;
+use std::time;
+
+#[derive(Debug)]
+struct ParseUnitError {
+    value: String,
+    expected: &'static str,
+}
+
+impl fmt::Display for ParseUnitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot parse {:?}: expected {}",
+            self.value, self.expected
+        )
+    }
+}
+
+impl error::Error for ParseUnitError {}
+
+fn split_number(value: &str) -> Option<(u64, &str)> {
+    let value = value.trim();
+    let index = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let number = value[..index].parse().ok()?;
+    Some((number, value[index..].trim()))
+}
+
+const SIZE_UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+/// Parses sizes such as `512`, `100M` or `10GiB`. Units are 1024-based.
+pub fn parse_size(value: &str) -> result::Result<u64> {
+    let error = || -> result::BoxedError {
+        Box::new(ParseUnitError {
+            value: value.to_string(),
+            expected: "size such as 512, 100M or 10GiB",
+        })
+    };
+    let (number, unit) = split_number(value).ok_or_else(error)?;
+    let exponent = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 1,
+        "M" | "MB" | "MIB" => 2,
+        "G" | "GB" | "GIB" => 3,
+        "T" | "TB" | "TIB" => 4,
+        _ => return Err(error()),
+    };
+    number.checked_mul(1024u64.pow(exponent)).ok_or_else(error)
+}
+
+pub fn format_size(size: u64) -> String {
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < SIZE_UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", size, SIZE_UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, SIZE_UNITS[unit])
+    }
+}
+
+/// Parses durations such as `90`, `30m`, `24h` or `7d`. Plain numbers are seconds.
+pub fn parse_duration(value: &str) -> result::Result<time::Duration> {
+    let error = || -> result::BoxedError {
+        Box::new(ParseUnitError {
+            value: value.to_string(),
+            expected: "duration such as 90s, 30m, 24h or 7d",
+        })
+    };
+    let (number, unit) = split_number(value).ok_or_else(error)?;
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(error()),
+    };
+    Ok(time::Duration::from_secs(
+        number.checked_mul(seconds).ok_or_else(error)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    mod parse_size {
+        use super::super::parse_size;
+
+        #[test]
+        fn parsable() {
+            assert_eq!(parse_size("512").unwrap(), 512);
+            assert_eq!(parse_size("2K").unwrap(), 2 * 1024);
+            assert_eq!(parse_size("100MB").unwrap(), 100 * 1024 * 1024);
+            assert_eq!(parse_size("10GiB").unwrap(), 10 * 1024 * 1024 * 1024);
+        }
+
+        #[test]
+        fn unparsable() {
+            assert!(parse_size("").is_err());
+            assert!(parse_size("G").is_err());
+            assert!(parse_size("10X").is_err());
+            assert!(parse_size("99999999999T").is_err());
+        }
+    }
+
+    mod parse_duration {
+        use super::super::parse_duration;
+        use std::time;
+
+        #[test]
+        fn parsable() {
+            assert_eq!(parse_duration("90").unwrap(), time::Duration::from_secs(90));
+            assert_eq!(
+                parse_duration("30m").unwrap(),
+                time::Duration::from_secs(30 * 60)
+            );
+            assert_eq!(
+                parse_duration("7d").unwrap(),
+                time::Duration::from_secs(7 * 24 * 60 * 60)
+            );
+        }
+
+        #[test]
+        fn unparsable() {
+            assert!(parse_duration("d").is_err());
+            assert!(parse_duration("1y").is_err());
+        }
+    }
+
+    #[test]
+    fn format_size() {
+        assert_eq!(super::format_size(0), "0 B");
+        assert_eq!(super::format_size(1023), "1023 B");
+        assert_eq!(super::format_size(1536), "1.5 KiB");
+        assert_eq!(super::format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+}
@@ -1,3 +1,6 @@
+mod common;
+
+use predicates::prelude::*;
 use std::convert;
 use std::path;
 
@@ -10,13 +13,53 @@ fn get_config_directory<P: convert::AsRef<path::Path>>(test_name: P) -> path::Pa
         .join(test_name)
 }
 
+/// Copy of the configuration directory `test_name`, for tests changing its files.
+fn copy_config_directory<P: convert::AsRef<path::Path>>(test_name: P) -> tempfile::TempDir {
+    let copy = tempfile::tempdir().unwrap();
+    for entry in std::fs::read_dir(get_config_directory(test_name)).unwrap() {
+        let entry = entry.unwrap();
+        std::fs::copy(entry.path(), copy.path().join(entry.file_name())).unwrap();
+    }
+    copy
+}
+
+/// Configuration directory of an image `app` holding the shell of the host and
+/// the libraries it links, followed by `scripts`, a YAML list.
+fn shell_config_directory(scripts: &str) -> tempfile::TempDir {
+    let ldd = std::process::Command::new("ldd")
+        .arg("/bin/sh")
+        .output()
+        .unwrap();
+    let mut files = vec!["/bin/sh".to_string()];
+    files.extend(
+        String::from_utf8(ldd.stdout)
+            .unwrap()
+            .split_whitespace()
+            .filter(|word| word.starts_with('/'))
+            .map(str::to_string),
+    );
+    let mut config = "image:\n  - name: app\n    scripts:\n".to_string();
+    for file in files {
+        config.push_str(&format!(
+            "      - {{ type: add, source: {:?}, destination: {:?} }}\n",
+            file, file
+        ));
+    }
+    for line in scripts.lines() {
+        config.push_str(&format!("      {}\n", line));
+    }
+    let directory = tempfile::tempdir().unwrap();
+    std::fs::write(directory.path().join("amethyst.yaml"), config).unwrap();
+    directory
+}
+
 mod io_error {
     #[test]
     fn cannot_run_build_command_in_empty_directory() {
         let empty_directory = super::get_config_directory("empty");
         let mut program = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).expect("program");
         program
-            .args(&["build", empty_directory.to_str().unwrap()])
+            .args(["build", empty_directory.to_str().unwrap()])
             .assert()
             .failure();
     }
@@ -26,7 +69,7 @@ mod io_error {
         let non_config_directory = super::get_config_directory("non-config");
         let mut program = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).expect("program");
         program
-            .args(&["build", non_config_directory.to_str().unwrap()])
+            .args(["build", non_config_directory.to_str().unwrap()])
             .assert()
             .failure();
     }
@@ -38,7 +81,7 @@ mod deserialize_error {
         let invalid_image_name_config_directory = super::get_config_directory("invalid-image-name");
         let mut program = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).expect("program");
         program
-            .args(&[
+            .args([
                 "build",
                 invalid_image_name_config_directory.to_str().unwrap(),
             ])
@@ -50,53 +93,1043 @@ mod deserialize_error {
 #[test]
 fn build_minimum_set() {
     let minimum_set_directory = get_config_directory("minimum-set");
-    let stdout = r#"---
-image:
-  - scripts: []
-    base_image:
-      name: scratch
-    name: image
-    tag: latest
+    let storage = tempfile::tempdir().unwrap();
 
-"#
-    .to_string();
-    let mut program = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).expect("program");
-    program
-        .args(&["build", minimum_set_directory.to_str().unwrap()])
+    common::amethyst(storage.path())
+        .args(["build", minimum_set_directory.to_str().unwrap()])
         .assert()
         .success()
-        .stdout(stdout);
+        .stdout(predicates::str::contains("[image:latest] built sha256:"));
+
+    let manifest = common::manifest(storage.path(), "image", "latest");
+    assert_eq!(manifest["layers"].as_array().unwrap().len(), 0);
 }
 
 #[test]
 fn build_multi_image() {
     let config_directory = get_config_directory("multi-image");
-    let stdout = r#"---
-image:
-  - scripts:
-      - type: add
-        source: "./source-file"
-        destination: "./destination-file"
-    base_image:
-      name: scrach
-      tag: latest
-    name: image1
-    tag: "22.04"
-  - scripts:
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", config_directory.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(
+            predicates::str::contains("[image1:22.04] built sha256:")
+                .and(predicates::str::contains("[image2:latest] built sha256:")),
+        );
+
+    let image1 = common::manifest(storage.path(), "image1", "22.04");
+    let image2 = common::manifest(storage.path(), "image2", "latest");
+    let image1_layers = image1["layers"].as_array().unwrap();
+    let image2_layers = image2["layers"].as_array().unwrap();
+    assert_eq!(image1_layers.len(), 1);
+    assert_eq!(image2_layers.len(), 2);
+    assert_eq!(image1_layers[0], image2_layers[0]);
+    assert_eq!(
+        common::layer_entries(storage.path(), image1_layers[0]["digest"].as_str().unwrap()),
+        vec!["destination-file"]
+    );
+    assert_eq!(
+        common::layer_entries(storage.path(), image2_layers[1]["digest"].as_str().unwrap()),
+        vec!["opt", "opt/amethyst", "opt/amethyst/destination-file"]
+    );
+
+    let config = common::config(storage.path(), &image2);
+    let diff_ids = config["rootfs"]["diff_ids"].as_array().unwrap();
+    let history = config["history"].as_array().unwrap();
+    assert_eq!(diff_ids.len(), image2_layers.len());
+    assert_eq!(history.len(), image2_layers.len());
+    assert_eq!(
+        history[1]["created_by"],
+        "amethyst add ./source-file /opt/amethyst/destination-file"
+    );
+    assert_eq!(history[1]["created"], config["created"]);
+    assert_eq!(config["os"], "linux");
+    assert!(config["architecture"].is_string());
+}
+
+#[test]
+fn build_reproducibly() {
+    let config_directory = get_config_directory("multi-image");
+    let storages = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+    for storage in &storages {
+        common::amethyst(storage.path())
+            .env("SOURCE_DATE_EPOCH", "1650000000")
+            .args(["build", config_directory.to_str().unwrap()])
+            .assert()
+            .success();
+    }
+
+    let first = common::manifest(storages[0].path(), "image2", "latest");
+    let second = common::manifest(storages[1].path(), "image2", "latest");
+    assert_eq!(first, second);
+}
+
+#[test]
+fn verify_reproducible_build() {
+    let config_directory = get_config_directory("multi-image");
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args([
+            "build",
+            "--verify-reproducible",
+            config_directory.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(
+            predicates::str::contains("[image1:22.04] reproducible sha256:").and(
+                predicates::str::contains("[image2:latest] reproducible sha256:"),
+            ),
+        );
+}
+
+#[test]
+fn build_with_configured_compression() {
+    let config_directory = get_config_directory("compression");
+    let storage = tempfile::tempdir().unwrap();
+    let rootfs = tempfile::tempdir().unwrap();
+    common::amethyst(storage.path())
+        .args(["build", config_directory.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let manifest = common::manifest(storage.path(), "uncompressed", "latest");
+    assert_eq!(
+        manifest["mediaType"],
+        "application/vnd.oci.image.manifest.v1+json"
+    );
+    assert_eq!(
+        manifest["config"]["mediaType"],
+        "application/vnd.oci.image.config.v1+json"
+    );
+    let media_types = manifest["layers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|layer| layer["mediaType"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        media_types,
+        vec![
+            "application/vnd.oci.image.layer.v1.tar+zstd",
+            "application/vnd.oci.image.layer.v1.tar"
+        ]
+    );
+
+    common::amethyst(storage.path())
+        .args(["rootfs", "uncompressed", rootfs.path().to_str().unwrap()])
+        .assert()
+        .success();
+    assert!(rootfs.path().join("zstd").is_file());
+    assert!(rootfs.path().join("uncompressed").is_file());
+}
+
+#[test]
+fn build_with_compression_option() {
+    let config_directory = get_config_directory("multi-image");
+    let storage = tempfile::tempdir().unwrap();
+    common::amethyst(storage.path())
+        .args([
+            "build",
+            "--compression",
+            "zstd:3",
+            config_directory.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    let manifest = common::manifest(storage.path(), "image2", "latest");
+    assert_eq!(
+        manifest["mediaType"],
+        "application/vnd.oci.image.manifest.v1+json"
+    );
+    for layer in manifest["layers"].as_array().unwrap() {
+        assert_eq!(
+            layer["mediaType"],
+            "application/vnd.oci.image.layer.v1.tar+zstd"
+        );
+    }
+}
+
+#[test]
+fn cannot_build_with_unknown_compression() {
+    let config_directory = get_config_directory("multi-image");
+    let storage = tempfile::tempdir().unwrap();
+    common::amethyst(storage.path())
+        .args([
+            "build",
+            "--compression",
+            "xz",
+            config_directory.to_str().unwrap(),
+        ])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn rebuild_from_cache() {
+    let config_directory = copy_config_directory("multi-image");
+    let storage = tempfile::tempdir().unwrap();
+    let build = || {
+        common::amethyst(storage.path())
+            .env("SOURCE_DATE_EPOCH", "1650000000")
+            .args(["build", config_directory.path().to_str().unwrap()])
+            .assert()
+            .success()
+    };
+    build().stdout(predicates::str::contains("cache hit").not());
+    let first = common::manifest(storage.path(), "image2", "latest");
+
+    build().stdout(
+        predicates::str::contains("[image1:22.04] cache hit sha256:")
+            .and(predicates::str::contains(
+                "[image2:latest] cache hit sha256:",
+            ))
+            .and(predicates::str::contains("cache miss").not()),
+    );
+    assert_eq!(common::manifest(storage.path(), "image2", "latest"), first);
+
+    std::fs::write(config_directory.path().join("source-file"), "changed").unwrap();
+    build().stdout(
+        predicates::str::contains("[image1:22.04] cache miss: no layer cached for this step").and(
+            predicates::str::contains("[image2:latest] cache miss: no layer cached for this step"),
+        ),
+    );
+}
+
+#[test]
+fn add_matching_files_except_ignored_ones() {
+    let config_directory = tempfile::tempdir().unwrap();
+    let files = [
+        (
+            "amethyst.yaml",
+            r#"image:
+  - name: app
+    scripts:
+      - { type: add, source: [Cargo.toml, "src/*.rs"], destination: /app }
+      - { type: add, source: assets, destination: /srv/assets }
+"#,
+        ),
+        (".amethystignore", "*.log\n/src/generated.rs\nassets/tmp/\n"),
+        ("Cargo.toml", "[package]"),
+        ("src/main.rs", "fn main() {}"),
+        ("src/generated.rs", ""),
+        ("src/notes.txt", ""),
+        ("assets/logo.svg", "<svg/>"),
+        ("assets/fonts/sans.ttf", ""),
+        ("assets/tmp/cache", ""),
+        ("assets/build.log", ""),
+    ];
+    for (name, content) in files {
+        let path = config_directory.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    let storage = tempfile::tempdir().unwrap();
+    let build = || {
+        common::amethyst(storage.path())
+            .args(["build", config_directory.path().to_str().unwrap()])
+            .assert()
+            .success()
+    };
+
+    build();
+    let manifest = common::manifest(storage.path(), "app", "latest");
+    let layers = manifest["layers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|layer| common::layer_entries(storage.path(), layer["digest"].as_str().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        layers,
+        vec![
+            vec!["app", "app/Cargo.toml", "app/main.rs"],
+            vec![
+                "srv",
+                "srv/assets",
+                "srv/assets/fonts",
+                "srv/assets/fonts/sans.ttf",
+                "srv/assets/logo.svg"
+            ],
+        ]
+    );
+
+    std::fs::write(config_directory.path().join("assets/build.log"), "changed").unwrap();
+    build().stdout(predicates::str::contains("cache miss").not());
+    std::fs::write(config_directory.path().join("assets/logo.svg"), "changed").unwrap();
+    build().stdout(predicates::str::contains(
+        "[app:latest] cache miss: no layer cached for this step",
+    ));
+}
+
+#[test]
+fn add_with_owner_mode_and_timestamps() {
+    let config_directory = tempfile::tempdir().unwrap();
+    let files = [
+        (
+            "amethyst.yaml",
+            r#"image:
+  - name: app
+    scripts:
+      - { type: add, source: [passwd, group], destination: /etc/ }
       - type: add
-        source: "./source-file"
-        destination: /destination-file
+        source: bin
+        destination: /opt/app/bin
+        owner: app:staff
+        mode: "0750"
+        preserve_timestamps: true
+"#,
+        ),
+        (
+            "passwd",
+            "root:x:0:0:root:/root:/bin/sh\napp:x:1000:1000::/app:/bin/sh\n",
+        ),
+        ("group", "root:x:0:\nstaff:x:50:\n"),
+        ("bin/tool", "#!/bin/sh\n"),
+    ];
+    for (name, content) in files {
+        let path = config_directory.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    let touch = |name: &str, seconds: i64| {
+        filetime::set_file_mtime(
+            config_directory.path().join(name),
+            filetime::FileTime::from_unix_time(seconds, 0),
+        )
+        .unwrap()
+    };
+    touch("bin/tool", 1_000_000_000);
+    let storage = tempfile::tempdir().unwrap();
+    let build = || {
+        common::amethyst(storage.path())
+            .args([
+                "build",
+                "--explain-cache",
+                config_directory.path().to_str().unwrap(),
+            ])
+            .assert()
+            .success()
+    };
+
+    build().stdout(predicates::str::contains(
+        "[app:latest] add --owner app:staff --mode 0750 --preserve-timestamps bin /opt/app/bin",
+    ));
+    let manifest = common::manifest(storage.path(), "app", "latest");
+    let layer = manifest["layers"][1]["digest"].as_str().unwrap();
+    assert_eq!(
+        common::layer_headers(storage.path(), layer)[2..],
+        [
+            ("opt/app/bin".to_string(), 1000, 50, 0o750),
+            ("opt/app/bin/tool".to_string(), 1000, 50, 0o750),
+        ]
+    );
+    assert_eq!(
+        common::layer_mtimes(storage.path(), layer)[3],
+        ("opt/app/bin/tool".to_string(), 1_000_000_000)
+    );
+
+    // only the modification times of sources whose timestamps are kept matter,
+    // and not the permissions of sources whose mode is given
+    touch("passwd", 1_000_000_000);
+    touch("bin/tool", 1_500_000_000);
+    std::fs::set_permissions(
+        config_directory.path().join("bin/tool"),
+        std::os::unix::fs::PermissionsExt::from_mode(0o664),
+    )
+    .unwrap();
+    build().stdout(
+        predicates::str::contains("[app:latest] cache hit")
+            .and(predicates::str::contains("mode changed").not())
+            .and(predicates::str::contains(
+                "[app:latest]   source bin/tool modification time changed from Some(1000000000) to Some(1500000000)",
+            )),
+    );
+}
+
+#[test]
+fn add_file_in_place_of_symbolic_link() {
+    let config_directory = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    let victim = outside.path().join("victim");
+    std::fs::write(&victim, "host").unwrap();
+    std::fs::write(
+        config_directory.path().join("amethyst.yaml"),
+        r#"image:
+  - name: app
+    scripts:
+      - { type: add, source: tool, destination: /app/ }
     base_image:
-      name: amethyst
-      tag: "22.04"
-    name: image2
-    tag: latest
+      local: runtime
+  - name: runtime
+    scripts:
+      - { type: add, source: base, destination: /app }
+"#,
+    )
+    .unwrap();
+    std::fs::write(config_directory.path().join("tool"), "tool").unwrap();
+    std::fs::create_dir(config_directory.path().join("base")).unwrap();
+    std::os::unix::fs::symlink(&victim, config_directory.path().join("base/tool")).unwrap();
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", config_directory.path().to_str().unwrap()])
+        .assert()
+        .success();
+
+    assert_eq!(std::fs::read_to_string(&victim).unwrap(), "host");
+    let manifest = common::manifest(storage.path(), "app", "latest");
+    let layer = manifest["layers"][1]["digest"].as_str().unwrap();
+    assert_eq!(
+        common::layer_entries(storage.path(), layer),
+        vec!["app", "app/tool"]
+    );
+}
+
+#[test]
+fn cannot_add_unmatched_source() {
+    let config_directory = tempfile::tempdir().unwrap();
+    std::fs::write(
+        config_directory.path().join("amethyst.yaml"),
+        "image:\n  - name: app\n    scripts:\n      - { type: add, source: '*.txt', destination: /app/ }\n",
+    )
+    .unwrap();
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", config_directory.path().to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "*.txt matches no file which is not ignored",
+        ));
+}
+
+#[test]
+fn rebuild_without_cache() {
+    let config_directory = get_config_directory("multi-image");
+    let storage = tempfile::tempdir().unwrap();
+    for _ in 0..2 {
+        common::amethyst(storage.path())
+            .args(["build", "--no-cache", config_directory.to_str().unwrap()])
+            .assert()
+            .success()
+            .stdout(
+                predicates::str::contains(
+                    "[image1:22.04] cache miss: cache disabled by --no-cache",
+                )
+                .and(predicates::str::contains("cache hit").not()),
+            );
+    }
+}
+
+#[test]
+fn explain_cache_misses() {
+    let config_directory = copy_config_directory("multi-image");
+    let storage = tempfile::tempdir().unwrap();
+    let build = || {
+        common::amethyst(storage.path())
+            .args([
+                "build",
+                "--explain-cache",
+                config_directory.path().to_str().unwrap(),
+            ])
+            .assert()
+            .success()
+    };
+    build().stdout(predicates::str::contains(
+        "[image1:22.04]   step 1 of image1:22.04 was never cached",
+    ));
+
+    std::fs::write(config_directory.path().join("source-file"), "changed").unwrap();
+    build().stdout(
+        predicates::str::contains(
+            "[image1:22.04]   source ./source-file content changed from sha256:",
+        )
+        .and(predicates::str::contains(
+            "[image2:latest]   base image changed from sha256:",
+        ))
+        .and(predicates::str::contains("scriptlet changed").not()),
+    );
+}
+
+#[test]
+fn share_cache_through_directory() {
+    let config_directory = get_config_directory("multi-image");
+    let cache = tempfile::tempdir().unwrap();
+    let cache_directory = cache.path().join("cache");
+    let exporting = tempfile::tempdir().unwrap();
+    common::amethyst(exporting.path())
+        .args([
+            "build",
+            "--cache-from",
+            cache_directory.to_str().unwrap(),
+            "--cache-to",
+            cache_directory.to_str().unwrap(),
+            config_directory.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("cannot import cache from"))
+        .stdout(predicates::str::contains("exported 2 cache entries to"));
+    assert!(cache_directory.join("oci-layout").is_file());
+    assert!(cache_directory.join("index.json").is_file());
+
+    let importing = tempfile::tempdir().unwrap();
+    common::amethyst(importing.path())
+        .args([
+            "build",
+            "--cache-from",
+            cache_directory.to_str().unwrap(),
+            config_directory.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(
+            predicates::str::contains("imported 2 cache entries from")
+                .and(predicates::str::contains(
+                    "[image1:22.04] cache hit sha256:",
+                ))
+                .and(predicates::str::contains(
+                    "[image2:latest] cache hit sha256:",
+                )),
+        );
+}
+
+#[test]
+fn skip_cache_entries_not_matching_their_step_or_layer() {
+    let config_directory = get_config_directory("multi-image");
+    let cache = tempfile::tempdir().unwrap();
+    let cache_directory = cache.path().join("cache");
+    let exporting = tempfile::tempdir().unwrap();
+    common::amethyst(exporting.path())
+        .args([
+            "build",
+            "--cache-to",
+            cache_directory.to_str().unwrap(),
+            config_directory.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    let read_json = |digest: &str| -> serde_json::Value {
+        serde_json::from_slice(&std::fs::read(common::blob_path(&cache_directory, digest)).unwrap())
+            .unwrap()
+    };
+    let index_path = cache_directory.join("index.json");
+    let mut index: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&index_path).unwrap()).unwrap();
+    let mut manifest = read_json(index["manifests"][0]["digest"].as_str().unwrap());
+    let config = read_json(manifest["config"]["digest"].as_str().unwrap());
+    let mut entries = config["entries"].as_object().unwrap().clone();
+    let keys = entries.keys().cloned().collect::<Vec<_>>();
+    entries[&keys[0]]["diff_id"] = serde_json::json!(format!("sha256:{}", "0".repeat(64)));
+    let entry = entries.remove(&keys[1]).unwrap();
+    entries.insert(format!("sha256:{}", "f".repeat(64)), entry);
+    let config = serde_json::to_vec(&serde_json::json!({ "entries": entries })).unwrap();
+    manifest["config"]["digest"] = serde_json::json!(common::write_blob(&cache_directory, &config));
+    manifest["config"]["size"] = serde_json::json!(config.len());
+    let manifest = serde_json::to_vec(&manifest).unwrap();
+    index["manifests"][0]["digest"] =
+        serde_json::json!(common::write_blob(&cache_directory, &manifest));
+    index["manifests"][0]["size"] = serde_json::json!(manifest.len());
+    std::fs::write(&index_path, serde_json::to_vec(&index).unwrap()).unwrap();
+
+    let importing = tempfile::tempdir().unwrap();
+    common::amethyst(importing.path())
+        .args([
+            "build",
+            "--cache-from",
+            cache_directory.to_str().unwrap(),
+            config_directory.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(
+            predicates::str::contains("imported 0 cache entries from")
+                .and(predicates::str::contains(
+                    "skipped 2 not matching their step or layer",
+                ))
+                .and(predicates::str::contains("cache hit").not()),
+        );
+}
+
+#[test]
+fn build_on_local_base_image() {
+    let config_directory = get_config_directory("local-base");
+    let storage = tempfile::tempdir().unwrap();
 
-"#;
-    let mut program = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).expect("program");
-    program
-        .args(&["build", config_directory.to_str().unwrap()])
+    let output = common::amethyst(storage.path())
+        .args(["build", config_directory.to_str().unwrap()])
         .assert()
         .success()
-        .stdout(stdout);
+        .stdout(predicates::str::contains("pulling").not())
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    assert!(
+        output.find("[runtime:1.0] built").unwrap() < output.find("[app:latest] built").unwrap()
+    );
+
+    let runtime = common::manifest(storage.path(), "runtime", "1.0");
+    let app = common::manifest(storage.path(), "app", "latest");
+    assert_eq!(app["layers"].as_array().unwrap().len(), 2);
+    assert_eq!(app["layers"][0], runtime["layers"][0]);
+}
+
+#[test]
+fn configure_image() {
+    let config_directory = get_config_directory("configuration");
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", config_directory.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let manifest = common::manifest(storage.path(), "app", "latest");
+    assert_eq!(manifest["layers"].as_array().unwrap().len(), 1);
+    let config = common::config(storage.path(), &manifest);
+    assert_eq!(config["rootfs"]["diff_ids"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        config["config"],
+        serde_json::json!({
+            "User": "1000:1000",
+            "ExposedPorts": {"8080/tcp": {}},
+            "Env": ["APP_MODE=production"],
+            "Entrypoint": ["/app"],
+            "Cmd": ["--port", "8080"],
+            "WorkingDir": "/srv",
+            "Labels": {"org.opencontainers.image.title": "app"},
+        })
+    );
+    let history = config["history"].as_array().unwrap();
+    assert_eq!(history.len(), 8);
+    assert_eq!(history[0].get("empty_layer"), None);
+    assert!(history[1..]
+        .iter()
+        .all(|entry| entry["empty_layer"] == serde_json::json!(true)));
+    assert_eq!(history[4]["created_by"], r#"amethyst entrypoint ["/app"]"#);
+}
+
+#[test]
+fn run_commands() {
+    let config_directory = shell_config_directory(
+        r#"- { type: env, variables: { GREETING: hello } }
+- { type: workdir, path: /srv }
+- { type: run, command: 'echo "$GREETING from $$ in $(pwd)"; echo warning >&2; echo built > out' }"#,
+    );
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", config_directory.path().to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(
+            predicates::str::contains("[app:latest] | hello from 1 in /srv")
+                .and(predicates::str::contains("[app:latest] | warning")),
+        );
+
+    let manifest = common::manifest(storage.path(), "app", "latest");
+    let layers = manifest["layers"].as_array().unwrap();
+    assert_eq!(
+        common::layer_entries(
+            storage.path(),
+            layers.last().unwrap()["digest"].as_str().unwrap()
+        ),
+        vec!["srv", "srv/out"]
+    );
+}
+
+#[test]
+fn run_commands_as_user() {
+    let config_directory = shell_config_directory(
+        r#"- { type: add, source: home, destination: /home/app, owner: "1000:1000", mode: "0700" }
+- { type: user, user: "1000:1000" }
+- { type: run, command: 'umask 077; echo private > /home/app/file' }"#,
+    );
+    std::fs::create_dir(config_directory.path().join("home")).unwrap();
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", config_directory.path().to_str().unwrap()])
+        .assert()
+        .success();
+
+    let manifest = common::manifest(storage.path(), "app", "latest");
+    let layers = manifest["layers"].as_array().unwrap();
+    let layer = layers.last().unwrap()["digest"].as_str().unwrap();
+    assert_eq!(
+        common::layer_headers(storage.path(), layer)[1..],
+        [
+            ("home/app".to_string(), 1000, 1000, 0o700),
+            ("home/app/file".to_string(), 1000, 1000, 0o600),
+        ]
+    );
+}
+
+#[test]
+fn mount_secrets_without_storing_them() {
+    let config_directory = shell_config_directory(
+        r#"- type: run
+  command: 'read value < /run/secrets/file; echo "file $value"; echo "env $TOKEN"; echo built > /out'
+  secrets: [{ id: file }, { id: token, env: TOKEN }]"#,
+    );
+    let config = config_directory.path().join("amethyst.yaml");
+    let mut content = std::fs::read_to_string(&config).unwrap();
+    content.push_str("    secrets:\n      file: { file: ./file-secret }\n");
+    std::fs::write(&config, content).unwrap();
+    std::fs::write(config_directory.path().join("file-secret"), "s3cret-file\n").unwrap();
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .env("TOKEN_SOURCE", "s3cret-env")
+        .args([
+            "build",
+            "--secret",
+            "id=token,env=TOKEN_SOURCE",
+            config_directory.path().to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(
+            predicates::str::contains("[app:latest] | file s3cret-file")
+                .and(predicates::str::contains("[app:latest] | env s3cret-env")),
+        );
+
+    let manifest = common::manifest(storage.path(), "app", "latest");
+    let layers = manifest["layers"].as_array().unwrap();
+    assert_eq!(
+        common::layer_entries(
+            storage.path(),
+            layers.last().unwrap()["digest"].as_str().unwrap()
+        ),
+        vec!["out"]
+    );
+    let config = common::config(storage.path(), &manifest).to_string();
+    let cache = std::fs::read_to_string(storage.path().join("cache.json")).unwrap();
+    for stored in [config, cache] {
+        assert!(!stored.contains("s3cret-file") && !stored.contains("s3cret-env"));
+    }
+    assert_eq!(
+        std::fs::read_dir(storage.path().join("tmp"))
+            .unwrap()
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn keep_cache_mounts_across_builds() {
+    let config_directory = shell_config_directory(
+        r#"- type: run
+  command: 'count=0; [ -f /var/cache/app/count ] && read count < /var/cache/app/count; count=$((count + 1)); echo $count > /var/cache/app/count; echo "run $count"; echo built > /out'
+  caches: [{ target: /var/cache/app, sharing: locked }]"#,
+    );
+    let storage = tempfile::tempdir().unwrap();
+    let build = |count: &str| {
+        common::amethyst(storage.path())
+            .args([
+                "build",
+                "--no-cache",
+                config_directory.path().to_str().unwrap(),
+            ])
+            .assert()
+            .success()
+            .stdout(predicates::str::contains(format!(
+                "[app:latest] | run {}",
+                count
+            )));
+    };
+
+    build("1");
+    build("2");
+    let manifest = common::manifest(storage.path(), "app", "latest");
+    let layers = manifest["layers"].as_array().unwrap();
+    assert_eq!(
+        common::layer_entries(
+            storage.path(),
+            layers.last().unwrap()["digest"].as_str().unwrap()
+        ),
+        vec!["out"]
+    );
+
+    common::amethyst(storage.path())
+        .args(["clear-cache-mounts"])
+        .assert()
+        .success()
+        .stdout("removed /var/cache/app\n");
+    build("1");
+    common::amethyst(storage.path())
+        .args(["clear-cache-mounts", "unknown"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("no cache mount unknown"));
+}
+
+#[test]
+fn keep_owners_of_cache_mount_files() {
+    let config_directory = shell_config_directory(
+        r#"- { type: add, source: /bin/chown, destination: /bin/chown }
+- type: run
+  command: 'echo root > /var/cache/app/file; /bin/chown 1000:1000 /var/cache/app/file'
+  caches: [{ target: /var/cache/app }]
+- { type: user, user: "1000:1000" }
+- type: run
+  command: '[ -O /var/cache/app/file ] && echo user >> /var/cache/app/file'
+  caches: [{ target: /var/cache/app }]"#,
+    );
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", config_directory.path().to_str().unwrap()])
+        .assert()
+        .success();
+
+    let cache_mounts = storage.path().join("cache-mounts");
+    let directory = std::fs::read_dir(&cache_mounts)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let instance = directory.join("0");
+    let root = std::os::unix::fs::MetadataExt::uid(&std::fs::metadata(&instance).unwrap());
+    let file = std::fs::metadata(instance.join("file")).unwrap();
+    assert_eq!(std::os::unix::fs::MetadataExt::uid(&file), root);
+    assert_eq!(
+        std::fs::read_to_string(instance.join("file")).unwrap(),
+        "root\nuser\n"
+    );
+    let owners: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(directory.join("0.owners.json")).unwrap()).unwrap();
+    assert_eq!(owners, serde_json::json!({ "file": [1000, 1000] }));
+
+    common::amethyst(storage.path())
+        .args(["clear-cache-mounts"])
+        .assert()
+        .success()
+        .stdout("removed /var/cache/app\n");
+    assert_eq!(std::fs::read_dir(&cache_mounts).unwrap().count(), 0);
+}
+
+#[test]
+fn cannot_mount_undeclared_secret() {
+    let config_directory =
+        shell_config_directory("- { type: run, command: 'true', secrets: [{ id: token }] }");
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", config_directory.path().to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "secret token is not declared by the image or --secret",
+        ));
+}
+
+#[test]
+fn cannot_build_with_failing_command() {
+    let config_directory =
+        shell_config_directory(r#"- { type: run, command: [/bin/sh, -c, "exit 3"] }"#);
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", config_directory.path().to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            r#"["/bin/sh","-c","exit 3"] exited with code 3"#,
+        ));
+}
+
+#[test]
+fn cannot_build_with_command_exiting_like_setup_failure() {
+    let config_directory =
+        shell_config_directory(r#"- { type: run, command: [/bin/sh, -c, "exit 125"] }"#);
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", config_directory.path().to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(
+            predicates::str::contains(r#"["/bin/sh","-c","exit 125"] exited with code 125"#)
+                .and(predicates::str::contains("set up").not()),
+        );
+}
+
+#[test]
+fn cannot_build_with_missing_program() {
+    let config_directory = shell_config_directory("- { type: run, command: [/bin/missing] }");
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", config_directory.path().to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            r#"cannot run ["/bin/missing"]: cannot set up the sandbox: cannot run /bin/missing"#,
+        ));
+}
+
+#[test]
+fn run_commands_with_loopback_without_network() {
+    let config_directory = shell_config_directory(
+        r#"- type: run
+  network: none
+  command: 'while read -r line; do [ "$line" = "|-- 127.0.0.1" ] && up=yes; done < /proc/net/fib_trie; echo "loopback ${up:-down}"'"#,
+    );
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", config_directory.path().to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("[app:latest] | loopback yes"));
+}
+
+#[test]
+fn copy_from_local_image() {
+    let config_directory = get_config_directory("copy-from");
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", config_directory.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let app = common::manifest(storage.path(), "app", "latest");
+    assert_eq!(app["layers"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        common::layer_headers(storage.path(), app["layers"][0]["digest"].as_str().unwrap()),
+        vec![
+            ("usr".to_string(), 0, 0, 0o755),
+            ("usr/local".to_string(), 0, 0, 0o755),
+            ("usr/local/bin".to_string(), 0, 0, 0o755),
+            ("usr/local/bin/program".to_string(), 1000, 1000, 0o750),
+        ]
+    );
+}
+
+#[test]
+fn cannot_build_cyclic_local_base_images() {
+    let config_directory = get_config_directory("local-cycle");
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", config_directory.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "images are based on each other: app:latest -> runtime:latest -> app:latest",
+        ));
+}
+
+#[test]
+fn stop_at_first_failure() {
+    let config_directory = get_config_directory("keep-going");
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", "--jobs", "1", config_directory.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(
+            predicates::str::contains("[broken:latest] failed:")
+                .and(predicates::str::contains("[app:latest] skipped"))
+                .and(predicates::str::contains("[other:latest] skipped")),
+        );
+}
+
+#[test]
+fn keep_going_after_failure() {
+    let config_directory = get_config_directory("keep-going");
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["build", "--keep-going", config_directory.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains("[other:latest] built sha256:"))
+        .stderr(
+            predicates::str::contains("[broken:latest] failed:")
+                .and(predicates::str::contains("[app:latest] skipped"))
+                .and(predicates::str::contains(
+                    "cannot build broken:latest, app:latest",
+                )),
+        );
+}
+
+#[test]
+fn list_targets() {
+    let config_directory = get_config_directory("local-base");
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args([
+            "build",
+            "--list-targets",
+            config_directory.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout("runtime:1.0\napp:latest\n");
+    assert!(!storage.path().join("tags.json").exists());
+}
+
+#[test]
+fn build_selected_images() {
+    let config_directory = get_config_directory("keep-going");
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args([
+            "build",
+            "--image",
+            "oth*",
+            config_directory.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(
+            predicates::str::contains("[other:latest] built sha256:")
+                .and(predicates::str::contains("broken").not()),
+        );
+
+    common::amethyst(storage.path())
+        .args([
+            "build",
+            "--target",
+            "app:latest",
+            "--list-targets",
+            config_directory.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout("broken:latest\napp:latest\n");
+}
+
+#[test]
+fn cannot_build_unknown_image() {
+    let config_directory = get_config_directory("multi-image");
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args([
+            "build",
+            "--image",
+            "image3",
+            config_directory.to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "no configured image matches \"image3\"",
+        ));
 }
@@ -0,0 +1,147 @@
+#![allow(dead_code)]
+
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path;
+
+pub const STORAGE_ENVIRONMENT_VARIABLE: &str = "AMETHYST_STORAGE";
+
+pub fn amethyst(storage: &path::Path) -> assert_cmd::Command {
+    let mut program = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).expect("program");
+    program.env(STORAGE_ENVIRONMENT_VARIABLE, storage);
+    program
+}
+
+pub fn sha256(content: &[u8]) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(content)))
+}
+
+pub fn blob_path(storage: &path::Path, digest: &str) -> path::PathBuf {
+    let (algorithm, encoded) = digest.split_once(':').unwrap();
+    storage.join("blobs").join(algorithm).join(encoded)
+}
+
+pub fn write_blob(storage: &path::Path, content: &[u8]) -> String {
+    let digest = sha256(content);
+    let path = blob_path(storage, &digest);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+    digest
+}
+
+pub struct Image {
+    pub manifest: String,
+    pub config: String,
+    pub layer: String,
+}
+
+/// Stores an image with one layer and tags it as `repository:tag`.
+pub fn seed_image(storage: &path::Path, repository: &str, tag: &str) -> Image {
+    let config = format!(
+        r#"{{"created": "2022-04-01T00:00:00Z", "architecture": "amd64", "os": "linux", "repository": "{}"}}"#,
+        repository
+    );
+    let layer = format!("layer of {}", repository);
+    let config_digest = write_blob(storage, config.as_bytes());
+    let layer_digest = write_blob(storage, layer.as_bytes());
+    let manifest = format!(
+        r#"{{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+            "config": {{"mediaType": "application/vnd.docker.container.image.v1+json", "size": {}, "digest": "{}"}},
+            "layers": [{{"mediaType": "application/vnd.docker.image.rootfs.diff.tar.gzip", "size": {}, "digest": "{}"}}]
+        }}"#,
+        config.len(),
+        config_digest,
+        layer.len(),
+        layer_digest
+    );
+    let manifest_digest = write_blob(storage, manifest.as_bytes());
+    tag_image(storage, repository, tag, &manifest_digest);
+    Image {
+        manifest: manifest_digest,
+        config: config_digest,
+        layer: layer_digest,
+    }
+}
+
+pub fn tag_image(storage: &path::Path, repository: &str, tag: &str, digest: &str) {
+    let path = storage.join("tags.json");
+    let mut index: serde_json::Value = match fs::read(&path) {
+        Ok(content) => serde_json::from_slice(&content).unwrap(),
+        Err(_) => serde_json::json!({ "repositories": {} }),
+    };
+    let repositories = index["repositories"].as_object_mut().unwrap();
+    let tags = repositories
+        .entry(repository)
+        .or_insert_with(|| serde_json::json!({}));
+    tags[tag] = serde_json::json!({
+        "digest": digest,
+        "pulled_at": "2022-04-01T00:00:00Z",
+    });
+    fs::write(path, serde_json::to_vec(&index).unwrap()).unwrap();
+}
+
+/// Manifest of the stored image tagged as `repository:tag`.
+pub fn manifest(storage: &path::Path, repository: &str, tag: &str) -> serde_json::Value {
+    let index: serde_json::Value =
+        serde_json::from_slice(&fs::read(storage.join("tags.json")).unwrap()).unwrap();
+    let digest = index["repositories"][repository][tag]["digest"]
+        .as_str()
+        .unwrap();
+    serde_json::from_slice(&fs::read(blob_path(storage, digest)).unwrap()).unwrap()
+}
+
+/// Config blob referred to by `manifest`.
+pub fn config(storage: &path::Path, manifest: &serde_json::Value) -> serde_json::Value {
+    let digest = manifest["config"]["digest"].as_str().unwrap();
+    serde_json::from_slice(&fs::read(blob_path(storage, digest)).unwrap()).unwrap()
+}
+
+/// Paths in the stored gzip compressed layer `digest`.
+pub fn layer_entries(storage: &path::Path, digest: &str) -> Vec<String> {
+    let layer = fs::File::open(blob_path(storage, digest)).unwrap();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(layer));
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| entry.unwrap().path().unwrap().to_string_lossy().to_string())
+        .collect()
+}
+
+/// Path, uid, gid and mode of the entries in the stored gzip compressed layer `digest`.
+pub fn layer_headers(storage: &path::Path, digest: &str) -> Vec<(String, u64, u64, u32)> {
+    let layer = fs::File::open(blob_path(storage, digest)).unwrap();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(layer));
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            let header = entry.header();
+            (
+                entry.path().unwrap().to_string_lossy().to_string(),
+                header.uid().unwrap(),
+                header.gid().unwrap(),
+                header.mode().unwrap(),
+            )
+        })
+        .collect()
+}
+
+/// Path and modification time of the entries in the stored gzip compressed layer `digest`.
+pub fn layer_mtimes(storage: &path::Path, digest: &str) -> Vec<(String, u64)> {
+    let layer = fs::File::open(blob_path(storage, digest)).unwrap();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(layer));
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            (
+                entry.path().unwrap().to_string_lossy().to_string(),
+                entry.header().mtime().unwrap(),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,146 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path;
+
+const STORAGE_ENVIRONMENT_VARIABLE: &str = "AMETHYST_STORAGE";
+
+fn sha256(content: &str) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(content.as_bytes())))
+}
+
+fn blob_path(storage: &path::Path, digest: &str) -> path::PathBuf {
+    let (algorithm, encoded) = digest.split_once(':').unwrap();
+    storage.join("blobs").join(algorithm).join(encoded)
+}
+
+fn write_blob(storage: &path::Path, content: &str) -> String {
+    let digest = sha256(content);
+    let path = blob_path(storage, &digest);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+    digest
+}
+
+struct Image {
+    config: String,
+    layer: String,
+}
+
+fn seed(storage: &path::Path) -> Image {
+    let config = sha256("config");
+    let layer = sha256("layer");
+    let manifest = format!(
+        r#"{{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+            "config": {{"mediaType": "application/vnd.docker.container.image.v1+json", "size": 6, "digest": "{}"}},
+            "layers": [{{"mediaType": "application/vnd.docker.image.rootfs.diff.tar.gzip", "size": 5, "digest": "{}"}}]
+        }}"#,
+        config, layer
+    );
+    let manifest = write_blob(storage, &manifest);
+    write_blob(storage, "config");
+    write_blob(storage, "layer");
+    fs::write(
+        storage.join("tags.json"),
+        format!(
+            r#"{{"repositories": {{"library/ubuntu": {{"latest": {{"digest": "{}", "pulled_at": "2022-01-01T00:00:00Z"}}}}}}}}"#,
+            manifest
+        ),
+    )
+    .unwrap();
+    Image { config, layer }
+}
+
+fn fsck(storage: &path::Path, args: &[&str]) -> assert_cmd::assert::Assert {
+    let mut program = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).expect("program");
+    program
+        .env(STORAGE_ENVIRONMENT_VARIABLE, storage)
+        .arg("fsck")
+        .args(args)
+        .assert()
+}
+
+#[test]
+fn check_healthy_storage() {
+    let storage = tempfile::tempdir().unwrap();
+    seed(storage.path());
+
+    fsck(storage.path(), &[])
+        .success()
+        .stdout(predicates::str::contains(
+            "checked 3 blob(s), 0 unresolved problem(s)",
+        ));
+}
+
+#[test]
+fn detect_corrupted_blob() {
+    let storage = tempfile::tempdir().unwrap();
+    let image = seed(storage.path());
+    fs::write(blob_path(storage.path(), &image.layer), "broken").unwrap();
+
+    fsck(storage.path(), &[])
+        .failure()
+        .stdout(predicates::str::contains(format!(
+            "corrupted {}",
+            image.layer
+        )));
+}
+
+#[test]
+fn quarantine_corrupted_blob() {
+    let storage = tempfile::tempdir().unwrap();
+    let image = seed(storage.path());
+    fs::write(blob_path(storage.path(), &image.layer), "broken").unwrap();
+
+    fsck(storage.path(), &["--quarantine"])
+        .failure()
+        .stdout(predicates::str::contains(format!(
+            "quarantined {}",
+            image.layer
+        )));
+
+    assert!(!blob_path(storage.path(), &image.layer).exists());
+    let (algorithm, encoded) = image.layer.split_once(':').unwrap();
+    assert!(storage
+        .path()
+        .join("quarantine")
+        .join(algorithm)
+        .join(encoded)
+        .exists());
+}
+
+#[test]
+fn detect_missing_blob() {
+    let storage = tempfile::tempdir().unwrap();
+    let image = seed(storage.path());
+    fs::remove_file(blob_path(storage.path(), &image.config)).unwrap();
+
+    fsck(storage.path(), &[])
+        .failure()
+        .stdout(predicates::str::contains(format!(
+            "missing {} referenced by library/ubuntu:latest",
+            image.config
+        )));
+}
+
+#[test]
+fn cannot_repair_built_image() {
+    let storage = tempfile::tempdir().unwrap();
+    let image = seed(storage.path());
+    let index_path = storage.path().join("tags.json");
+    let index = fs::read_to_string(&index_path).unwrap();
+    fs::write(
+        &index_path,
+        index.replace(r#""pulled_at""#, r#""built": true, "pulled_at""#),
+    )
+    .unwrap();
+    fs::remove_file(blob_path(storage.path(), &image.config)).unwrap();
+
+    fsck(storage.path(), &["--repair"])
+        .failure()
+        .stdout(predicates::str::contains(format!(
+            "cannot repair {}: not pulled from a registry",
+            image.config
+        )));
+}
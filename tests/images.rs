@@ -0,0 +1,124 @@
+mod common;
+
+use predicates::prelude::*;
+use std::fs;
+
+#[test]
+fn list_no_images() {
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .arg("images")
+        .assert()
+        .success()
+        .stdout("REPOSITORY   TAG   DIGEST   SIZE   CREATED\n");
+}
+
+#[test]
+fn list_images() {
+    let storage = tempfile::tempdir().unwrap();
+    let ubuntu = common::seed_image(storage.path(), "library/ubuntu", "latest");
+    common::seed_image(storage.path(), "library/debian", "11");
+    let (_, encoded) = ubuntu.manifest.split_once(':').unwrap();
+
+    common::amethyst(storage.path())
+        .arg("images")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("library/debian")
+                .and(predicate::str::contains(&encoded[..12]))
+                .and(predicate::str::contains("2022-04-01 00:00:00")),
+        );
+    common::amethyst(storage.path())
+        .args(["images", "--no-trunc"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(&ubuntu.manifest));
+}
+
+#[test]
+fn inspect_image() {
+    let storage = tempfile::tempdir().unwrap();
+    let ubuntu = common::seed_image(storage.path(), "library/ubuntu", "latest");
+
+    let output = common::amethyst(storage.path())
+        .args(["inspect", "ubuntu"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let inspected: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(inspected[0]["digest"], ubuntu.manifest.as_str());
+    assert_eq!(inspected[0]["tags"][0], "library/ubuntu:latest");
+    assert_eq!(
+        inspected[0]["manifest"]["config"]["digest"],
+        ubuntu.config.as_str()
+    );
+    assert_eq!(inspected[0]["config"]["repository"], "library/ubuntu");
+}
+
+#[test]
+fn cannot_inspect_unknown_image() {
+    let storage = tempfile::tempdir().unwrap();
+
+    common::amethyst(storage.path())
+        .args(["inspect", "ubuntu"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no such image: ubuntu"));
+}
+
+#[test]
+fn remove_image_by_tag() {
+    let storage = tempfile::tempdir().unwrap();
+    let ubuntu = common::seed_image(storage.path(), "library/ubuntu", "latest");
+    common::tag_image(storage.path(), "library/ubuntu", "22.04", &ubuntu.manifest);
+
+    common::amethyst(storage.path())
+        .args(["rmi", "ubuntu:latest"])
+        .assert()
+        .success()
+        .stdout("untagged library/ubuntu:latest\n");
+
+    let index = fs::read_to_string(storage.path().join("tags.json")).unwrap();
+    assert!(index.contains("22.04"));
+    assert!(!index.contains("\"latest\""));
+}
+
+#[test]
+fn remove_image_by_digest() {
+    let storage = tempfile::tempdir().unwrap();
+    let ubuntu = common::seed_image(storage.path(), "library/ubuntu", "latest");
+    common::tag_image(storage.path(), "library/ubuntu", "22.04", &ubuntu.manifest);
+    common::seed_image(storage.path(), "library/debian", "11");
+
+    common::amethyst(storage.path())
+        .args(["rmi", &ubuntu.manifest])
+        .assert()
+        .success()
+        .stdout(format!(
+            "untagged library/ubuntu:22.04\nuntagged library/ubuntu:latest\nremoved {} from the image database\nrun `amethyst prune` to delete the blobs no longer used\n",
+            ubuntu.manifest
+        ));
+
+    let index = fs::read_to_string(storage.path().join("tags.json")).unwrap();
+    assert!(!index.contains("library/ubuntu"));
+    assert!(index.contains("library/debian"));
+}
+
+#[test]
+fn cannot_remove_images_with_unknown_reference() {
+    let storage = tempfile::tempdir().unwrap();
+    common::seed_image(storage.path(), "library/ubuntu", "latest");
+
+    common::amethyst(storage.path())
+        .args(["rmi", "ubuntu", "fedora"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("no such image: fedora"));
+
+    let index = fs::read_to_string(storage.path().join("tags.json")).unwrap();
+    assert!(index.contains("library/ubuntu"));
+}
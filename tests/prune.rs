@@ -0,0 +1,183 @@
+use std::fs;
+use std::path;
+
+const STORAGE_ENVIRONMENT_VARIABLE: &str = "AMETHYST_STORAGE";
+
+fn blob_path(storage: &path::Path, digest: &str) -> path::PathBuf {
+    let (algorithm, encoded) = digest.split_once(':').unwrap();
+    storage.join("blobs").join(algorithm).join(encoded)
+}
+
+fn write_blob(storage: &path::Path, digest: &str, content: &str) {
+    let path = blob_path(storage, digest);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+fn write_image(storage: &path::Path, manifest_digest: &str, config: &str, layers: &[&str]) {
+    let layers = layers
+        .iter()
+        .map(|digest| {
+            format!(
+                r#"{{"mediaType": "application/vnd.docker.image.rootfs.diff.tar.gzip", "size": 5, "digest": "{}"}}"#,
+                digest
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    let manifest = format!(
+        r#"{{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+            "config": {{"mediaType": "application/vnd.docker.container.image.v1+json", "size": 6, "digest": "{}"}},
+            "layers": [{}]
+        }}"#,
+        config, layers
+    );
+    write_blob(storage, manifest_digest, &manifest);
+    write_blob(storage, config, "config");
+}
+
+fn write_index(storage: &path::Path, tags: &[(&str, &str, &str)]) {
+    let tags = tags
+        .iter()
+        .map(|(repository, tag, digest)| {
+            format!(
+                r#""{}": {{"{}": {{"digest": "{}", "pulled_at": "2022-01-01T00:00:00Z"}}}}"#,
+                repository, tag, digest
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    fs::write(
+        storage.join("tags.json"),
+        format!(r#"{{"repositories": {{{}}}}}"#, tags),
+    )
+    .unwrap();
+}
+
+fn blob_exists(storage: &path::Path, digest: &str) -> bool {
+    blob_path(storage, digest).exists()
+}
+
+fn prune(storage: &path::Path, args: &[&str]) -> assert_cmd::assert::Assert {
+    let mut program = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).expect("program");
+    program
+        .env(STORAGE_ENVIRONMENT_VARIABLE, storage)
+        .arg("prune")
+        .args(args)
+        .assert()
+}
+
+fn seed(storage: &path::Path) {
+    write_image(
+        storage,
+        "sha256:manifest",
+        "sha256:config",
+        &["sha256:layer"],
+    );
+    write_blob(storage, "sha256:layer", "layer");
+    write_blob(storage, "sha256:orphan", "orphan");
+    write_index(storage, &[("library/ubuntu", "latest", "sha256:manifest")]);
+}
+
+#[test]
+fn prune_empty_storage() {
+    let storage = tempfile::tempdir().unwrap();
+
+    prune(storage.path(), &[]).success();
+}
+
+#[test]
+fn prune_unreachable_blobs() {
+    let storage = tempfile::tempdir().unwrap();
+    seed(storage.path());
+
+    prune(storage.path(), &[])
+        .success()
+        .stdout(predicates::str::contains("removed sha256:orphan"));
+
+    assert!(!blob_exists(storage.path(), "sha256:orphan"));
+    assert!(blob_exists(storage.path(), "sha256:manifest"));
+    assert!(blob_exists(storage.path(), "sha256:config"));
+    assert!(blob_exists(storage.path(), "sha256:layer"));
+}
+
+#[test]
+fn prune_dangling_manifest() {
+    let storage = tempfile::tempdir().unwrap();
+    seed(storage.path());
+    write_image(
+        storage.path(),
+        "sha256:old-manifest",
+        "sha256:old-config",
+        &[],
+    );
+
+    prune(storage.path(), &[]).success();
+
+    assert!(!blob_exists(storage.path(), "sha256:old_manifest"));
+    assert!(!blob_exists(storage.path(), "sha256:old_config"));
+    assert!(blob_exists(storage.path(), "sha256:manifest"));
+}
+
+#[test]
+fn cannot_prune_with_unreadable_manifest() {
+    let storage = tempfile::tempdir().unwrap();
+    seed(storage.path());
+    write_blob(storage.path(), "sha256:manifest", "corrupt");
+
+    prune(storage.path(), &[])
+        .failure()
+        .stderr(predicates::str::contains(
+            "cannot read manifest sha256:manifest of library/ubuntu:latest",
+        ));
+
+    assert!(blob_exists(storage.path(), "sha256:config"));
+    assert!(blob_exists(storage.path(), "sha256:layer"));
+    assert!(blob_exists(storage.path(), "sha256:orphan"));
+}
+
+#[test]
+fn dry_run_keeps_blobs() {
+    let storage = tempfile::tempdir().unwrap();
+    seed(storage.path());
+
+    prune(storage.path(), &["--dry-run"])
+        .success()
+        .stdout(predicates::str::contains("would remove sha256:orphan"));
+
+    assert!(blob_exists(storage.path(), "sha256:orphan"));
+}
+
+#[test]
+fn older_than_keeps_recent_blobs() {
+    let storage = tempfile::tempdir().unwrap();
+    seed(storage.path());
+
+    prune(storage.path(), &["--older-than", "1d"]).success();
+
+    assert!(blob_exists(storage.path(), "sha256:orphan"));
+}
+
+#[test]
+fn max_size_evicts_images() {
+    let storage = tempfile::tempdir().unwrap();
+    seed(storage.path());
+
+    prune(storage.path(), &["--max-size", "0"])
+        .success()
+        .stdout(predicates::str::contains("evicted library/ubuntu:latest"));
+
+    assert!(!blob_exists(storage.path(), "sha256:manifest"));
+    assert!(!blob_exists(storage.path(), "sha256:layer"));
+    let index = fs::read_to_string(storage.path().join("tags.json")).unwrap();
+    assert!(!index.contains("library/ubuntu"));
+}
+
+#[test]
+fn cannot_prune_with_invalid_size() {
+    let storage = tempfile::tempdir().unwrap();
+
+    prune(storage.path(), &["--max-size", "ten"]).failure();
+}
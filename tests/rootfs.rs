@@ -0,0 +1,45 @@
+mod common;
+
+use std::fs;
+use std::path;
+
+#[test]
+fn export_built_image() {
+    let config_directory = path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("build")
+        .join("multi-image");
+    let storage = tempfile::tempdir().unwrap();
+    let rootfs = tempfile::tempdir().unwrap();
+    common::amethyst(storage.path())
+        .args(["build", config_directory.to_str().unwrap()])
+        .assert()
+        .success();
+
+    common::amethyst(storage.path())
+        .args(["rootfs", "image2", rootfs.path().to_str().unwrap()])
+        .assert()
+        .success();
+
+    assert!(rootfs.path().join("destination-file").is_file());
+    assert!(rootfs
+        .path()
+        .join("opt")
+        .join("amethyst")
+        .join("destination-file")
+        .is_file());
+}
+
+#[test]
+fn cannot_export_into_non_empty_directory() {
+    let storage = tempfile::tempdir().unwrap();
+    let rootfs = tempfile::tempdir().unwrap();
+    common::seed_image(storage.path(), "library/ubuntu", "latest");
+    fs::write(rootfs.path().join("file"), "content").unwrap();
+
+    common::amethyst(storage.path())
+        .args(["rootfs", "ubuntu", rootfs.path().to_str().unwrap()])
+        .assert()
+        .failure();
+}